@@ -0,0 +1,179 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::types::*;
+
+/// Headers larger than this mean the archive is damaged.
+const MAX_HEADER_LEN: u32 = 256 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsarEntry {
+    /// Path inside the archive, with `/` as separator.
+    pub name: String,
+    pub size: u64,
+    offset: u64,
+}
+
+impl AsarEntry {
+    pub fn components(&self) -> impl Iterator<Item = &str> {
+        self.name.split('/').filter(|c| !c.is_empty())
+    }
+}
+
+/// Reader for Electron `app.asar` bundles.
+///
+/// The file starts with two pickles: the first holds the size of the second,
+/// which holds a JSON tree of the files with their offset past the header.
+/// Files kept outside in `app.asar.unpacked` and symlinks are not listed.
+pub struct AsarArchive<R> {
+    reader: R,
+    data_start: u64,
+    entries: Vec<AsarEntry>,
+}
+
+impl<R: Read + Seek> AsarArchive<R> {
+    pub fn open(mut reader: R) -> io::Result<Self> {
+        let mut prefix = [0u8; 16];
+        reader
+            .read_exact(&mut prefix)
+            .map_err(|_| Error::InvalidArchive)?;
+        let field = |at: usize| u32::from_le_bytes(prefix[at..at + 4].try_into().unwrap());
+
+        let (size_pickle_len, header_len, json_len) = (field(0), field(4), field(12));
+        if size_pickle_len != 4
+            || header_len > MAX_HEADER_LEN
+            || json_len > header_len.saturating_sub(8)
+        {
+            return Err(Error::InvalidArchive.into());
+        }
+
+        let mut json = vec![0u8; json_len as usize];
+        reader.read_exact(&mut json)?;
+        let header: serde_json::Value =
+            serde_json::from_slice(&json).map_err(|_| Error::InvalidArchive)?;
+
+        let mut entries = Vec::new();
+        collect_entries(&header, "", &mut entries)?;
+
+        Ok(Self {
+            reader,
+            data_start: 8 + header_len as u64,
+            entries,
+        })
+    }
+
+    pub fn entries(&self) -> &[AsarEntry] {
+        &self.entries
+    }
+
+    /// Looks an entry up by path, accepting either separator.
+    pub fn find(&self, name: &str) -> Option<&AsarEntry> {
+        let wanted: Vec<&str> = name.split(['\\', '/']).filter(|c| !c.is_empty()).collect();
+        self.entries
+            .iter()
+            .find(|entry| entry.components().eq(wanted.iter().copied()))
+    }
+
+    pub fn read(&mut self, entry: &AsarEntry) -> io::Result<Vec<u8>> {
+        self.reader
+            .seek(SeekFrom::Start(self.data_start + entry.offset))?;
+        let mut data = Vec::new();
+        (&mut self.reader).take(entry.size).read_to_end(&mut data)?;
+        if data.len() as u64 != entry.size {
+            return Err(Error::InvalidArchive.into());
+        }
+        Ok(data)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+fn collect_entries(
+    node: &serde_json::Value,
+    prefix: &str,
+    out: &mut Vec<AsarEntry>,
+) -> io::Result<()> {
+    let Some(files) = node.get("files").and_then(|f| f.as_object()) else {
+        return Ok(());
+    };
+
+    for (name, child) in files {
+        let path = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+
+        if child.get("files").is_some() {
+            collect_entries(child, &path, out)?;
+            continue;
+        }
+        if child.get("link").is_some()
+            || child.get("unpacked").and_then(|u| u.as_bool()) == Some(true)
+        {
+            continue;
+        }
+
+        // Offsets are strings since they may not fit in a JS number.
+        let offset = child
+            .get("offset")
+            .and_then(|o| o.as_str())
+            .and_then(|o| o.parse().ok())
+            .ok_or(Error::InvalidArchive)?;
+        let size = child
+            .get("size")
+            .and_then(|s| s.as_u64())
+            .ok_or(Error::InvalidArchive)?;
+        out.push(AsarEntry {
+            name: path,
+            size,
+            offset,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn asar() -> Vec<u8> {
+        let json = r#"{"files":{"www":{"files":{
+            "index.html":{"size":5,"offset":"0"},
+            "data":{"files":{"System.json":{"size":2,"offset":"5"}}},
+            "big.ogg":{"size":9,"unpacked":true}
+        }}}}"#;
+        // Pickles are padded to 4 bytes.
+        let padded = json.len().div_ceil(4) * 4;
+        let header_len = (8 + padded) as u32;
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&4u32.to_le_bytes());
+        out.extend_from_slice(&header_len.to_le_bytes());
+        out.extend_from_slice(&(header_len - 4).to_le_bytes());
+        out.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        out.extend_from_slice(json.as_bytes());
+        out.resize(8 + header_len as usize, 0);
+        out.extend_from_slice(b"hello{}");
+        out
+    }
+
+    #[test]
+    fn test_read_asar() {
+        let mut archive = AsarArchive::open(Cursor::new(asar())).unwrap();
+        assert_eq!(archive.entries().len(), 2);
+
+        let entry = archive.find("www/index.html").unwrap().clone();
+        assert_eq!(archive.read(&entry).unwrap(), b"hello");
+        let entry = archive.find("www\\data\\System.json").unwrap().clone();
+        assert_eq!(archive.read(&entry).unwrap(), b"{}");
+        assert!(archive.find("www/big.ogg").is_none());
+    }
+
+    #[test]
+    fn test_rejects_non_asar() {
+        assert!(AsarArchive::open(Cursor::new(b"PK\x03\x04 and more bytes".to_vec())).is_err());
+    }
+}
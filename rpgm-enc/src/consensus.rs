@@ -0,0 +1,205 @@
+use crate::decrypter::Decrypter;
+use crate::header::HeaderParams;
+use crate::types::*;
+
+/// How many bytes of each file are kept to check candidate keys against,
+/// past the fake header.
+const SAMPLE_LEN: usize = 64;
+
+struct Sample {
+    name: String,
+    file_type: FileExtension,
+    head: Vec<u8>,
+}
+
+/// A key proposed by at least one file, scored against all of them.
+#[derive(Debug, Clone)]
+pub struct KeyCandidate {
+    pub key: Key,
+    /// Number of files the key was derived from.
+    pub votes: usize,
+    /// Share of the files whose header decrypts correctly, from 0.0 to 1.0.
+    pub confidence: f32,
+    /// Files whose header does not decrypt with this key.
+    pub failing_files: Vec<String>,
+}
+
+/// Derives a candidate key from every encrypted file it is given and ranks
+/// the candidates by how many of the files they actually decrypt.
+pub struct KeyConsensus {
+    keyless: Decrypter,
+    samples: Vec<Sample>,
+    candidates: Vec<(Key, usize)>,
+}
+
+impl Default for KeyConsensus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyConsensus {
+    pub fn new() -> Self {
+        Self::with_header_params(&HeaderParams::default())
+    }
+
+    /// For files with a non-standard fake header.
+    pub fn with_header_params(params: &HeaderParams) -> Self {
+        let mut keyless = Decrypter::new(None);
+        params.apply_to(&mut keyless);
+        Self {
+            keyless,
+            samples: Vec::new(),
+            candidates: Vec::new(),
+        }
+    }
+
+    /// Adds an encrypted file. For OGG `data` should hold at least the first
+    /// page, for PNG and M4A the first few dozen bytes are enough.
+    pub fn add(&mut self, name: impl Into<String>, data: &[u8], file_type: FileExtension) {
+        if !file_type.is_encrypted() || data.is_empty() {
+            return;
+        }
+
+        if let Some(key) = self.keyless.detect_key_for(data, file_type) {
+            match self.candidates.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, votes)) => *votes += 1,
+                None => self.candidates.push((key, 1)),
+            }
+        }
+
+        self.samples.push(Sample {
+            name: name.into(),
+            file_type,
+            head: data[..data.len().min(self.keyless.get_header_len() + SAMPLE_LEN)].to_vec(),
+        });
+    }
+
+    /// Adds a key from outside the files, e.g. `System.json`, so it is
+    /// ranked with the others. It gets no vote of its own.
+    pub fn propose(&mut self, key: Key) {
+        if !self.candidates.iter().any(|(k, _)| *k == key) {
+            self.candidates.push((key, 0));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// All candidates, most likely first.
+    pub fn rank(&self) -> Vec<KeyCandidate> {
+        let mut ranked: Vec<KeyCandidate> = self
+            .candidates
+            .iter()
+            .map(|(key, votes)| {
+                let mut decrypter = self.keyless.clone();
+                decrypter.key = Some(key.clone());
+                let failing_files: Vec<String> = self
+                    .samples
+                    .iter()
+                    .filter(|sample| !key_fits(&decrypter, sample))
+                    .map(|sample| sample.name.clone())
+                    .collect();
+                let fitting = self.samples.len() - failing_files.len();
+
+                KeyCandidate {
+                    key: key.clone(),
+                    votes: *votes,
+                    confidence: fitting as f32 / self.samples.len().max(1) as f32,
+                    failing_files,
+                }
+            })
+            .collect();
+
+        ranked.sort_by(|a, b| {
+            b.confidence
+                .total_cmp(&a.confidence)
+                .then(b.votes.cmp(&a.votes))
+        });
+        ranked
+    }
+
+    pub fn best(&self) -> Option<KeyCandidate> {
+        self.rank().into_iter().next()
+    }
+}
+
+/// Whether the sample's header decrypts to the fixed bytes its format starts with.
+fn key_fits(decrypter: &Decrypter, sample: &Sample) -> bool {
+    let Ok(content) = decrypter.decrypt(&sample.head, sample.file_type) else {
+        return false;
+    };
+
+    match sample.file_type {
+        FileExtension::PNG | FileExtension::RPGMVP | FileExtension::PNG_ => {
+            content.starts_with(PNG_HEADER_BYTES)
+        }
+        FileExtension::OGG | FileExtension::RPGMVO | FileExtension::OGG_ => {
+            // Capture pattern, version, BOS flag and a zero granule position.
+            content.len() >= 14
+                && content.starts_with(b"OggS\x00\x02")
+                && content[6..14].iter().all(|&b| b == 0)
+        }
+        FileExtension::M4A | FileExtension::RPGMVM | FileExtension::M4A_ => {
+            // The minor version is a guess, so only size, type and brand are compared.
+            m4a_ftyp_header(&content).is_some_and(|header| content[..12] == header[..12])
+        }
+        FileExtension::XYZ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(key: &str) -> Vec<u8> {
+        let mut data = PNG_HEADER_BYTES.to_vec();
+        data.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        data.extend_from_slice(&[0u8; 32]);
+        Decrypter::new(Key::new(key))
+            .encrypt(&data, FileExtension::PNG_)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_consensus_ranks_majority_key() {
+        let key_a = "0123456789abcdef0123456789abcdef";
+        let key_b = "fedcba9876543210fedcba9876543210";
+
+        let mut consensus = KeyConsensus::new();
+        consensus.add("a1.png_", &png(key_a), FileExtension::PNG_);
+        consensus.add("a2.png_", &png(key_a), FileExtension::PNG_);
+        consensus.add("b1.png_", &png(key_b), FileExtension::PNG_);
+        consensus.add("plain.png", &[1, 2, 3], FileExtension::PNG);
+
+        let ranked = consensus.rank();
+        assert_eq!(consensus.len(), 3);
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].key.as_str(), key_a);
+        assert_eq!(ranked[0].votes, 2);
+        assert!((ranked[0].confidence - 2.0 / 3.0).abs() < f32::EPSILON);
+        assert_eq!(ranked[0].failing_files, vec!["b1.png_".to_string()]);
+        assert_eq!(ranked[1].key.as_str(), key_b);
+    }
+
+    #[test]
+    fn test_consensus_scores_proposed_key() {
+        let key = "0123456789abcdef0123456789abcdef";
+
+        let mut consensus = KeyConsensus::new();
+        consensus.propose(Key::new(key).unwrap());
+        consensus.propose(Key::new("fedcba9876543210fedcba9876543210").unwrap());
+        consensus.add("a.png_", &png(key), FileExtension::PNG_);
+
+        let ranked = consensus.rank();
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].key.as_str(), key);
+        assert_eq!(ranked[0].votes, 1);
+        assert_eq!(ranked[1].confidence, 0.0);
+    }
+}
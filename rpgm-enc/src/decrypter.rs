@@ -1,3 +1,4 @@
+use crate::header::HeaderParams;
 use crate::types::*;
 
 #[derive(Default, serde::Deserialize, serde::Serialize, Clone)]
@@ -12,10 +13,10 @@ pub struct Decrypter {
 }
 
 impl Decrypter {
-    const DEFAULT_HEADER_LEN: usize = 16;
-    const DEFAULT_SIGNATURE: &'static str = "5250474d56000000";
-    const DEFAULT_VERSION: &'static str = "000301";
-    const DEFAULT_REMAIN: &'static str = "0000000000";
+    pub const DEFAULT_HEADER_LEN: usize = 16;
+    pub const DEFAULT_SIGNATURE: &'static str = "5250474d56000000";
+    pub const DEFAULT_VERSION: &'static str = "000301";
+    pub const DEFAULT_REMAIN: &'static str = "0000000000";
 
     pub fn new(key: Option<Key>) -> Self {
         let mut decrypter = Decrypter {
@@ -62,46 +63,65 @@ impl Decrypter {
         file_header[..self.get_header_len()] == fake_header[..self.get_header_len()]
     }
 
-    pub fn decrypt(&self, data: &[u8], file_type: FileExtension) -> Result<Vec<u8>> {
+    pub fn decrypt(&self, data: &[u8], _file_type: FileExtension) -> Result<Vec<u8>> {
         if data.is_empty() {
             return Err(Error::EmptyFile);
         }
 
-        if matches!(
-            file_type,
-            FileExtension::M4A | FileExtension::M4A_ | FileExtension::RPGMVM
-        ) {
-            return Ok(data.to_vec());
-        }
+        let header_len = self.check_fake_header(data)?;
+        let mut content = data[header_len..].to_vec();
+        self.xor_bytes(&mut content);
+        Ok(content)
+    }
 
-        let header_len = self.get_header_len();
-        if data.len() < header_len {
-            return Err(Error::InvalidHeader);
-        }
+    /// Like `decrypt`, but fails with `Error::WrongKey` unless the result
+    /// starts like a real file of `file_type`.
+    pub fn decrypt_verified(&self, data: &[u8], file_type: FileExtension) -> Result<Vec<u8>> {
+        let content = self.decrypt(data, file_type)?;
+        Self::verify_content(&content, file_type)?;
+        Ok(content)
+    }
 
-        if !self.ignore_fake_header {
-            let header = &data[0..header_len];
-            if !self.verify_fake_header(header) {
-                return Err(Error::InvalidHeader);
+    /// Checks decrypted content: PNG signature and IHDR chunk header, OGG
+    /// capture pattern and first page CRC, or a plausible `ftyp` box.
+    pub fn verify_content(content: &[u8], file_type: FileExtension) -> Result<()> {
+        let valid = match file_type {
+            FileExtension::PNG | FileExtension::RPGMVP | FileExtension::PNG_ => {
+                content.starts_with(PNG_HEADER_BYTES)
             }
-        }
+            FileExtension::OGG | FileExtension::RPGMVO | FileExtension::OGG_ => {
+                content.starts_with(b"OggS") && ogg_first_page_crc_matches(content)
+            }
+            FileExtension::M4A | FileExtension::RPGMVM | FileExtension::M4A_ => {
+                content.len() >= 8
+                    && &content[4..8] == b"ftyp"
+                    && u32::from_be_bytes([content[0], content[1], content[2], content[3]]) >= 8
+            }
+            FileExtension::XYZ => content.starts_with(XYZ_HEADER_BYTES),
+        };
 
-        let mut content = data[header_len..].to_vec();
-        self.xor_bytes(&mut content);
-        Ok(content)
+        if valid { Ok(()) } else { Err(Error::WrongKey) }
     }
 
-    pub fn encrypt(&self, data: &[u8], file_type: FileExtension) -> Result<Vec<u8>> {
+    /// Decrypts `data` without allocating and returns the content behind the fake header.
+    pub fn decrypt_in_place<'a>(
+        &self,
+        data: &'a mut [u8],
+        _file_type: FileExtension,
+    ) -> Result<&'a mut [u8]> {
         if data.is_empty() {
             return Err(Error::EmptyFile);
         }
 
-        // M4A does not get encrypted — just return the data
-        if matches!(
-            file_type,
-            FileExtension::M4A | FileExtension::M4A_ | FileExtension::RPGMVM
-        ) {
-            return Ok(data.to_vec());
+        let header_len = self.check_fake_header(data)?;
+        let content = &mut data[header_len..];
+        self.xor_bytes(content);
+        Ok(content)
+    }
+
+    pub fn encrypt(&self, data: &[u8], _file_type: FileExtension) -> Result<Vec<u8>> {
+        if data.is_empty() {
+            return Err(Error::EmptyFile);
         }
 
         let mut content = data.to_vec();
@@ -118,16 +138,61 @@ impl Decrypter {
         Ok(result)
     }
 
+    /// Encrypts `data` without allocating. The fake header is not part of the
+    /// output, write `build_fake_header()` in front of it.
+    pub fn encrypt_in_place(&self, data: &mut [u8], _file_type: FileExtension) -> Result<()> {
+        if data.is_empty() {
+            return Err(Error::EmptyFile);
+        }
+
+        self.xor_bytes(data);
+        Ok(())
+    }
+
+    /// Checks the fake header in front of `data` and returns its length.
+    fn check_fake_header(&self, data: &[u8]) -> Result<usize> {
+        let header_len = self.get_header_len();
+        if data.len() < header_len {
+            return Err(Error::InvalidHeader);
+        }
+
+        if !self.ignore_fake_header && !self.verify_fake_header(&data[0..header_len]) {
+            return Err(Error::InvalidHeader);
+        }
+        Ok(header_len)
+    }
+
+    pub(crate) fn ignores_fake_header(&self) -> bool {
+        self.ignore_fake_header
+    }
+
     fn xor_bytes(&self, data: &mut [u8]) {
+        self.xor_at(0, data);
+    }
+
+    /// XORs `data`, which starts at `offset` within the file content.
+    /// Only the first `header_len` bytes of the content are encrypted.
+    pub(crate) fn xor_at(&self, offset: usize, data: &mut [u8]) {
         if let Some(key) = &self.key {
             let key_bytes = key.as_bytes();
-            for i in 0..self.get_header_len().min(data.len()).min(key_bytes.len()) {
-                data[i] ^= key_bytes[i];
+            let end = self.get_header_len().min(key_bytes.len());
+            for i in offset..end.min(offset + data.len()) {
+                data[i - offset] ^= key_bytes[i];
             }
         }
     }
 
     pub fn restore_header(&self, data: &[u8], file_type: FileExtension) -> Result<Vec<u8>> {
+        let mut restored = data.to_vec();
+        self.restore_header_in_place(&mut restored, file_type)?;
+        Ok(restored)
+    }
+
+    pub fn restore_header_in_place(
+        &self,
+        data: &mut Vec<u8>,
+        file_type: FileExtension,
+    ) -> Result<()> {
         if data.is_empty() {
             return Err(Error::EmptyFile);
         }
@@ -146,34 +211,60 @@ impl Decrypter {
         };
 
         if has_correct_header {
-            return Ok(data.to_vec());
+            return Ok(());
         }
 
+        let fake_header_len = self.get_header_len();
+        let has_fake_header =
+            data.len() >= fake_header_len && self.verify_fake_header(&data[0..fake_header_len]);
+        let content_start = if has_fake_header { fake_header_len } else { 0 };
+
         match file_type {
             FileExtension::PNG | FileExtension::RPGMVP | FileExtension::PNG_ => {
-                let fake_header_len = self.get_header_len();
                 let header = &PNG_HEADER_BYTES[..fake_header_len.min(PNG_HEADER_BYTES.len())];
-
-                let has_fake_header = data.len() >= fake_header_len
-                    && self.verify_fake_header(&data[0..fake_header_len]);
-                let content = if has_fake_header {
-                    if data.len() < fake_header_len {
-                        return Err(Error::InvalidHeader);
-                    }
-                    &data[fake_header_len..]
-                } else {
-                    data
-                };
-
-                let mut result = Vec::with_capacity(content.len() + header.len());
-                result.extend_from_slice(header);
-                result.extend_from_slice(content);
-                Ok(result)
+                data.splice(..content_start, header.iter().copied());
+                Ok(())
+            }
+            FileExtension::M4A | FileExtension::RPGMVM | FileExtension::M4A_ => {
+                let header = m4a_ftyp_header(&data[content_start..]).ok_or(Error::InvalidHeader)?;
+                data.splice(..content_start + header.len(), header);
+                Ok(())
             }
             _ => Err(Error::InvalidHeader),
         }
     }
 
+    /// Recovers an encrypted file without the key. Only the first 16 bytes of
+    /// the content are encrypted and for these formats they can be rebuilt:
+    /// the PNG signature and IHDR header, the first OGG page header or the
+    /// m4a `ftyp` box.
+    pub fn recover_without_key(&self, data: &[u8], file_type: FileExtension) -> Result<Vec<u8>> {
+        if data.is_empty() {
+            return Err(Error::EmptyFile);
+        }
+
+        let header_len = self.check_fake_header(data)?;
+        let mut content = data[header_len..].to_vec();
+        if content.len() < PNG_HEADER_BYTES.len() {
+            return Err(Error::InvalidHeader);
+        }
+
+        match file_type {
+            FileExtension::PNG | FileExtension::RPGMVP | FileExtension::PNG_ => {
+                content[..PNG_HEADER_BYTES.len()].copy_from_slice(PNG_HEADER_BYTES);
+            }
+            FileExtension::OGG | FileExtension::RPGMVO | FileExtension::OGG_ => {
+                rebuild_ogg_first_page(&mut content)?;
+            }
+            FileExtension::M4A | FileExtension::RPGMVM | FileExtension::M4A_ => {
+                let header = m4a_ftyp_header(&content).ok_or(Error::InvalidHeader)?;
+                content[..header.len()].copy_from_slice(&header);
+            }
+            FileExtension::XYZ => return Err(Error::InvalidHeader),
+        }
+        Ok(content)
+    }
+
     pub fn get_header_len(&self) -> usize {
         self.header_len.unwrap_or(Self::DEFAULT_HEADER_LEN)
     }
@@ -198,7 +289,30 @@ impl Decrypter {
             FileExtension::OGG | FileExtension::RPGMVO | FileExtension::OGG_ => {
                 Key::from_ogg_header(Self::DEFAULT_HEADER_LEN, data)
             }
-            _ => None,
+            FileExtension::M4A | FileExtension::RPGMVM | FileExtension::M4A_ => {
+                Key::from_m4a_header(Self::DEFAULT_HEADER_LEN, data)
+            }
+            FileExtension::XYZ => None,
+        }
+    }
+
+    /// Like `detect_key`, for files carrying this decrypter's fake header.
+    pub fn detect_key_for(&self, data: &[u8], file_type: FileExtension) -> Option<Key> {
+        let header_len = self.get_header_len();
+        if data.len() < header_len {
+            return None;
+        }
+        let mut normalized = vec![0u8; Self::DEFAULT_HEADER_LEN];
+        normalized.extend_from_slice(&data[header_len..]);
+        Self::detect_key(&normalized, file_type)
+    }
+
+    pub fn header_params(&self) -> HeaderParams {
+        HeaderParams {
+            len: self.get_header_len(),
+            signature: self.get_signature().to_string(),
+            version: self.get_version().to_string(),
+            remain: self.get_remain().to_string(),
         }
     }
 
@@ -233,4 +347,56 @@ mod tests {
         assert_eq!(&decrypted, test_data);
         Ok(())
     }
+
+    #[test]
+    fn test_decrypt_verified_wrong_key() -> Result<()> {
+        let mut png = PNG_HEADER_BYTES.to_vec();
+        png.extend_from_slice(&[0u8; 32]);
+        let right = Decrypter::new(Key::new("0123456789abcdef0123456789abcdef"));
+        let wrong = Decrypter::new(Key::new("fedcba9876543210fedcba9876543210"));
+
+        let encrypted = right.encrypt(&png, FileExtension::RPGMVP)?;
+        assert_eq!(
+            right.decrypt_verified(&encrypted, FileExtension::RPGMVP)?,
+            png
+        );
+        assert!(matches!(
+            wrong.decrypt_verified(&encrypted, FileExtension::RPGMVP),
+            Err(Error::WrongKey)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_png_recovered_without_key() -> Result<()> {
+        let mut png = PNG_HEADER_BYTES.to_vec();
+        png.extend_from_slice(b"rest of the image");
+        let encrypted = Decrypter::new(Key::new("0123456789abcdef0123456789abcdef"))
+            .encrypt(&png, FileExtension::PNG_)?;
+
+        let recovered =
+            Decrypter::new(None).recover_without_key(&encrypted, FileExtension::PNG_)?;
+        assert_eq!(recovered, png);
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_place_round_trip() -> Result<()> {
+        let key = Key::new("0123456789abcdef0123456789abcdef").unwrap();
+        let decrypter = Decrypter::new(Some(key));
+        let test_data = b"In-place data that is longer than the header";
+
+        let mut encrypted = decrypter.build_fake_header().to_vec();
+        let mut content = test_data.to_vec();
+        decrypter.encrypt_in_place(&mut content, FileExtension::PNG_)?;
+        encrypted.extend_from_slice(&content);
+        assert_eq!(
+            encrypted,
+            decrypter.encrypt(test_data, FileExtension::PNG_)?
+        );
+
+        let decrypted = decrypter.decrypt_in_place(&mut encrypted, FileExtension::PNG_)?;
+        assert_eq!(decrypted, test_data);
+        Ok(())
+    }
 }
@@ -0,0 +1,342 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::types::*;
+
+const MAGIC: &[u8; 4] = b"EVB\0";
+/// Magic plus the rest of the package header, which we do not need.
+const PACKAGE_HEADER_LEN: u64 = 64;
+
+const NODE_FILE: u8 = 2;
+const NODE_FOLDER: u8 = 3;
+
+/// Reserved bytes after the type of a folder node.
+const FOLDER_TAIL_LEN: u64 = 25;
+
+/// Folder standing for the directory of the executable.
+const DEFAULT_FOLDER: &str = "%DEFAULT FOLDER%";
+
+/// Names longer than this, in UTF-16 units, mean the package is damaged.
+const MAX_NAME_LEN: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvbEntry {
+    /// Path inside the package, with `\` as separator.
+    pub name: String,
+    pub size: u64,
+    /// Compressed by the packer. Such entries cannot be read.
+    pub compressed: bool,
+    offset: u64,
+}
+
+impl EvbEntry {
+    pub fn components(&self) -> impl Iterator<Item = &str> {
+        self.name.split(['\\', '/']).filter(|c| !c.is_empty())
+    }
+}
+
+/// Reader for the virtual file system Enigma Virtual Box embeds in an
+/// executable.
+///
+/// The package sits at the start of a PE section and begins with `EVB\0`.
+/// After its header comes the file tree, depth first: each node has its size,
+/// the number of children, a UTF-16 name and a type, followed by the sizes
+/// for files. The file contents follow the tree in the same order. Files
+/// compressed by the packer are listed, but reading them fails.
+pub struct EvbArchive<R> {
+    reader: R,
+    entries: Vec<EvbEntry>,
+}
+
+impl<R: Read + Seek> EvbArchive<R> {
+    pub fn open(mut reader: R) -> io::Result<Self> {
+        let start = find_package(&mut reader)?;
+        reader.seek(SeekFrom::Start(start + PACKAGE_HEADER_LEN))?;
+
+        let (entries, data_start) = read_tree(&mut reader)?;
+        let entries = entries
+            .into_iter()
+            .map(|(name, node)| {
+                let name = name
+                    .strip_prefix(DEFAULT_FOLDER)
+                    .map(|rest| rest.trim_start_matches('\\').to_string())
+                    .unwrap_or(name);
+                EvbEntry {
+                    name,
+                    size: node.size as u64,
+                    compressed: node.stored_size != node.size,
+                    offset: data_start + node.offset,
+                }
+            })
+            .collect();
+
+        Ok(Self { reader, entries })
+    }
+
+    pub fn entries(&self) -> &[EvbEntry] {
+        &self.entries
+    }
+
+    /// Looks an entry up by path, accepting either separator.
+    pub fn find(&self, name: &str) -> Option<&EvbEntry> {
+        let wanted: Vec<&str> = name.split(['\\', '/']).filter(|c| !c.is_empty()).collect();
+        self.entries
+            .iter()
+            .find(|entry| entry.components().eq(wanted.iter().copied()))
+    }
+
+    pub fn read(&mut self, entry: &EvbEntry) -> io::Result<Vec<u8>> {
+        if entry.compressed {
+            return Err(Error::CompressedEntry.into());
+        }
+        self.reader.seek(SeekFrom::Start(entry.offset))?;
+        let mut data = Vec::new();
+        (&mut self.reader).take(entry.size).read_to_end(&mut data)?;
+        if data.len() as u64 != entry.size {
+            return Err(Error::InvalidArchive.into());
+        }
+        Ok(data)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+struct FileNode {
+    size: u32,
+    stored_size: u32,
+    /// Relative to the end of the tree.
+    offset: u64,
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn skip(reader: &mut impl Read, len: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.take(len), &mut io::sink())?;
+    if skipped != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+/// Offset of the package, found through the section table of the PE file.
+fn find_package<R: Read + Seek>(reader: &mut R) -> io::Result<u64> {
+    let mut dos_header = [0u8; 64];
+    reader
+        .read_exact(&mut dos_header)
+        .map_err(|_| Error::InvalidArchive)?;
+    if !dos_header.starts_with(b"MZ") {
+        return Err(Error::InvalidArchive.into());
+    }
+    let pe_offset = u32::from_le_bytes(dos_header[60..64].try_into().unwrap()) as u64;
+
+    reader.seek(SeekFrom::Start(pe_offset))?;
+    let mut pe_header = [0u8; 24];
+    reader
+        .read_exact(&mut pe_header)
+        .map_err(|_| Error::InvalidArchive)?;
+    if !pe_header.starts_with(b"PE\0\0") {
+        return Err(Error::InvalidArchive.into());
+    }
+    let section_count = u16::from_le_bytes([pe_header[6], pe_header[7]]);
+    let optional_header_len = u16::from_le_bytes([pe_header[20], pe_header[21]]) as u64;
+
+    let sections_start = pe_offset + 24 + optional_header_len;
+    for i in 0..section_count as u64 {
+        reader.seek(SeekFrom::Start(sections_start + i * 40))?;
+        let mut section = [0u8; 40];
+        reader.read_exact(&mut section)?;
+        let raw_offset = u32::from_le_bytes(section[20..24].try_into().unwrap()) as u64;
+
+        reader.seek(SeekFrom::Start(raw_offset))?;
+        let mut magic = [0u8; 4];
+        if reader.read_exact(&mut magic).is_ok() && &magic == MAGIC {
+            return Ok(raw_offset);
+        }
+    }
+    Err(Error::InvalidArchive.into())
+}
+
+/// Reads a node up to its name, returning the number of children and the name.
+fn read_node_header(reader: &mut impl Read) -> io::Result<(u32, String)> {
+    let _size = read_u32(reader)?;
+    skip(reader, 8)?;
+    let children = read_u32(reader)?;
+
+    let mut units = Vec::new();
+    loop {
+        let mut unit = [0u8; 2];
+        reader.read_exact(&mut unit)?;
+        match u16::from_le_bytes(unit) {
+            0 => break,
+            _ if units.len() >= MAX_NAME_LEN => return Err(Error::InvalidArchive.into()),
+            unit => units.push(unit),
+        }
+    }
+    Ok((children, String::from_utf16_lossy(&units)))
+}
+
+/// Walks the tree, returning the files with their path and where the
+/// contents start.
+fn read_tree<R: Read + Seek>(reader: &mut R) -> io::Result<(Vec<(String, FileNode)>, u64)> {
+    // The root has no name or type.
+    let _size = read_u32(reader)?;
+    skip(reader, 8)?;
+    let root_children = read_u32(reader)?;
+
+    let mut files = Vec::new();
+    let mut offset = 0;
+    // Folders being read, with their path and how many children are left.
+    let mut stack = vec![(String::new(), root_children)];
+
+    while let Some((folder, remaining)) = stack.last_mut() {
+        if *remaining == 0 {
+            stack.pop();
+            continue;
+        }
+        *remaining -= 1;
+
+        let (children, name) = read_node_header(reader)?;
+        let path = if folder.is_empty() {
+            name
+        } else {
+            format!("{}\\{}", folder, name)
+        };
+
+        skip(reader, 2)?;
+        match read_u8(reader)? {
+            NODE_FOLDER => {
+                skip(reader, FOLDER_TAIL_LEN)?;
+                stack.push((path, children));
+            }
+            NODE_FILE => {
+                skip(reader, 2)?;
+                let size = read_u32(reader)?;
+                // Reserved bytes and the creation, access and write times.
+                skip(reader, 4 + 24 + 15)?;
+                let stored_size = read_u32(reader)?;
+                skip(reader, 4)?;
+
+                files.push((
+                    path,
+                    FileNode {
+                        size,
+                        stored_size,
+                        offset,
+                    },
+                ));
+                offset += stored_size as u64;
+            }
+            _ => return Err(Error::InvalidArchive.into()),
+        }
+    }
+
+    Ok((files, reader.stream_position()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn node(children: u32, name: &str, kind: u8) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&[0; 8]);
+        out.extend_from_slice(&children.to_le_bytes());
+        for unit in name.encode_utf16().chain([0]) {
+            out.extend_from_slice(&unit.to_le_bytes());
+        }
+        out.extend_from_slice(&[0; 2]);
+        out.push(kind);
+        out
+    }
+
+    fn folder(children: u32, name: &str) -> Vec<u8> {
+        let mut out = node(children, name, NODE_FOLDER);
+        out.extend_from_slice(&[0; FOLDER_TAIL_LEN as usize]);
+        out
+    }
+
+    fn file(name: &str, size: u32, stored_size: u32) -> Vec<u8> {
+        let mut out = node(0, name, NODE_FILE);
+        out.extend_from_slice(&[0; 2]);
+        out.extend_from_slice(&size.to_le_bytes());
+        out.extend_from_slice(&[0; 4 + 24 + 15]);
+        out.extend_from_slice(&stored_size.to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out
+    }
+
+    /// A PE file with a code section and the package in a second section.
+    fn exe() -> Vec<u8> {
+        let mut package = MAGIC.to_vec();
+        package.resize(PACKAGE_HEADER_LEN as usize, 0);
+        package.extend_from_slice(&0u32.to_le_bytes());
+        package.extend_from_slice(&[0; 8]);
+        package.extend_from_slice(&1u32.to_le_bytes());
+        package.extend(folder(2, DEFAULT_FOLDER));
+        package.extend(folder(2, "www"));
+        package.extend(file("index.html", 5, 5));
+        package.extend(file("packed.bin", 10, 3));
+        package.extend(file("Game.dat", 2, 2));
+        package.extend_from_slice(b"hello");
+        package.extend_from_slice(b"zzz");
+        package.extend_from_slice(b"ok");
+
+        let mut out = vec![0u8; 64];
+        out[..2].copy_from_slice(b"MZ");
+        out[60..64].copy_from_slice(&64u32.to_le_bytes());
+        out.extend_from_slice(b"PE\0\0");
+        let mut coff = [0u8; 20];
+        coff[2..4].copy_from_slice(&2u16.to_le_bytes());
+        out.extend_from_slice(&coff);
+
+        let code_offset = 64 + 24 + 2 * 40;
+        let package_offset = code_offset + 16;
+        for raw_offset in [code_offset, package_offset] {
+            let mut section = [0u8; 40];
+            section[20..24].copy_from_slice(&(raw_offset as u32).to_le_bytes());
+            out.extend_from_slice(&section);
+        }
+        out.extend_from_slice(&[0xCC; 16]);
+        out.extend(package);
+        out
+    }
+
+    #[test]
+    fn test_read_evb() {
+        let mut archive = EvbArchive::open(Cursor::new(exe())).unwrap();
+        let names: Vec<&str> = archive.entries().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["www\\index.html", "www\\packed.bin", "Game.dat"]);
+
+        let entry = archive.find("www/index.html").unwrap().clone();
+        assert_eq!(archive.read(&entry).unwrap(), b"hello");
+        let entry = archive.find("Game.dat").unwrap().clone();
+        assert_eq!(archive.read(&entry).unwrap(), b"ok");
+
+        let entry = archive.find("www/packed.bin").unwrap().clone();
+        assert!(entry.compressed);
+        let e = archive.read(&entry).unwrap_err();
+        assert!(matches!(Error::from_io(&e), Some(Error::CompressedEntry)));
+    }
+
+    #[test]
+    fn test_rejects_exe_without_package() {
+        let mut bytes = exe();
+        let at = bytes.windows(4).position(|w| w == MAGIC).unwrap();
+        bytes[at] = b'X';
+        assert!(EvbArchive::open(Cursor::new(bytes)).is_err());
+        assert!(EvbArchive::open(Cursor::new(b"not an exe".to_vec())).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use crate::decrypter::Decrypter;
+use crate::types::*;
+
+/// Longest fake header `HeaderParams::infer` considers.
+const MAX_HEADER_LEN: usize = 64;
+
+/// Bytes of the fake header taken by the signature and the version,
+/// the rest is `remain`.
+const SIGNATURE_LEN: usize = 8;
+const VERSION_LEN: usize = 3;
+
+/// Layout of the fake header in front of encrypted files, as hex strings.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct HeaderParams {
+    pub len: usize,
+    pub signature: String,
+    pub version: String,
+    pub remain: String,
+}
+
+impl Default for HeaderParams {
+    fn default() -> Self {
+        Self {
+            len: Decrypter::DEFAULT_HEADER_LEN,
+            signature: Decrypter::DEFAULT_SIGNATURE.to_string(),
+            version: Decrypter::DEFAULT_VERSION.to_string(),
+            remain: Decrypter::DEFAULT_REMAIN.to_string(),
+        }
+    }
+}
+
+impl HeaderParams {
+    /// Splits raw header bytes into signature, version and remain.
+    pub fn from_bytes(header: &[u8]) -> Self {
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let sig_end = header.len().min(SIGNATURE_LEN);
+        let ver_end = header.len().min(SIGNATURE_LEN + VERSION_LEN);
+
+        Self {
+            len: header.len(),
+            signature: hex(&header[..sig_end]),
+            version: hex(&header[sig_end..ver_end]),
+            remain: hex(&header[ver_end..]),
+        }
+    }
+
+    /// Finds the fake header shared by a set of encrypted files.
+    ///
+    /// Every file has to start with it, so its length is bounded by the
+    /// common prefix. Files encrypted with the same key also share their
+    /// encrypted PNG/OGG magic, so the length is picked by checking where
+    /// the plain structure behind the encrypted bytes lines up: the IHDR CRC
+    /// for PNG, the first page length for OGG and the box after `ftyp` for M4A.
+    pub fn infer(files: &[(&[u8], FileExtension)]) -> Option<Self> {
+        if files.len() < 2 {
+            return None;
+        }
+
+        let (first, _) = files[0];
+        let prefix_len = files[1..].iter().fold(first.len(), |len, (data, _)| {
+            first[..len]
+                .iter()
+                .zip(data.iter())
+                .take_while(|(a, b)| a == b)
+                .count()
+        });
+
+        let len = (1..=prefix_len.min(MAX_HEADER_LEN)).max_by_key(|&len| {
+            let fits = files
+                .iter()
+                .filter(|(data, ext)| content_fits(&data[len..], *ext))
+                .count();
+            // On a tie prefer the standard length.
+            (fits, len == Decrypter::DEFAULT_HEADER_LEN)
+        })?;
+
+        let fits_all = files
+            .iter()
+            .all(|(data, ext)| content_fits(&data[len..], *ext));
+        fits_all.then(|| Self::from_bytes(&first[..len]))
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn apply_to(&self, decrypter: &mut Decrypter) {
+        decrypter.set_header_params(self.len, &self.signature, &self.version, &self.remain);
+    }
+}
+
+/// Checks the plain bytes behind the 16 encrypted ones of `content`.
+fn content_fits(content: &[u8], file_type: FileExtension) -> bool {
+    match file_type {
+        FileExtension::PNG | FileExtension::RPGMVP | FileExtension::PNG_ => {
+            // The IHDR CRC covers the chunk type, which is encrypted but known.
+            if content.len() < 33 {
+                return false;
+            }
+            let mut chunk = b"IHDR".to_vec();
+            chunk.extend_from_slice(&content[16..29]);
+            crc32(&chunk).to_be_bytes() == content[29..33]
+        }
+        FileExtension::OGG | FileExtension::RPGMVO | FileExtension::OGG_ => {
+            // Page sequence 0 and the next page right where the segment table says.
+            if content.len() < 27 || content[18..22] != [0; 4] {
+                return false;
+            }
+            let n_segments = content[26] as usize;
+            let Some(segments) = content.get(27..27 + n_segments) else {
+                return false;
+            };
+            let page_len = 27 + n_segments + segments.iter().map(|&b| b as usize).sum::<usize>();
+            content.get(page_len..page_len + 4) == Some(&b"OggS"[..])
+        }
+        FileExtension::M4A | FileExtension::RPGMVM | FileExtension::M4A_ => {
+            m4a_ftyp_header(content).is_some()
+        }
+        FileExtension::XYZ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u8) -> Vec<u8> {
+        let mut data = PNG_HEADER_BYTES.to_vec();
+        let ihdr = [0, 0, 0, width, 0, 0, 0, 1, 8, 6, 0, 0, 0];
+        let mut chunk = b"IHDR".to_vec();
+        chunk.extend_from_slice(&ihdr);
+        data.extend_from_slice(&ihdr);
+        data.extend_from_slice(&crc32(&chunk).to_be_bytes());
+        data.extend_from_slice(&[0u8; 16]);
+        data
+    }
+
+    #[test]
+    fn test_infer_patched_header() {
+        let params = HeaderParams {
+            len: 16,
+            signature: "4d5947414d450000".to_string(),
+            version: "000102".to_string(),
+            remain: "0000000000".to_string(),
+        };
+        let mut decrypter = Decrypter::new(Key::new("0123456789abcdef0123456789abcdef"));
+        params.apply_to(&mut decrypter);
+
+        let a = decrypter.encrypt(&png(1), FileExtension::PNG_).unwrap();
+        let b = decrypter.encrypt(&png(2), FileExtension::PNG_).unwrap();
+        let inferred =
+            HeaderParams::infer(&[(&a[..], FileExtension::PNG_), (&b[..], FileExtension::PNG_)]);
+        assert_eq!(inferred, Some(params));
+    }
+
+    #[test]
+    fn test_infer_needs_shared_prefix() {
+        let a = [1u8; 64];
+        let b = [2u8; 64];
+        assert_eq!(
+            HeaderParams::infer(&[(&a[..], FileExtension::PNG_), (&b[..], FileExtension::PNG_)]),
+            None
+        );
+    }
+}
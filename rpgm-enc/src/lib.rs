@@ -1,5 +1,21 @@
+mod asar;
+mod consensus;
 mod decrypter;
+mod evb;
+mod header;
+mod rgss;
+mod stream;
 mod types;
+mod xyz;
+mod zip;
 
+pub use asar::{AsarArchive, AsarEntry};
+pub use consensus::{KeyCandidate, KeyConsensus};
 pub use decrypter::Decrypter;
+pub use evb::{EvbArchive, EvbEntry};
+pub use header::HeaderParams;
+pub use rgss::{RgssArchive, RgssEntry, RgssVersion, RgssWriter};
+pub use stream::{DecryptReader, EncryptWriter};
 pub use types::*;
+pub use xyz::XyzImage;
+pub use zip::{ZipArchive, ZipEntry, ZipEntryWriter, ZipWriter};
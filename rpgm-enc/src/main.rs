@@ -0,0 +1,557 @@
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use rpgm_enc::{
+    DecryptReader, Decrypter, EncryptWriter, FileExtension, HeaderParams, Key, KeyConsensus,
+    RPGMakerVersion,
+};
+
+const USAGE: &str = "\
+Usage: rpgm-enc <command> <path>... [options]
+
+Commands:
+  decrypt       Decrypt encrypted assets
+  encrypt       Encrypt plain assets
+  detect-key    Recover the encryption key from encrypted assets
+  verify        Check that encrypted assets decrypt with the key
+
+Options:
+  -k, --key <hex>              Encryption key (detected from the files if omitted)
+  -e, --engine <mv|mz>         RPG Maker version for encrypted extensions [default: mv]
+  -o, --output <dir>           Output directory [default: next to the originals]
+  -r, --recursive              Descend into subdirectories
+      --header-len <n>         Fake header length (inferred from the files if omitted)
+      --signature <hex>        Fake header signature
+      --header-version <hex>   Fake header version
+      --remain <hex>           Fake header remain bytes
+  -h, --help                   Print this help
+
+Exit codes: 0 on success, 1 if any file failed, 2 on invalid usage.";
+
+/// Files and bytes per file read to infer the fake header.
+const MAX_HEADER_SAMPLES: usize = 64;
+const HEADER_SAMPLE_BYTES: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Decrypt,
+    Encrypt,
+    DetectKey,
+    Verify,
+}
+
+#[derive(Default)]
+struct HeaderArgs {
+    len: Option<usize>,
+    signature: Option<String>,
+    version: Option<String>,
+    remain: Option<String>,
+}
+
+impl HeaderArgs {
+    fn is_set(&self) -> bool {
+        self.len.is_some()
+            || self.signature.is_some()
+            || self.version.is_some()
+            || self.remain.is_some()
+    }
+}
+
+struct Options {
+    command: Command,
+    paths: Vec<PathBuf>,
+    key: Option<Key>,
+    version: RPGMakerVersion,
+    output: Option<PathBuf>,
+    recursive: bool,
+    header: HeaderArgs,
+}
+
+enum ParseResult {
+    Run(Options),
+    Help,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<ParseResult, String> {
+    let command = match args.next().as_deref() {
+        Some("decrypt") => Command::Decrypt,
+        Some("encrypt") => Command::Encrypt,
+        Some("detect-key") => Command::DetectKey,
+        Some("verify") => Command::Verify,
+        Some("-h" | "--help") => return Ok(ParseResult::Help),
+        Some(other) => return Err(format!("unknown command '{}'", other)),
+        None => return Err("no command given".to_string()),
+    };
+
+    let mut options = Options {
+        command,
+        paths: Vec::new(),
+        key: None,
+        version: RPGMakerVersion::MV,
+        output: None,
+        recursive: false,
+        header: HeaderArgs::default(),
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("missing value for '{}'", name))
+        };
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(ParseResult::Help),
+            "-r" | "--recursive" => options.recursive = true,
+            "-k" | "--key" => {
+                let hex = value(&arg)?.replace(' ', "");
+                options.key = Some(Key::new(&hex).ok_or(format!("invalid key '{}'", hex))?);
+            }
+            "-e" | "--engine" => {
+                options.version = match value(&arg)?.to_lowercase().as_str() {
+                    "mv" => RPGMakerVersion::MV,
+                    "mz" => RPGMakerVersion::MZ,
+                    other => return Err(format!("unknown engine '{}'", other)),
+                };
+            }
+            "-o" | "--output" => options.output = Some(PathBuf::from(value(&arg)?)),
+            "--header-len" => {
+                let len = value(&arg)?;
+                options.header.len = Some(
+                    len.parse()
+                        .map_err(|_| format!("invalid header length '{}'", len))?,
+                );
+            }
+            "--signature" => options.header.signature = Some(hex_arg(value(&arg)?)?),
+            "--header-version" => options.header.version = Some(hex_arg(value(&arg)?)?),
+            "--remain" => options.header.remain = Some(hex_arg(value(&arg)?)?),
+            flag if flag.starts_with('-') => return Err(format!("unknown option '{}'", flag)),
+            path => options.paths.push(PathBuf::from(path)),
+        }
+    }
+
+    if options.paths.is_empty() {
+        return Err("no input path given".to_string());
+    }
+    Ok(ParseResult::Run(options))
+}
+
+fn hex_arg(value: String) -> Result<String, String> {
+    if value.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(value)
+    } else {
+        Err(format!("'{}' is not a hex string", value))
+    }
+}
+
+/// An input file together with the directory its output path is relative to.
+struct InputFile {
+    root: PathBuf,
+    path: PathBuf,
+    ext: FileExtension,
+}
+
+fn collect_files(options: &Options) -> Result<Vec<InputFile>, String> {
+    let want_encrypted = options.command != Command::Encrypt;
+    let mut files = Vec::new();
+
+    for path in &options.paths {
+        if path.is_dir() {
+            collect_dir(path, path, options.recursive, want_encrypted, &mut files)
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        } else if path.is_file() {
+            let ext = ext_from_path(path)
+                .ok_or_else(|| format!("unsupported file type: {}", path.display()))?;
+            let root = path.parent().unwrap_or(Path::new("")).to_path_buf();
+            files.push(InputFile {
+                root,
+                path: path.clone(),
+                ext,
+            });
+        } else {
+            return Err(format!("no such file or directory: {}", path.display()));
+        }
+    }
+    Ok(files)
+}
+
+fn collect_dir(
+    root: &Path,
+    dir: &Path,
+    recursive: bool,
+    want_encrypted: bool,
+    files: &mut Vec<InputFile>,
+) -> io::Result<()> {
+    let mut entries: Vec<_> = std::fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            if recursive {
+                collect_dir(root, &path, recursive, want_encrypted, files)?;
+            }
+        } else if let Some(ext) = ext_from_path(&path)
+            && ext.is_encrypted() == want_encrypted
+            // XYZ images have no encrypted form.
+            && ext != FileExtension::XYZ
+        {
+            files.push(InputFile {
+                root: root.to_path_buf(),
+                path,
+                ext,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn ext_from_path(path: &Path) -> Option<FileExtension> {
+    FileExtension::from_str(path.extension()?.to_str()?)
+}
+
+fn output_path(options: &Options, file: &InputFile, new_ext: FileExtension) -> PathBuf {
+    let relative = file.path.strip_prefix(&file.root).unwrap_or(&file.path);
+    let mut output = options.output.as_ref().unwrap_or(&file.root).join(relative);
+    output.set_extension(new_ext.to_str());
+    output
+}
+
+fn build_decrypter(params: &HeaderParams, key: Option<Key>) -> Decrypter {
+    let mut decrypter = Decrypter::new(key);
+    params.apply_to(&mut decrypter);
+    decrypter
+}
+
+/// Fake header from the command line, or inferred from the files if none was given.
+fn header_params(options: &Options, files: &[InputFile]) -> HeaderParams {
+    let header = &options.header;
+    if header.is_set() {
+        let defaults = HeaderParams::default();
+        return HeaderParams {
+            len: header.len.unwrap_or(defaults.len),
+            signature: header.signature.clone().unwrap_or(defaults.signature),
+            version: header.version.clone().unwrap_or(defaults.version),
+            remain: header.remain.clone().unwrap_or(defaults.remain),
+        };
+    }
+    if options.command == Command::Encrypt {
+        return HeaderParams::default();
+    }
+
+    let samples: Vec<(Vec<u8>, FileExtension)> = files
+        .iter()
+        .take(MAX_HEADER_SAMPLES)
+        .filter_map(|file| {
+            let mut data = Vec::new();
+            std::fs::File::open(&file.path)
+                .and_then(|f| f.take(HEADER_SAMPLE_BYTES).read_to_end(&mut data))
+                .ok()?;
+            Some((data, file.ext))
+        })
+        .collect();
+    let samples: Vec<(&[u8], FileExtension)> = samples
+        .iter()
+        .map(|(data, ext)| (&data[..], *ext))
+        .collect();
+
+    match HeaderParams::infer(&samples) {
+        Some(params) if !params.is_default() => {
+            println!(
+                "using inferred fake header: len {}, signature {}, version {}, remain {}",
+                params.len, params.signature, params.version, params.remain
+            );
+            params
+        }
+        _ => HeaderParams::default(),
+    }
+}
+
+fn key_consensus(params: &HeaderParams, files: &[InputFile]) -> KeyConsensus {
+    let mut consensus = KeyConsensus::with_header_params(params);
+    for file in files {
+        match std::fs::read(&file.path) {
+            Ok(data) => consensus.add(file.path.display().to_string(), &data, file.ext),
+            Err(e) => eprintln!("failed to read {}: {}", file.path.display(), e),
+        }
+    }
+    consensus
+}
+
+/// Fails when writing `output` would truncate the file being read.
+fn check_output(file: &InputFile, output: &Path) -> io::Result<()> {
+    let same_file = match (
+        std::fs::canonicalize(&file.path),
+        std::fs::canonicalize(output),
+    ) {
+        (Ok(source), Ok(output)) => source == output,
+        _ => false,
+    };
+    if same_file || output == file.path {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "output path is the source file",
+        ));
+    }
+    Ok(())
+}
+
+fn decrypt_file(decrypter: &Decrypter, file: &InputFile, output: &Path) -> io::Result<u64> {
+    check_output(file, output)?;
+    let source = std::fs::File::open(&file.path)?;
+    let mut reader = io::BufReader::new(
+        DecryptReader::new(source, decrypter.clone(), file.ext)
+            .with_verification()
+            .with_restored_header(),
+    );
+    // Check the header and key before creating the output file.
+    io::BufRead::fill_buf(&mut reader)?;
+
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut target = std::fs::File::create(output)?;
+    io::copy(&mut reader, &mut target)
+}
+
+fn encrypt_file(decrypter: &Decrypter, file: &InputFile, output: &Path) -> io::Result<u64> {
+    check_output(file, output)?;
+    let mut source = std::fs::File::open(&file.path)?;
+    if source.metadata()?.len() == 0 {
+        return Err(rpgm_enc::Error::EmptyFile.into());
+    }
+
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let target = std::fs::File::create(output)?;
+    let mut writer = EncryptWriter::new(target, decrypter.clone());
+    let written = io::copy(&mut source, &mut writer)?;
+    writer.finish()?;
+    Ok(written)
+}
+
+fn verify_file(decrypter: &Decrypter, file: &InputFile) -> io::Result<()> {
+    let source = std::fs::File::open(&file.path)?;
+    // The key is checked on the first read.
+    DecryptReader::new(source, decrypter.clone(), file.ext)
+        .with_verification()
+        .read_exact(&mut [0u8; 1])
+}
+
+fn run(options: &Options) -> Result<bool, String> {
+    let files = collect_files(options)?;
+    if files.is_empty() {
+        return Err("no matching files found".to_string());
+    }
+    let params = header_params(options, &files);
+
+    if options.command == Command::DetectKey {
+        let consensus = key_consensus(&params, &files);
+        let ranked = consensus.rank();
+        if ranked.is_empty() {
+            return Err("failed to detect the encryption key".to_string());
+        }
+
+        for candidate in &ranked {
+            println!(
+                "{}  {} votes, fits {:.0}% of {} files",
+                candidate.key,
+                candidate.votes,
+                candidate.confidence * 100.0,
+                consensus.len()
+            );
+            for file in &candidate.failing_files {
+                println!("    fails on {}", file);
+            }
+        }
+        println!("key: {}", ranked[0].key);
+        return Ok(ranked[0].failing_files.is_empty());
+    }
+
+    let key = match options.key.clone() {
+        Some(key) => key,
+        None if options.command == Command::Encrypt => {
+            return Err("encryption needs a key, pass it with --key".to_string());
+        }
+        None => {
+            let best = key_consensus(&params, &files)
+                .best()
+                .ok_or("failed to detect the encryption key")?;
+            println!(
+                "using detected key: {} (fits {:.0}% of files)",
+                best.key,
+                best.confidence * 100.0
+            );
+            best.key
+        }
+    };
+    let decrypter = build_decrypter(&params, Some(key));
+
+    let mut failed = 0;
+    for file in &files {
+        let result = match options.command {
+            Command::Decrypt => {
+                let output = output_path(options, file, file.ext.convert(true, options.version));
+                decrypt_file(&decrypter, file, &output)
+                    .map(|_| format!("{} -> {}", file.path.display(), output.display()))
+            }
+            Command::Encrypt => {
+                let output = output_path(options, file, file.ext.convert(false, options.version));
+                encrypt_file(&decrypter, file, &output)
+                    .map(|_| format!("{} -> {}", file.path.display(), output.display()))
+            }
+            Command::Verify => {
+                verify_file(&decrypter, file).map(|_| file.path.display().to_string())
+            }
+            Command::DetectKey => unreachable!(),
+        };
+
+        match result {
+            Ok(message) => println!("ok      {}", message),
+            Err(e) => {
+                failed += 1;
+                println!("FAILED  {}: {}", file.path.display(), e);
+            }
+        }
+    }
+
+    println!("{} files, {} failed", files.len(), failed);
+    Ok(failed == 0)
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(ParseResult::Run(options)) => options,
+        Ok(ParseResult::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&options) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<ParseResult, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn options(args: &[&str]) -> Options {
+        match parse(args) {
+            Ok(ParseResult::Run(options)) => options,
+            Ok(ParseResult::Help) => panic!("parsed as help"),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = options(&[
+            "decrypt",
+            "www/img",
+            "-k",
+            "0123 4567 89ab cdef 0123 4567 89ab cdef",
+            "--engine",
+            "MZ",
+            "-o",
+            "out",
+            "-r",
+            "Audio",
+        ]);
+        assert_eq!(options.command, Command::Decrypt);
+        assert_eq!(
+            options.paths,
+            [PathBuf::from("www/img"), PathBuf::from("Audio")]
+        );
+        assert!(options.key.is_some());
+        assert_eq!(options.version, RPGMakerVersion::MZ);
+        assert_eq!(options.output, Some(PathBuf::from("out")));
+        assert!(options.recursive);
+        assert!(!options.header.is_set());
+    }
+
+    #[test]
+    fn test_parse_header_args() {
+        let options = options(&[
+            "verify",
+            "www",
+            "--header-len",
+            "32",
+            "--signature",
+            "5250474d56",
+            "--header-version",
+            "000301",
+            "--remain",
+            "0000000000",
+        ]);
+        assert_eq!(options.header.len, Some(32));
+        assert_eq!(options.header.signature.as_deref(), Some("5250474d56"));
+        assert_eq!(options.header.version.as_deref(), Some("000301"));
+        assert_eq!(options.header.remain.as_deref(), Some("0000000000"));
+        assert!(options.header.is_set());
+    }
+
+    #[test]
+    fn test_parse_help() {
+        assert!(matches!(parse(&["--help"]), Ok(ParseResult::Help)));
+        assert!(matches!(
+            parse(&["decrypt", "www", "-h"]),
+            Ok(ParseResult::Help)
+        ));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |args: &[&str]| parse(args).err().unwrap();
+        assert_eq!(error(&[]), "no command given");
+        assert_eq!(error(&["unpack", "www"]), "unknown command 'unpack'");
+        assert_eq!(error(&["decrypt"]), "no input path given");
+        assert_eq!(error(&["decrypt", "www", "-k"]), "missing value for '-k'");
+        assert_eq!(error(&["decrypt", "www", "-k", "abc"]), "invalid key 'abc'");
+        assert_eq!(
+            error(&["decrypt", "www", "-e", "xp"]),
+            "unknown engine 'xp'"
+        );
+        assert_eq!(
+            error(&["decrypt", "www", "--force"]),
+            "unknown option '--force'"
+        );
+        assert_eq!(
+            error(&["decrypt", "www", "--header-len", "-1"]),
+            "invalid header length '-1'"
+        );
+        assert_eq!(
+            error(&["decrypt", "www", "--signature", "RPGMV"]),
+            "'RPGMV' is not a hex string"
+        );
+        assert_eq!(
+            error(&["decrypt", "www", "--remain", "00 00"]),
+            "'00 00' is not a hex string"
+        );
+    }
+
+    #[test]
+    fn test_header_params_from_args() {
+        let options = options(&["decrypt", "www", "--header-len", "32"]);
+        let params = header_params(&options, &[]);
+        assert_eq!(params.len, 32);
+        assert_eq!(params.signature, HeaderParams::default().signature);
+    }
+}
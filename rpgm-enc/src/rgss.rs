@@ -0,0 +1,444 @@
+use std::hash::{BuildHasher, RandomState};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::types::*;
+
+const MAGIC: &[u8; 7] = b"RGSSAD\0";
+
+/// Initial key of v1 archives.
+const V1_KEY: u32 = 0xDEADCAFE;
+
+/// Entry names longer than this mean the archive is damaged.
+const MAX_NAME_LEN: u32 = 4096;
+
+/// Archive format, by engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RgssVersion {
+    /// `.rgssad` (XP) and `.rgss2a` (VX).
+    V1,
+    /// `.rgss3a` (VX Ace).
+    V3,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgssEntry {
+    /// Path inside the archive, with `\` as separator.
+    pub name: String,
+    pub offset: u64,
+    pub size: u32,
+    key: u32,
+}
+
+impl RgssEntry {
+    pub fn components(&self) -> impl Iterator<Item = &str> {
+        self.name.split(['\\', '/']).filter(|c| !c.is_empty())
+    }
+}
+
+/// Reader for RGSSAD archives. The index is read once by `open`, entries
+/// are read and decrypted on demand.
+pub struct RgssArchive<R> {
+    reader: R,
+    version: RgssVersion,
+    entries: Vec<RgssEntry>,
+}
+
+impl<R: Read + Seek> RgssArchive<R> {
+    pub fn open(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; 8];
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| Error::InvalidArchive)?;
+        if &header[..7] != MAGIC {
+            return Err(Error::InvalidArchive.into());
+        }
+
+        let (version, entries) = match header[7] {
+            1 => (RgssVersion::V1, read_v1_index(&mut reader)?),
+            3 => (RgssVersion::V3, read_v3_index(&mut reader)?),
+            _ => return Err(Error::InvalidArchive.into()),
+        };
+
+        Ok(Self {
+            reader,
+            version,
+            entries,
+        })
+    }
+
+    pub fn version(&self) -> RgssVersion {
+        self.version
+    }
+
+    pub fn entries(&self) -> &[RgssEntry] {
+        &self.entries
+    }
+
+    /// Looks an entry up by path, accepting either separator.
+    pub fn find(&self, name: &str) -> Option<&RgssEntry> {
+        let wanted: Vec<&str> = name.split(['\\', '/']).filter(|c| !c.is_empty()).collect();
+        self.entries
+            .iter()
+            .find(|entry| entry.components().eq(wanted.iter().copied()))
+    }
+
+    /// Reads and decrypts an entry of this archive.
+    pub fn read(&mut self, entry: &RgssEntry) -> io::Result<Vec<u8>> {
+        self.reader.seek(SeekFrom::Start(entry.offset))?;
+        let mut data = Vec::new();
+        (&mut self.reader)
+            .take(entry.size as u64)
+            .read_to_end(&mut data)?;
+        if data.len() != entry.size as usize {
+            return Err(Error::InvalidArchive.into());
+        }
+
+        xor_data(&mut data, entry.key);
+        Ok(data)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Writes RGSSAD archives that `RgssArchive` and the engines can read.
+pub struct RgssWriter<W: Write> {
+    inner: W,
+    version: RgssVersion,
+    seed: u32,
+}
+
+impl<W: Write> RgssWriter<W> {
+    /// Uses a random seed, see `with_seed`.
+    pub fn new(inner: W, version: RgssVersion) -> Self {
+        Self {
+            inner,
+            version,
+            seed: RandomState::new().hash_one(0u8) as u32,
+        }
+    }
+
+    /// Base key of v3 archives. v1 archives always start from the same key.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Writes `entries`, given as name and size. `open` is called once per
+    /// entry, in order, for its content.
+    pub fn write<R: Read>(
+        mut self,
+        entries: &[(String, u32)],
+        mut open: impl FnMut(&str) -> io::Result<R>,
+    ) -> io::Result<W> {
+        self.inner.write_all(MAGIC)?;
+        match self.version {
+            RgssVersion::V1 => {
+                self.inner.write_all(&[1])?;
+                self.write_v1(entries, &mut open)?;
+            }
+            RgssVersion::V3 => {
+                self.inner.write_all(&[3])?;
+                self.write_v3(entries, &mut open)?;
+            }
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn write_v1<R: Read>(
+        &mut self,
+        entries: &[(String, u32)],
+        open: &mut impl FnMut(&str) -> io::Result<R>,
+    ) -> io::Result<()> {
+        let mut key = V1_KEY;
+        for (name, size) in entries {
+            self.inner
+                .write_all(&(name.len() as u32 ^ key).to_le_bytes())?;
+            key = next_key(key);
+
+            let mut name_bytes = name.as_bytes().to_vec();
+            for byte in &mut name_bytes {
+                *byte ^= key as u8;
+                key = next_key(key);
+            }
+            self.inner.write_all(&name_bytes)?;
+
+            self.inner.write_all(&(size ^ key).to_le_bytes())?;
+            key = next_key(key);
+
+            self.write_data(open(name)?, *size, key)?;
+        }
+        Ok(())
+    }
+
+    fn write_v3<R: Read>(
+        &mut self,
+        entries: &[(String, u32)],
+        open: &mut impl FnMut(&str) -> io::Result<R>,
+    ) -> io::Result<()> {
+        let key = self.seed.wrapping_mul(9).wrapping_add(3);
+        self.inner.write_all(&self.seed.to_le_bytes())?;
+
+        // Header, seed, one record per entry and the zero terminator.
+        let index_len: usize = entries.iter().map(|(name, _)| 16 + name.len()).sum();
+        let mut offset = (12 + index_len + 4) as u64;
+        let mut file_key = key;
+        let mut file_keys = Vec::with_capacity(entries.len());
+
+        for (name, size) in entries {
+            file_key = next_key(file_key);
+            file_keys.push(file_key);
+            let offset32 = u32::try_from(offset).map_err(|_| Error::InvalidArchive)?;
+            for field in [offset32, *size, file_key, name.len() as u32] {
+                self.inner.write_all(&(field ^ key).to_le_bytes())?;
+            }
+
+            let mut name_bytes = name.as_bytes().to_vec();
+            for (i, byte) in name_bytes.iter_mut().enumerate() {
+                *byte ^= (key >> (8 * (i % 4))) as u8;
+            }
+            self.inner.write_all(&name_bytes)?;
+            offset += *size as u64;
+        }
+        self.inner.write_all(&key.to_le_bytes())?;
+
+        for ((name, size), file_key) in entries.iter().zip(file_keys) {
+            self.write_data(open(name)?, *size, file_key)?;
+        }
+        Ok(())
+    }
+
+    fn write_data(&mut self, source: impl Read, size: u32, key: u32) -> io::Result<()> {
+        let mut data = Vec::with_capacity(size as usize);
+        source.take(size as u64).read_to_end(&mut data)?;
+        if data.len() != size as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Entry shorter than its size",
+            ));
+        }
+        xor_data(&mut data, key);
+        self.inner.write_all(&data)
+    }
+}
+
+fn next_key(key: u32) -> u32 {
+    key.wrapping_mul(7).wrapping_add(3)
+}
+
+/// Entry data is XORed 4 bytes at a time, the key advancing after each word.
+fn xor_data(data: &mut [u8], mut key: u32) {
+    for chunk in data.chunks_mut(4) {
+        for (byte, k) in chunk.iter_mut().zip(key.to_le_bytes()) {
+            *byte ^= k;
+        }
+        key = next_key(key);
+    }
+}
+
+/// Reads a little-endian `u32`, or `None` at the end of the stream.
+fn read_u32(reader: &mut impl Read) -> io::Result<Option<u32>> {
+    let mut buf = [0u8; 4];
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 if filled == 0 => return Ok(None),
+            0 => return Err(Error::InvalidArchive.into()),
+            n => filled += n,
+        }
+    }
+    Ok(Some(u32::from_le_bytes(buf)))
+}
+
+fn expect_u32(reader: &mut impl Read) -> io::Result<u32> {
+    read_u32(reader)?.ok_or_else(|| Error::InvalidArchive.into())
+}
+
+fn read_name(reader: &mut impl Read, len: u32) -> io::Result<Vec<u8>> {
+    if len > MAX_NAME_LEN {
+        return Err(Error::InvalidArchive.into());
+    }
+    let mut name = vec![0u8; len as usize];
+    reader.read_exact(&mut name)?;
+    Ok(name)
+}
+
+/// v1 interleaves the index with the data. Every field is XORed with a
+/// rolling key, names one byte at a time.
+fn read_v1_index(reader: &mut (impl Read + Seek)) -> io::Result<Vec<RgssEntry>> {
+    let mut key = V1_KEY;
+    let mut entries = Vec::new();
+
+    while let Some(name_len) = read_u32(reader)? {
+        let name_len = name_len ^ key;
+        key = next_key(key);
+
+        let mut name = read_name(reader, name_len)?;
+        for byte in &mut name {
+            *byte ^= key as u8;
+            key = next_key(key);
+        }
+
+        let size = expect_u32(reader)? ^ key;
+        key = next_key(key);
+
+        let offset = reader.stream_position()?;
+        entries.push(RgssEntry {
+            name: String::from_utf8_lossy(&name).into_owned(),
+            offset,
+            size,
+            key,
+        });
+        reader.seek(SeekFrom::Current(size as i64))?;
+    }
+
+    Ok(entries)
+}
+
+/// v3 keeps the whole index up front, XORed with a fixed key derived from a
+/// seed. It ends with a zero offset.
+fn read_v3_index(reader: &mut impl Read) -> io::Result<Vec<RgssEntry>> {
+    let key = expect_u32(reader)?.wrapping_mul(9).wrapping_add(3);
+    let mut entries = Vec::new();
+
+    loop {
+        let offset = expect_u32(reader)? ^ key;
+        if offset == 0 {
+            break;
+        }
+        let size = expect_u32(reader)? ^ key;
+        let file_key = expect_u32(reader)? ^ key;
+        let name_len = expect_u32(reader)? ^ key;
+
+        let mut name = read_name(reader, name_len)?;
+        for (i, byte) in name.iter_mut().enumerate() {
+            *byte ^= (key >> (8 * (i % 4))) as u8;
+        }
+
+        entries.push(RgssEntry {
+            name: String::from_utf8_lossy(&name).into_owned(),
+            offset: offset as u64,
+            size,
+            key: file_key,
+        });
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const FILES: [(&str, &[u8]); 2] = [
+        ("Graphics\\Pictures\\title.png", b"not really a png"),
+        ("Audio\\SE\\click.ogg", b"abc"),
+    ];
+
+    fn v1_archive() -> Vec<u8> {
+        let mut out = b"RGSSAD\0\x01".to_vec();
+        let mut key = V1_KEY;
+        for (name, data) in FILES {
+            out.extend_from_slice(&(name.len() as u32 ^ key).to_le_bytes());
+            key = next_key(key);
+            for &byte in name.as_bytes() {
+                out.push(byte ^ key as u8);
+                key = next_key(key);
+            }
+            out.extend_from_slice(&(data.len() as u32 ^ key).to_le_bytes());
+            key = next_key(key);
+            let mut data = data.to_vec();
+            xor_data(&mut data, key);
+            out.extend_from_slice(&data);
+        }
+        out
+    }
+
+    fn v3_archive() -> Vec<u8> {
+        let seed = 0x1234u32;
+        let key = seed.wrapping_mul(9).wrapping_add(3);
+        let mut out = b"RGSSAD\0\x03".to_vec();
+        out.extend_from_slice(&seed.to_le_bytes());
+
+        let index_len: usize = FILES.iter().map(|(name, _)| 16 + name.len()).sum::<usize>() + 4;
+        let mut offset = out.len() + index_len;
+        let mut data_section = Vec::new();
+        for (i, (name, data)) in FILES.iter().enumerate() {
+            let file_key = 0xABCD0000 + i as u32;
+            for field in [
+                offset as u32,
+                data.len() as u32,
+                file_key,
+                name.len() as u32,
+            ] {
+                out.extend_from_slice(&(field ^ key).to_le_bytes());
+            }
+            for (j, &byte) in name.as_bytes().iter().enumerate() {
+                out.push(byte ^ (key >> (8 * (j % 4))) as u8);
+            }
+            let mut data = data.to_vec();
+            xor_data(&mut data, file_key);
+            data_section.extend_from_slice(&data);
+            offset += data.len();
+        }
+        out.extend_from_slice(&key.to_le_bytes());
+        out.extend_from_slice(&data_section);
+        out
+    }
+
+    fn check(bytes: Vec<u8>, version: RgssVersion) {
+        let mut archive = RgssArchive::open(Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.version(), version);
+        assert_eq!(archive.entries().len(), FILES.len());
+
+        for (name, data) in FILES {
+            let entry = archive.find(&name.replace('\\', "/")).unwrap().clone();
+            assert_eq!(entry.name, name);
+            assert_eq!(archive.read(&entry).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_read_v1_archive() {
+        check(v1_archive(), RgssVersion::V1);
+    }
+
+    #[test]
+    fn test_read_v3_archive() {
+        check(v3_archive(), RgssVersion::V3);
+    }
+
+    #[test]
+    fn test_writer_round_trip() {
+        let entries: Vec<(String, u32)> = FILES
+            .iter()
+            .map(|(name, data)| (name.to_string(), data.len() as u32))
+            .collect();
+        let open = |name: &str| {
+            let (_, data) = FILES.iter().find(|(n, _)| *n == name).unwrap();
+            Ok(*data)
+        };
+
+        for version in [RgssVersion::V1, RgssVersion::V3] {
+            let bytes = RgssWriter::new(Vec::new(), version)
+                .with_seed(0xCAFE)
+                .write(&entries, open)
+                .unwrap();
+            if version == RgssVersion::V3 {
+                assert_eq!(bytes[8..12], 0xCAFEu32.to_le_bytes());
+            }
+            check(bytes, version);
+        }
+    }
+
+    #[test]
+    fn test_rejects_truncated_archive() {
+        let mut bytes = v1_archive();
+        bytes.truncate(10);
+        assert!(RgssArchive::open(Cursor::new(bytes)).is_err());
+        assert!(RgssArchive::open(Cursor::new(b"PK\x03\x04".to_vec())).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+use std::io::{self, Read, Write};
+
+use crate::decrypter::Decrypter;
+use crate::types::*;
+
+/// How many decrypted bytes `DecryptReader` buffers before handing them to
+/// `Decrypter::restore_header`. Every header we repair lives well inside it.
+const RESTORE_WINDOW: usize = 512;
+
+/// How many bytes are buffered to verify the key, enough for a full OGG page.
+const VERIFY_WINDOW: usize = 27 + 255 + 255 * 255;
+
+/// Strips the fake header from an encrypted stream and decrypts it on the fly.
+/// Only the first `header_len` bytes of the content are touched, the rest is
+/// passed through as-is, so memory use does not depend on the file size.
+pub struct DecryptReader<R: Read> {
+    inner: R,
+    decrypter: Decrypter,
+    file_type: FileExtension,
+    restore_header: bool,
+    verify: bool,
+    started: bool,
+    position: usize,
+    head: Vec<u8>,
+    head_pos: usize,
+}
+
+impl<R: Read> DecryptReader<R> {
+    pub fn new(inner: R, decrypter: Decrypter, file_type: FileExtension) -> Self {
+        Self {
+            inner,
+            decrypter,
+            file_type,
+            restore_header: false,
+            verify: false,
+            started: false,
+            position: 0,
+            head: Vec::new(),
+            head_pos: 0,
+        }
+    }
+
+    /// Also run `Decrypter::restore_header` on the start of the stream.
+    pub fn with_restored_header(mut self) -> Self {
+        self.restore_header = true;
+        self
+    }
+
+    /// Fail with `Error::WrongKey` if the start of the stream does not
+    /// decrypt to a valid file, see `Decrypter::verify_content`.
+    pub fn with_verification(mut self) -> Self {
+        self.verify = true;
+        self
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        self.started = true;
+
+        let header_len = self.decrypter.get_header_len();
+        let mut header = vec![0u8; header_len];
+        let read = read_full(&mut self.inner, &mut header)?;
+        if read == 0 {
+            return Err(Error::EmptyFile.into());
+        }
+        if read < header_len {
+            return Err(Error::InvalidHeader.into());
+        }
+        if !self.decrypter.ignores_fake_header() && !self.decrypter.verify_fake_header(&header) {
+            return Err(Error::InvalidHeader.into());
+        }
+
+        if self.verify || self.restore_header {
+            let window = if self.verify {
+                VERIFY_WINDOW
+            } else {
+                RESTORE_WINDOW
+            };
+            let mut head = vec![0u8; window];
+            let read = read_full(&mut self.inner, &mut head)?;
+            head.truncate(read);
+            self.decrypt_chunk(&mut head);
+
+            if self.verify {
+                Decrypter::verify_content(&head, self.file_type)?;
+            }
+            if self.restore_header {
+                self.decrypter
+                    .restore_header_in_place(&mut head, self.file_type)?;
+            }
+            self.head = head;
+        }
+        Ok(())
+    }
+
+    fn decrypt_chunk(&mut self, chunk: &mut [u8]) {
+        self.decrypter.xor_at(self.position, chunk);
+        self.position += chunk.len();
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.started {
+            self.start()?;
+        }
+
+        if self.head_pos < self.head.len() {
+            let n = buf.len().min(self.head.len() - self.head_pos);
+            buf[..n].copy_from_slice(&self.head[self.head_pos..self.head_pos + n]);
+            self.head_pos += n;
+            return Ok(n);
+        }
+
+        let n = self.inner.read(buf)?;
+        self.decrypt_chunk(&mut buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Writes the fake header and encrypts the stream on the fly.
+/// Call `finish` once everything is written to flush it and get `W` back.
+pub struct EncryptWriter<W: Write> {
+    inner: W,
+    decrypter: Decrypter,
+    position: usize,
+}
+
+impl<W: Write> EncryptWriter<W> {
+    pub fn new(inner: W, decrypter: Decrypter) -> Self {
+        Self {
+            inner,
+            decrypter,
+            position: 0,
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.position == 0 {
+            return Err(Error::EmptyFile.into());
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.position == 0 {
+            self.inner.write_all(self.decrypter.build_fake_header())?;
+        }
+
+        let header_len = self.decrypter.get_header_len();
+        if self.position < header_len {
+            let mut chunk = buf[..buf.len().min(header_len - self.position)].to_vec();
+            self.decrypter.xor_at(self.position, &mut chunk);
+            self.inner.write_all(&chunk)?;
+            self.position += chunk.len();
+            return Ok(chunk.len());
+        }
+
+        let n = self.inner.write(buf)?;
+        self.position += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out one byte per `read` call to exercise chunk boundaries.
+    struct ByteReader<'a>(&'a [u8]);
+
+    impl Read for ByteReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    fn decrypter() -> Decrypter {
+        Decrypter::new(Some(Key::new("0123456789abcdef0123456789abcdef").unwrap()))
+    }
+
+    #[test]
+    fn test_stream_round_trip() -> io::Result<()> {
+        let decrypter = decrypter();
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+
+        let mut writer = EncryptWriter::new(Vec::new(), decrypter.clone());
+        for chunk in data.chunks(7) {
+            writer.write_all(chunk)?;
+        }
+        let encrypted = writer.finish()?;
+        assert_eq!(
+            encrypted,
+            decrypter.encrypt(&data, FileExtension::OGG_).unwrap()
+        );
+
+        let mut decrypted = Vec::new();
+        DecryptReader::new(ByteReader(&encrypted), decrypter, FileExtension::OGG_)
+            .read_to_end(&mut decrypted)?;
+        assert_eq!(decrypted, data);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_restores_png_header() -> io::Result<()> {
+        let decrypter = decrypter();
+        let mut data = vec![0u8; 64];
+        data[..PNG_HEADER_BYTES.len()].copy_from_slice(&[0xAA; 16]);
+
+        let encrypted = decrypter.encrypt(&data, FileExtension::PNG_).unwrap();
+        let mut restored = Vec::new();
+        DecryptReader::new(encrypted.as_slice(), decrypter.clone(), FileExtension::PNG_)
+            .with_restored_header()
+            .read_to_end(&mut restored)?;
+
+        let decrypted = decrypter.decrypt(&encrypted, FileExtension::PNG_).unwrap();
+        assert_eq!(
+            restored,
+            decrypter
+                .restore_header(&decrypted, FileExtension::PNG_)
+                .unwrap()
+        );
+        assert_eq!(&restored[..16], PNG_HEADER_BYTES);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_verification_rejects_garbage() {
+        let data = decrypter()
+            .encrypt(&[0x42u8; 64], FileExtension::OGG_)
+            .unwrap();
+        let mut reader = DecryptReader::new(data.as_slice(), decrypter(), FileExtension::OGG_)
+            .with_verification();
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(matches!(Error::from_io(&err), Some(Error::WrongKey)));
+    }
+
+    #[test]
+    fn test_stream_rejects_bad_header() {
+        let mut reader = DecryptReader::new(&[0u8; 32][..], decrypter(), FileExtension::PNG_);
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+    }
+}
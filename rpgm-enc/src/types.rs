@@ -4,6 +4,8 @@ pub const PNG_HEADER_BYTES: &[u8] = &[
     0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44, 0x52,
 ];
 
+pub const XYZ_HEADER_BYTES: &[u8] = b"XYZ1";
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub enum RPGMakerVersion {
     #[default]
@@ -11,6 +13,30 @@ pub enum RPGMakerVersion {
     MZ,
 }
 
+/// Encryption settings a project stores in `data/System.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct SystemConfig {
+    pub key: Option<Key>,
+    pub has_encrypted_images: bool,
+    pub has_encrypted_audio: bool,
+}
+
+impl SystemConfig {
+    pub fn from_json(json: &str) -> Option<Self> {
+        let value = serde_json::from_str::<serde_json::Value>(json).ok()?;
+        let flag = |name: &str| value.get(name).and_then(|v| v.as_bool()).unwrap_or(false);
+
+        Some(Self {
+            key: value
+                .get("encryptionKey")
+                .and_then(|k| k.as_str())
+                .and_then(Key::new),
+            has_encrypted_images: flag("hasEncryptedImages"),
+            has_encrypted_audio: flag("hasEncryptedAudio"),
+        })
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct Key {
     #[serde(rename = "key")]
@@ -90,6 +116,18 @@ impl Key {
         Self::bruteforce_last_two_bytes(header_len, data, &key_bytes)
     }
 
+    /// The first 16 bytes of an m4a are the `ftyp` box header (size, type,
+    /// major brand, minor version). They are rebuilt from the plain
+    /// compatible brands and the box that follows, see `m4a_ftyp_header`.
+    pub fn from_m4a_header(header_len: usize, data: &[u8]) -> Option<Self> {
+        if data.len() < header_len * 2 {
+            return None;
+        }
+
+        let known_header = m4a_ftyp_header(&data[header_len..])?;
+        Self::from_known_header(header_len, data, &known_header)
+    }
+
     fn bruteforce_last_two_bytes(
         header_len: usize,
         data: &[u8],
@@ -178,15 +216,9 @@ impl Key {
         Self::new(&key)
     }
 
+    /// The `encryptionKey` of a `System.json`.
     pub fn from_json(json: &str) -> Option<Self> {
-        serde_json::from_str::<serde_json::Value>(json)
-            .ok()
-            .and_then(|v| {
-                v.get("encryptionKey")
-                    .and_then(|k| k.as_str())
-                    .map(|s| s.to_string())
-            })
-            .and_then(|key| Self::new(&key))
+        SystemConfig::from_json(json)?.key
     }
 
     pub fn from_rpg_core(content: &str) -> Option<Self> {
@@ -267,6 +299,8 @@ pub enum FileExtension {
     PNG_,
     OGG_,
     M4A_,
+    /// RPG Maker 2000/2003 paletted image, never encrypted.
+    XYZ,
 }
 
 impl FileExtension {
@@ -281,6 +315,7 @@ impl FileExtension {
             "png_" => Some(Self::PNG_),
             "ogg_" => Some(Self::OGG_),
             "m4a_" => Some(Self::M4A_),
+            "xyz" => Some(Self::XYZ),
             _ => None,
         }
     }
@@ -296,6 +331,7 @@ impl FileExtension {
             Self::PNG_ => "png_",
             Self::OGG_ => "ogg_",
             Self::M4A_ => "m4a_",
+            Self::XYZ => "xyz",
         }
     }
 
@@ -311,12 +347,13 @@ impl FileExtension {
             Self::PNG | Self::RPGMVP | Self::PNG_ => "image/png",
             Self::OGG | Self::RPGMVO | Self::OGG_ => "audio/ogg",
             Self::M4A | Self::RPGMVM | Self::M4A_ => "audio/m4a",
+            Self::XYZ => "image/x-xyz",
         }
     }
 
     pub fn get_file_type(&self) -> FileType {
         match self {
-            Self::PNG | Self::RPGMVP | Self::PNG_ => FileType::Image,
+            Self::PNG | Self::RPGMVP | Self::PNG_ | Self::XYZ => FileType::Image,
             Self::OGG | Self::RPGMVO | Self::OGG_ | Self::M4A | Self::RPGMVM | Self::M4A_ => {
                 FileType::Audio
             }
@@ -331,6 +368,8 @@ impl FileExtension {
                 Self::RPGMVM | Self::M4A_ => Self::M4A,
                 _ => *self,
             }
+        } else if *self == Self::XYZ {
+            *self
         } else {
             match (self.get_file_type(), version) {
                 (FileType::Image, RPGMakerVersion::MV) => Self::RPGMVP,
@@ -362,10 +401,38 @@ pub enum Error {
 
     #[error("Failed to detect encryption key")]
     KeyDetectionFailed,
+
+    #[error("Key mismatch")]
+    WrongKey,
+
+    #[error("Invalid archive")]
+    InvalidArchive,
+
+    #[error("Archive entry is compressed, which is not supported")]
+    CompressedEntry,
+
+    #[error("Invalid image")]
+    InvalidImage,
+
+    #[error("Image has more than 256 colors")]
+    TooManyColors,
+}
+
+impl Error {
+    /// Gets back the `Error` carried by an `io::Error` made with `From<Error>`.
+    pub fn from_io(e: &std::io::Error) -> Option<&Error> {
+        e.get_ref()?.downcast_ref()
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
 const OGG_CRC_TABLE: [u32; 256] = {
     let mut table = [0u32; 256];
     let mut i = 0u32;
@@ -386,10 +453,131 @@ const OGG_CRC_TABLE: [u32; 256] = {
     table
 };
 
-fn ogg_crc32(data: &[u8]) -> u32 {
+pub(crate) fn ogg_crc32(data: &[u8]) -> u32 {
     let mut crc = 0u32;
     for &b in data {
         crc = (crc << 8) ^ OGG_CRC_TABLE[((crc >> 24) as u8 ^ b) as usize];
     }
     crc
 }
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0u32;
+    while i < 256 {
+        let mut crc = i;
+        let mut j = 0;
+        while j < 8 {
+            if crc & 1 != 0 {
+                crc = (crc >> 1) ^ 0xEDB88320;
+            } else {
+                crc >>= 1;
+            }
+            j += 1;
+        }
+        table[i as usize] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 as used for PNG chunks and zip entries.
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+/// Extends the CRC-32 `crc` of some data with the bytes that follow it.
+pub(crate) fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &b in data {
+        crc = (crc >> 8) ^ CRC32_TABLE[((crc as u8) ^ b) as usize];
+    }
+    !crc
+}
+
+/// Length of the OGG page at the start of `data`, if all of it is there.
+fn ogg_page_len(data: &[u8]) -> Option<usize> {
+    let n_segments = *data.get(26)? as usize;
+    let segments = data.get(27..27 + n_segments)?;
+    let page_len = 27 + n_segments + segments.iter().map(|&b| b as usize).sum::<usize>();
+    (data.len() >= page_len).then_some(page_len)
+}
+
+/// Checks the CRC of the OGG page at the start of `data`. A page cut off by
+/// the end of `data` cannot be checked and is accepted.
+pub(crate) fn ogg_first_page_crc_matches(data: &[u8]) -> bool {
+    let Some(page_len) = ogg_page_len(data) else {
+        return true;
+    };
+
+    let stored_crc = u32::from_le_bytes([data[22], data[23], data[24], data[25]]);
+    let mut page = data[..page_len].to_vec();
+    page[22..26].fill(0);
+    ogg_crc32(&page) == stored_crc
+}
+
+/// Rewrites the first 16 bytes of an OGG stream's first page. They are fixed
+/// except for the low half of the stream serial, which is taken from a later
+/// page of the same stream. The page CRC is recomputed afterwards.
+pub(crate) fn rebuild_ogg_first_page(content: &mut [u8]) -> Result<()> {
+    const KNOWN: &[u8; 14] = b"OggS\x00\x02\x00\x00\x00\x00\x00\x00\x00\x00";
+
+    let page_len = ogg_page_len(content).ok_or(Error::InvalidHeader)?;
+    let serial_lo =
+        Key::find_ogg_serial_lo(&content[16..], [content[16], content[17]]).unwrap_or([0, 0]);
+
+    content[..14].copy_from_slice(KNOWN);
+    content[14..16].copy_from_slice(&serial_lo);
+    content[22..26].fill(0);
+    let crc = ogg_crc32(&content[..page_len]);
+    content[22..26].copy_from_slice(&crc.to_le_bytes());
+    Ok(())
+}
+
+/// Box types that usually follow `ftyp` at the start of an mp4 file.
+const M4A_NEXT_BOXES: &[&[u8; 4]] = &[
+    b"free", b"mdat", b"moov", b"wide", b"skip", b"uuid", b"meta", b"pdin", b"moof",
+];
+
+/// Longest `ftyp` box we look for, in compatible brands after the header.
+const M4A_MAX_BRANDS: usize = 16;
+
+/// Rebuilds the 16-byte `ftyp` header of an m4a whose first 16 bytes are
+/// unreadable. The box size is found by locating the next top-level box,
+/// the major brand is taken from the compatible brands (`M4A ` if listed).
+/// The minor version is not recoverable, so we use what encoders write:
+/// ffmpeg (recognisable by `iso2`) uses 0x200, everything else 0.
+pub(crate) fn m4a_ftyp_header(content: &[u8]) -> Option<[u8; 16]> {
+    let size = (0..=M4A_MAX_BRANDS)
+        .map(|i| 16 + i * 4)
+        .take_while(|&size| content.len() >= size + 8)
+        .find(|&size| {
+            let next_size = u32::from_be_bytes(content[size..size + 4].try_into().unwrap());
+            let next_type = &content[size + 4..size + 8];
+            (next_size == 0 || next_size == 1 || next_size >= 8)
+                && M4A_NEXT_BOXES.iter().any(|t| &t[..] == next_type)
+                && content[16..size]
+                    .iter()
+                    .all(|&b| b == 0 || b.is_ascii_graphic() || b == b' ')
+        })?;
+
+    let brands: Vec<&[u8]> = content[16..size].chunks(4).collect();
+    let major_brand = brands
+        .iter()
+        .find(|&&brand| brand == b"M4A ")
+        .or_else(|| brands.iter().find(|brand| brand.iter().any(|&b| b != 0)))
+        .copied()
+        .unwrap_or(b"M4A ");
+    let minor_version: u32 = if brands.contains(&&b"iso2"[..]) {
+        0x200
+    } else {
+        0
+    };
+
+    let mut header = [0u8; 16];
+    header[0..4].copy_from_slice(&(size as u32).to_be_bytes());
+    header[4..8].copy_from_slice(b"ftyp");
+    header[8..12].copy_from_slice(major_brand);
+    header[12..16].copy_from_slice(&minor_version.to_be_bytes());
+    Some(header)
+}
@@ -0,0 +1,262 @@
+use std::io::{Cursor, Read, Write};
+
+use flate2::{Compression, read::ZlibDecoder, write::ZlibEncoder};
+
+use crate::types::*;
+
+const PALETTE_LEN: usize = 256 * 3;
+
+/// Pixels with less alpha are mapped to palette index 0, which RPG Maker
+/// 2000/2003 draws as transparent.
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// An 8-bit paletted image in the XYZ format of RPG Maker 2000/2003.
+///
+/// The file is `XYZ1`, the width and height as little-endian `u16`, then a
+/// zlib stream with the 256 RGB palette entries followed by one palette
+/// index per pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XyzImage {
+    pub width: u16,
+    pub height: u16,
+    pub palette: [[u8; 3]; 256],
+    pub pixels: Vec<u8>,
+}
+
+impl XyzImage {
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < 8 || !data.starts_with(XYZ_HEADER_BYTES) {
+            return Err(Error::InvalidHeader);
+        }
+        let width = u16::from_le_bytes([data[4], data[5]]);
+        let height = u16::from_le_bytes([data[6], data[7]]);
+        let pixel_count = width as usize * height as usize;
+
+        let mut body = Vec::with_capacity(PALETTE_LEN + pixel_count);
+        ZlibDecoder::new(&data[8..])
+            .take((PALETTE_LEN + pixel_count) as u64)
+            .read_to_end(&mut body)
+            .map_err(|_| Error::InvalidImage)?;
+        if body.len() != PALETTE_LEN + pixel_count {
+            return Err(Error::InvalidImage);
+        }
+
+        let mut palette = [[0u8; 3]; 256];
+        for (entry, rgb) in palette
+            .iter_mut()
+            .zip(body[..PALETTE_LEN].as_chunks::<3>().0)
+        {
+            *entry = *rgb;
+        }
+
+        Ok(Self {
+            width,
+            height,
+            palette,
+            pixels: body[PALETTE_LEN..].to_vec(),
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = XYZ_HEADER_BYTES.to_vec();
+        out.extend_from_slice(&self.width.to_le_bytes());
+        out.extend_from_slice(&self.height.to_le_bytes());
+
+        let mut encoder = ZlibEncoder::new(out, Compression::default());
+        // Writing to a Vec cannot fail.
+        encoder.write_all(self.palette.as_flattened()).unwrap();
+        encoder.write_all(&self.pixels).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Pixels as RGBA, all opaque. Whether index 0 is transparent depends on
+    /// where the engine uses the image.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|&index| {
+                let [r, g, b] = self.palette[index as usize];
+                [r, g, b, 255]
+            })
+            .collect()
+    }
+
+    /// Builds an image from RGBA pixels with at most 256 colors, counting
+    /// transparent pixels as one color kept at index 0.
+    pub fn from_rgba(width: u16, height: u16, rgba: &[u8]) -> Result<Self> {
+        if rgba.len() != width as usize * height as usize * 4 {
+            return Err(Error::InvalidImage);
+        }
+
+        let (rgba, _) = rgba.as_chunks::<4>();
+        let transparent = rgba.iter().find(|px| px[3] < ALPHA_THRESHOLD);
+        let reserved = transparent.is_some() as usize;
+        let mut colors: Vec<[u8; 3]> = transparent
+            .map(|px| [px[0], px[1], px[2]])
+            .into_iter()
+            .collect();
+
+        let mut pixels = Vec::with_capacity(rgba.len());
+        for px in rgba {
+            if px[3] < ALPHA_THRESHOLD {
+                pixels.push(0);
+                continue;
+            }
+            let rgb = [px[0], px[1], px[2]];
+            let index = match colors[reserved..].iter().position(|&c| c == rgb) {
+                Some(i) => i + reserved,
+                None => {
+                    if colors.len() == 256 {
+                        return Err(Error::TooManyColors);
+                    }
+                    colors.push(rgb);
+                    colors.len() - 1
+                }
+            };
+            pixels.push(index as u8);
+        }
+
+        let mut palette = [[0u8; 3]; 256];
+        palette[..colors.len()].copy_from_slice(&colors);
+        Ok(Self {
+            width,
+            height,
+            palette,
+            pixels,
+        })
+    }
+
+    /// Encodes as an 8-bit indexed PNG with the same palette.
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(self.palette.as_flattened());
+
+        let mut writer = encoder.write_header().map_err(|_| Error::InvalidImage)?;
+        writer
+            .write_image_data(&self.pixels)
+            .map_err(|_| Error::InvalidImage)?;
+        writer.finish().map_err(|_| Error::InvalidImage)?;
+        Ok(out)
+    }
+
+    /// Decodes a PNG. 8-bit indexed images keep their palette as is, others
+    /// go through `from_rgba`.
+    pub fn from_png(data: &[u8]) -> Result<Self> {
+        let reader = png::Decoder::new(Cursor::new(data))
+            .read_info()
+            .map_err(|_| Error::InvalidImage)?;
+        let info = reader.info();
+        let width = u16::try_from(info.width).map_err(|_| Error::InvalidImage)?;
+        let height = u16::try_from(info.height).map_err(|_| Error::InvalidImage)?;
+
+        if info.color_type == png::ColorType::Indexed
+            && info.bit_depth == png::BitDepth::Eight
+            && let Some(png_palette) = info.palette.as_deref()
+        {
+            let mut palette = [[0u8; 3]; 256];
+            for (entry, rgb) in palette.iter_mut().zip(png_palette.as_chunks::<3>().0) {
+                *entry = *rgb;
+            }
+            let pixels = read_frame(reader)?;
+            return Ok(Self {
+                width,
+                height,
+                palette,
+                pixels,
+            });
+        }
+
+        let mut decoder = png::Decoder::new(Cursor::new(data));
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let reader = decoder.read_info().map_err(|_| Error::InvalidImage)?;
+        let (color_type, _) = reader.output_color_type();
+        let pixels = read_frame(reader)?;
+
+        let rgba: Vec<u8> = match color_type {
+            png::ColorType::Rgba => pixels,
+            png::ColorType::Rgb => pixels
+                .as_chunks::<3>()
+                .0
+                .iter()
+                .flat_map(|px| [px[0], px[1], px[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => pixels
+                .as_chunks::<2>()
+                .0
+                .iter()
+                .flat_map(|px| [px[0], px[0], px[0], px[1]])
+                .collect(),
+            png::ColorType::Grayscale => pixels.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+            png::ColorType::Indexed => return Err(Error::InvalidImage),
+        };
+        Self::from_rgba(width, height, &rgba)
+    }
+}
+
+fn read_frame(mut reader: png::Reader<Cursor<&[u8]>>) -> Result<Vec<u8>> {
+    let size = reader.output_buffer_size().ok_or(Error::InvalidImage)?;
+    let mut buf = vec![0u8; size];
+    let frame = reader
+        .next_frame(&mut buf)
+        .map_err(|_| Error::InvalidImage)?;
+    buf.truncate(frame.buffer_size());
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> XyzImage {
+        let mut palette = [[0u8; 3]; 256];
+        palette[1] = [255, 0, 0];
+        palette[2] = [0, 0, 255];
+        XyzImage {
+            width: 3,
+            height: 2,
+            palette,
+            pixels: vec![0, 1, 2, 2, 1, 0],
+        }
+    }
+
+    #[test]
+    fn test_xyz_round_trip() {
+        let image = sample();
+        let encoded = image.encode();
+        assert!(encoded.starts_with(XYZ_HEADER_BYTES));
+        assert_eq!(XyzImage::decode(&encoded).unwrap(), image);
+    }
+
+    #[test]
+    fn test_png_conversion_keeps_palette() {
+        let image = sample();
+        let png = image.to_png().unwrap();
+        assert_eq!(XyzImage::from_png(&png).unwrap(), image);
+    }
+
+    #[test]
+    fn test_from_rgba_reserves_transparent_index() {
+        let rgba = [
+            10, 20, 30, 0, //
+            1, 2, 3, 255, //
+            4, 5, 6, 255, //
+            1, 2, 3, 255,
+        ];
+        let image = XyzImage::from_rgba(2, 2, &rgba).unwrap();
+        assert_eq!(image.pixels, vec![0, 1, 2, 1]);
+        assert_eq!(image.palette[1], [1, 2, 3]);
+        assert_eq!(image.palette[0], [10, 20, 30]);
+        assert_eq!(image.palette[2], [4, 5, 6]);
+    }
+
+    #[test]
+    fn test_decode_rejects_truncated_data() {
+        let mut encoded = sample().encode();
+        encoded.truncate(12);
+        assert!(XyzImage::decode(&encoded).is_err());
+        assert!(XyzImage::decode(b"XYZ1").is_err());
+    }
+}
@@ -0,0 +1,472 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use flate2::{Compression, read::DeflateDecoder, write::DeflateEncoder};
+
+use crate::types::*;
+
+const LOCAL_HEADER_SIG: &[u8; 4] = b"PK\x03\x04";
+const CENTRAL_HEADER_SIG: &[u8; 4] = b"PK\x01\x02";
+const END_OF_DIRECTORY_SIG: &[u8; 4] = b"PK\x05\x06";
+const DATA_DESCRIPTOR_SIG: &[u8; 4] = b"PK\x07\x08";
+
+const LOCAL_HEADER_LEN: usize = 30;
+const CENTRAL_HEADER_LEN: usize = 46;
+const END_OF_DIRECTORY_LEN: usize = 22;
+const MAX_COMMENT_LEN: usize = 0xFFFF;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+/// Version 2.0, needed for deflate.
+const ZIP_VERSION: u16 = 20;
+/// Sizes and CRC follow the data, names are UTF-8.
+const WRITER_FLAGS: u16 = 0x0808;
+/// 1980-01-01, the earliest date a zip can hold.
+const DOS_DATE: u16 = 0x21;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipEntry {
+    /// Path inside the archive, with `/` as separator.
+    pub name: String,
+    pub size: u64,
+    compressed_size: u64,
+    method: u16,
+    crc: u32,
+    header_offset: u64,
+}
+
+impl ZipEntry {
+    pub fn components(&self) -> impl Iterator<Item = &str> {
+        self.name.split(['\\', '/']).filter(|c| !c.is_empty())
+    }
+}
+
+/// Reader for zip archives like NW.js `package.nw`, including ones appended
+/// to an executable. Only stored and deflated entries can be read.
+pub struct ZipArchive<R> {
+    reader: R,
+    entries: Vec<ZipEntry>,
+}
+
+impl<R: Read + Seek> ZipArchive<R> {
+    pub fn open(mut reader: R) -> io::Result<Self> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        let tail_len = len.min((END_OF_DIRECTORY_LEN + MAX_COMMENT_LEN) as u64);
+        reader.seek(SeekFrom::Start(len - tail_len))?;
+        let mut tail = vec![0u8; tail_len as usize];
+        reader.read_exact(&mut tail)?;
+
+        let eocd = (0..tail.len().saturating_sub(END_OF_DIRECTORY_LEN - 1))
+            .rev()
+            .find(|&i| tail[i..].starts_with(END_OF_DIRECTORY_SIG))
+            .ok_or(Error::InvalidArchive)?;
+        let eocd_pos = len - tail_len + eocd as u64;
+        let eocd = &tail[eocd..eocd + END_OF_DIRECTORY_LEN];
+        let directory_size = u32_at(eocd, 12) as u64;
+        let directory_offset = u32_at(eocd, 16) as u64;
+
+        // Offsets are relative to the start of the zip, which is not the
+        // start of the file when it was appended to an executable.
+        let base = eocd_pos
+            .checked_sub(directory_size + directory_offset)
+            .ok_or(Error::InvalidArchive)?;
+
+        reader.seek(SeekFrom::Start(base + directory_offset))?;
+        let mut directory = vec![0u8; directory_size as usize];
+        reader.read_exact(&mut directory)?;
+
+        Ok(Self {
+            reader,
+            entries: parse_central_directory(&directory, base)?,
+        })
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    /// Looks an entry up by path, accepting either separator.
+    pub fn find(&self, name: &str) -> Option<&ZipEntry> {
+        let wanted: Vec<&str> = name.split(['\\', '/']).filter(|c| !c.is_empty()).collect();
+        self.entries
+            .iter()
+            .find(|entry| entry.components().eq(wanted.iter().copied()))
+    }
+
+    /// Reads and inflates an entry, checking its CRC.
+    pub fn read(&mut self, entry: &ZipEntry) -> io::Result<Vec<u8>> {
+        self.reader.seek(SeekFrom::Start(entry.header_offset))?;
+        let mut header = [0u8; LOCAL_HEADER_LEN];
+        self.reader.read_exact(&mut header)?;
+        if !header.starts_with(LOCAL_HEADER_SIG) {
+            return Err(Error::InvalidArchive.into());
+        }
+        let skip = u16_at(&header, 26) as i64 + u16_at(&header, 28) as i64;
+        self.reader.seek(SeekFrom::Current(skip))?;
+
+        let compressed = (&mut self.reader).take(entry.compressed_size);
+        let mut data = Vec::new();
+        match entry.method {
+            METHOD_STORED => compressed.take(entry.size).read_to_end(&mut data)?,
+            METHOD_DEFLATED => DeflateDecoder::new(compressed)
+                .take(entry.size)
+                .read_to_end(&mut data)?,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Unsupported zip compression method",
+                ));
+            }
+        };
+
+        if data.len() as u64 != entry.size || crc32(&data) != entry.crc {
+            return Err(Error::InvalidArchive.into());
+        }
+        Ok(data)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Counts the bytes written, which gives the offsets of entries.
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct CentralRecord {
+    name: String,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    header_offset: u32,
+}
+
+/// Writes deflated zip archives as a stream, without seeking back.
+/// Zip64 is not supported, so archives are limited to 4 GiB.
+pub struct ZipWriter<W: Write> {
+    out: CountingWriter<W>,
+    records: Vec<CentralRecord>,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            out: CountingWriter { inner, count: 0 },
+            records: Vec::new(),
+        }
+    }
+
+    /// Starts an entry at `name`, with `/` as separator. Its data is written
+    /// to the returned writer, which must be finished before the next entry.
+    pub fn start_entry(&mut self, name: &str) -> io::Result<ZipEntryWriter<'_, W>> {
+        let header_offset = zip32(self.out.count)?;
+        let name_len = u16::try_from(name.len()).map_err(|_| too_large())?;
+
+        let mut header = Vec::with_capacity(LOCAL_HEADER_LEN + name.len());
+        header.extend_from_slice(LOCAL_HEADER_SIG);
+        header.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+        header.extend_from_slice(&WRITER_FLAGS.to_le_bytes());
+        header.extend_from_slice(&METHOD_DEFLATED.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&DOS_DATE.to_le_bytes());
+        // CRC and sizes, in the data descriptor instead.
+        header.extend_from_slice(&[0; 12]);
+        header.extend_from_slice(&name_len.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        self.out.write_all(&header)?;
+
+        Ok(ZipEntryWriter {
+            data_offset: self.out.count,
+            encoder: DeflateEncoder::new(&mut self.out, Compression::default()),
+            records: &mut self.records,
+            name: name.to_string(),
+            header_offset,
+            crc: 0,
+            size: 0,
+        })
+    }
+
+    /// Adds an entry holding everything read from `data`. Returns its size.
+    pub fn write_entry(&mut self, name: &str, mut data: impl Read) -> io::Result<u64> {
+        let mut entry = self.start_entry(name)?;
+        let size = io::copy(&mut data, &mut entry)?;
+        entry.finish()?;
+        Ok(size)
+    }
+
+    /// Writes the central directory and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let directory_offset = zip32(self.out.count)?;
+        let count = u16::try_from(self.records.len()).map_err(|_| too_large())?;
+
+        let mut directory = Vec::new();
+        for record in &self.records {
+            directory.extend_from_slice(CENTRAL_HEADER_SIG);
+            directory.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+            directory.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+            directory.extend_from_slice(&WRITER_FLAGS.to_le_bytes());
+            directory.extend_from_slice(&METHOD_DEFLATED.to_le_bytes());
+            directory.extend_from_slice(&0u16.to_le_bytes());
+            directory.extend_from_slice(&DOS_DATE.to_le_bytes());
+            directory.extend_from_slice(&record.crc.to_le_bytes());
+            directory.extend_from_slice(&record.compressed_size.to_le_bytes());
+            directory.extend_from_slice(&record.size.to_le_bytes());
+            directory.extend_from_slice(&(record.name.len() as u16).to_le_bytes());
+            // Extra and comment lengths, disk number and attributes.
+            directory.extend_from_slice(&[0; 12]);
+            directory.extend_from_slice(&record.header_offset.to_le_bytes());
+            directory.extend_from_slice(record.name.as_bytes());
+        }
+        let directory_size = zip32(directory.len() as u64)?;
+
+        directory.extend_from_slice(END_OF_DIRECTORY_SIG);
+        directory.extend_from_slice(&[0; 4]);
+        directory.extend_from_slice(&count.to_le_bytes());
+        directory.extend_from_slice(&count.to_le_bytes());
+        directory.extend_from_slice(&directory_size.to_le_bytes());
+        directory.extend_from_slice(&directory_offset.to_le_bytes());
+        directory.extend_from_slice(&0u16.to_le_bytes());
+        self.out.write_all(&directory)?;
+        self.out.flush()?;
+        Ok(self.out.inner)
+    }
+}
+
+/// Deflates the data of one entry. Call `finish` once everything is written.
+pub struct ZipEntryWriter<'a, W: Write> {
+    encoder: DeflateEncoder<&'a mut CountingWriter<W>>,
+    records: &'a mut Vec<CentralRecord>,
+    name: String,
+    header_offset: u32,
+    data_offset: u64,
+    crc: u32,
+    size: u64,
+}
+
+impl<W: Write> ZipEntryWriter<'_, W> {
+    pub fn finish(self) -> io::Result<()> {
+        let out = self.encoder.finish()?;
+        let compressed_size = zip32(out.count - self.data_offset)?;
+        let size = zip32(self.size)?;
+
+        let mut descriptor = Vec::with_capacity(16);
+        descriptor.extend_from_slice(DATA_DESCRIPTOR_SIG);
+        descriptor.extend_from_slice(&self.crc.to_le_bytes());
+        descriptor.extend_from_slice(&compressed_size.to_le_bytes());
+        descriptor.extend_from_slice(&size.to_le_bytes());
+        out.write_all(&descriptor)?;
+
+        self.records.push(CentralRecord {
+            name: self.name,
+            crc: self.crc,
+            compressed_size,
+            size,
+            header_offset: self.header_offset,
+        });
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ZipEntryWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.encoder.write(buf)?;
+        self.crc = crc32_update(self.crc, &buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.flush()
+    }
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "Too large for a zip archive")
+}
+
+/// `value` as a zip field, where `u32::MAX` would mean zip64.
+fn zip32(value: u64) -> io::Result<u32> {
+    u32::try_from(value)
+        .ok()
+        .filter(|&v| v != u32::MAX)
+        .ok_or_else(too_large)
+}
+
+fn u16_at(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn u32_at(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+fn parse_central_directory(directory: &[u8], base: u64) -> io::Result<Vec<ZipEntry>> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+
+    while pos + CENTRAL_HEADER_LEN <= directory.len() {
+        let header = &directory[pos..pos + CENTRAL_HEADER_LEN];
+        if !header.starts_with(CENTRAL_HEADER_SIG) {
+            return Err(Error::InvalidArchive.into());
+        }
+        let flags = u16_at(header, 8);
+        let method = u16_at(header, 10);
+        let crc = u32_at(header, 16);
+        let compressed_size = u32_at(header, 20);
+        let size = u32_at(header, 24);
+        let name_len = u16_at(header, 28) as usize;
+        let extra_len = u16_at(header, 30) as usize;
+        let comment_len = u16_at(header, 32) as usize;
+        let header_offset = u32_at(header, 42);
+
+        let name_start = pos + CENTRAL_HEADER_LEN;
+        let name = directory
+            .get(name_start..name_start + name_len)
+            .ok_or(Error::InvalidArchive)?;
+        pos = name_start + name_len + extra_len + comment_len;
+
+        // Zip64 sizes live in the extra field, which we do not read.
+        if [compressed_size, size, header_offset].contains(&u32::MAX) {
+            return Err(Error::InvalidArchive.into());
+        }
+        // Directories and encrypted entries.
+        if name.ends_with(b"/") || flags & 1 != 0 {
+            continue;
+        }
+
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            size: size as u64,
+            compressed_size: compressed_size as u64,
+            method,
+            crc,
+            header_offset: base + header_offset as u64,
+        });
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const FILES: [(&str, &[u8]); 2] = [
+        ("www/data/System.json", b"{\"encryptionKey\":\"00\"}"),
+        (
+            "www/img/title.png_",
+            b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        ),
+    ];
+
+    /// The first file is stored, the second deflated.
+    fn zip(prefix: &[u8]) -> Vec<u8> {
+        let mut out = prefix.to_vec();
+        let mut directory = Vec::new();
+        for (i, (name, data)) in FILES.iter().enumerate() {
+            let (method, body) = if i == 0 {
+                (METHOD_STORED, data.to_vec())
+            } else {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data).unwrap();
+                (METHOD_DEFLATED, encoder.finish().unwrap())
+            };
+            let offset = (out.len() - prefix.len()) as u32;
+            let crc = crc32(data);
+
+            let mut common = Vec::new();
+            common.extend_from_slice(&20u16.to_le_bytes());
+            common.extend_from_slice(&0u16.to_le_bytes());
+            common.extend_from_slice(&method.to_le_bytes());
+            common.extend_from_slice(&[0; 4]);
+            common.extend_from_slice(&crc.to_le_bytes());
+            common.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            common.extend_from_slice(&0u16.to_le_bytes());
+
+            out.extend_from_slice(LOCAL_HEADER_SIG);
+            out.extend_from_slice(&common);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&body);
+
+            directory.extend_from_slice(CENTRAL_HEADER_SIG);
+            directory.extend_from_slice(&20u16.to_le_bytes());
+            directory.extend_from_slice(&common);
+            directory.extend_from_slice(&[0; 10]);
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+
+        let directory_offset = (out.len() - prefix.len()) as u32;
+        out.extend_from_slice(&directory);
+        out.extend_from_slice(END_OF_DIRECTORY_SIG);
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&(FILES.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(FILES.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        out.extend_from_slice(&directory_offset.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out
+    }
+
+    fn check(bytes: Vec<u8>) {
+        let mut archive = ZipArchive::open(Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.entries().len(), FILES.len());
+        for (name, data) in FILES {
+            let entry = archive.find(name).unwrap().clone();
+            assert_eq!(archive.read(&entry).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_read_zip() {
+        check(zip(b""));
+    }
+
+    #[test]
+    fn test_read_zip_appended_to_executable() {
+        check(zip(b"MZ\x90\x00 pretend this is nw.exe"));
+    }
+
+    #[test]
+    fn test_write_zip() {
+        let mut writer = ZipWriter::new(Vec::new());
+        writer
+            .write_entry(FILES[0].0, Cursor::new(FILES[0].1))
+            .unwrap();
+        let mut entry = writer.start_entry(FILES[1].0).unwrap();
+        for byte in FILES[1].1 {
+            entry.write_all(&[*byte]).unwrap();
+        }
+        entry.finish().unwrap();
+        check(writer.finish().unwrap());
+    }
+
+    #[test]
+    fn test_rejects_corrupted_entry() {
+        let mut bytes = zip(b"");
+        // Inside the stored System.json body.
+        bytes[LOCAL_HEADER_LEN + FILES[0].0.len() + 2] ^= 0xFF;
+        let mut archive = ZipArchive::open(Cursor::new(bytes)).unwrap();
+        let entry = archive.entries()[0].clone();
+        assert!(archive.read(&entry).is_err());
+    }
+}
@@ -175,18 +175,62 @@ fn test_full_audio_only_pipeline() -> Result<()> {
 }
 
 #[test]
-fn test_m4a_passthrough() -> Result<()> {
-    let key = Key::new("deadbeef").unwrap();
+fn test_m4a_round_trip() -> Result<()> {
+    let test_m4a = include_bytes!("test_data/test.m4a_");
+
+    let key = Decrypter::detect_key(test_m4a, FileExtension::M4A_)
+        .expect("detect_key should find key in M4A");
     let decrypter = Decrypter::new(Some(key));
 
-    let mut m4a = vec![0x00, 0x00, 0x00, 0x20];
-    m4a.extend_from_slice(b"ftypM4A ");
-    m4a.extend_from_slice(&[0u8; 8]);
+    let decrypted = decrypter.decrypt(test_m4a, FileExtension::M4A_)?;
+    assert_eq!(&decrypted[4..8], b"ftyp");
+    assert!(verify_audio_format(&decrypted, "m4a"));
+
+    let encrypted = decrypter.encrypt(&decrypted, FileExtension::M4A_)?;
+    assert_eq!(&encrypted[..], &test_m4a[..]);
+
+    // Without the key the ftyp box is rebuilt from the plain bytes behind it.
+    let restored = Decrypter::new(None).restore_header(test_m4a, FileExtension::M4A_)?;
+    assert_eq!(restored, decrypted);
+
+    let other = Decrypter::new(Key::new("00112233445566778899aabbccddeeff"));
+    let reencrypted = other.encrypt(&decrypted, FileExtension::RPGMVM)?;
+    let recovered = Decrypter::detect_key(&reencrypted, FileExtension::RPGMVM)
+        .expect("detect_key should find key in re-encrypted M4A");
+    assert_eq!(recovered.as_str(), "00112233445566778899aabbccddeeff");
+    Ok(())
+}
+
+#[test]
+fn test_ogg_wrong_key() -> Result<()> {
+    let test_ogg = include_bytes!("test_data/test.ogg_");
+    let key = Decrypter::detect_key(test_ogg, FileExtension::OGG_).unwrap();
+
+    let decrypter = Decrypter::new(Some(key.clone()));
+    let decrypted = decrypter.decrypt_verified(test_ogg, FileExtension::OGG_)?;
+    assert_eq!(&decrypted[0..4], b"OggS");
+
+    // Only the stream serial differs, which the page CRC catches.
+    let mut wrong = key.as_str().to_string();
+    wrong.replace_range(28..30, "00");
+    let decrypter = Decrypter::new(Key::new(&wrong));
+    assert!(matches!(
+        decrypter.decrypt_verified(test_ogg, FileExtension::OGG_),
+        Err(rpgm_enc::Error::WrongKey)
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_ogg_recovered_without_key() -> Result<()> {
+    let test_ogg = include_bytes!("test_data/test.ogg_");
 
-    let encrypted = decrypter.encrypt(&m4a, FileExtension::M4A_)?;
-    assert_eq!(encrypted, m4a);
+    let recovered = Decrypter::new(None).recover_without_key(test_ogg, FileExtension::OGG_)?;
+    Decrypter::verify_content(&recovered, FileExtension::OGG_)?;
+    assert!(verify_audio_format(&recovered, "ogg"));
 
-    let decrypted = decrypter.decrypt(&m4a, FileExtension::M4A_)?;
-    assert_eq!(decrypted, m4a);
+    let key = Decrypter::detect_key(test_ogg, FileExtension::OGG_).unwrap();
+    let decrypted = Decrypter::new(Some(key)).decrypt(test_ogg, FileExtension::OGG_)?;
+    assert_eq!(recovered, decrypted);
     Ok(())
 }
@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn rpgm_enc(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rpgm-enc"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// A fresh folder holding the encrypted test image.
+fn game_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rpgm-enc-cli-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("img")).unwrap();
+    std::fs::write(
+        dir.join("img/test.png_"),
+        include_bytes!("test_data/test.png_"),
+    )
+    .unwrap();
+    dir
+}
+
+fn path(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn test_help_succeeds() {
+    let output = rpgm_enc(&["--help"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("Usage:"));
+}
+
+#[test]
+fn test_invalid_usage_exits_with_2() {
+    for args in [
+        &[][..],
+        &["unpack", "www"],
+        &["decrypt", "www", "--header-len", "long"],
+        &["decrypt", "www", "--signature", "xyz"],
+    ] {
+        let output = rpgm_enc(args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Usage:"));
+    }
+}
+
+#[test]
+fn test_missing_path_exits_with_1() {
+    let output = rpgm_enc(&["decrypt", "no/such/folder"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no such file or directory"));
+}
+
+#[test]
+fn test_detect_key_and_decrypt() {
+    let dir = game_dir("decrypt");
+    let output = rpgm_enc(&["detect-key", path(&dir), "-r"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("key: "));
+
+    let out = dir.join("out");
+    let output = rpgm_enc(&["decrypt", path(&dir), "-r", "-o", path(&out)]);
+    assert_eq!(output.status.code(), Some(0));
+    let image = std::fs::read(out.join("img/test.png")).unwrap();
+    assert!(image.starts_with(rpgm_enc::PNG_HEADER_BYTES));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_wrong_key_exits_with_1() {
+    let dir = game_dir("wrong-key");
+    let output = rpgm_enc(&[
+        "verify",
+        path(&dir),
+        "-r",
+        "-k",
+        "00112233445566778899aabbccddeeff",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("1 files, 1 failed"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_encrypt_leaves_xyz_files_alone() {
+    let dir = game_dir("xyz");
+    let image = dir.join("img/a.png");
+    let xyz = dir.join("img/b.xyz");
+    std::fs::write(&image, b"not checked when encrypting").unwrap();
+    std::fs::write(&xyz, b"XYZ1 image data").unwrap();
+
+    let key = "00112233445566778899aabbccddeeff";
+    let output = rpgm_enc(&["encrypt", path(&dir), "-r", "-k", key]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(dir.join("img/a.rpgmvp").exists());
+    assert_eq!(std::fs::read(&xyz).unwrap(), b"XYZ1 image data");
+
+    // Named on its own, it is refused instead of truncated.
+    let output = rpgm_enc(&["encrypt", path(&xyz), "-k", key]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(std::fs::read(&xyz).unwrap(), b"XYZ1 image data");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,12 +1,15 @@
-use log::{debug, trace};
+use log::{debug, error, trace};
 
 use crate::components::audio::AudioState;
 use crate::components::crypt_manager::CryptManager;
 use crate::components::crypt_settings::ui::CryptSettingsWindow;
 use crate::components::dropped_file::DroppedFile;
 use crate::components::file_browser::FileBrowser;
+use crate::components::history::ui::HistoryWindow;
 use crate::components::image_viewer::ImageViewer;
+use crate::components::jobs::ui::JobsWindow;
 use crate::components::logger;
+use crate::components::trash::ui::TrashWindow;
 use crate::components::ui_settings::UiSettings;
 use crate::theme;
 use egui::Panel;
@@ -37,6 +40,84 @@ impl ImageViewerApp {
         app.audio = AudioState::new();
         app
     }
+
+    /// Arrows or J/K step through the listed images, Space runs the
+    /// slideshow and F11 toggles fullscreen.
+    fn handle_navigation_keys(&mut self, ctx: &egui::Context) {
+        if ctx.wants_keyboard_input() || self.file_browser.current_image.is_none() {
+            return;
+        }
+        let (previous, next, slideshow, fullscreen) = ctx.input(|i| {
+            (
+                i.key_pressed(egui::Key::ArrowLeft) || i.key_pressed(egui::Key::K),
+                i.key_pressed(egui::Key::ArrowRight) || i.key_pressed(egui::Key::J),
+                i.key_pressed(egui::Key::Space),
+                i.key_pressed(egui::Key::F11),
+            )
+        });
+        let step = match (previous, next) {
+            (true, false) => Some(-1),
+            (false, true) => Some(1),
+            _ => None,
+        };
+        if let Some(step) = step {
+            self.file_browser
+                .show_neighbour(ctx, &self.crypt_settings, step, false);
+            self.image_viewer.slideshow.restart();
+        }
+        if slideshow {
+            self.image_viewer.slideshow.toggle();
+        }
+        if fullscreen {
+            let fullscreen = !self.image_viewer.fullscreen;
+            self.image_viewer.set_fullscreen(ctx, fullscreen);
+        }
+    }
+
+    /// Undo and redo of the current project's file changes.
+    fn show_history_menu(&mut self, ui: &mut egui::Ui) {
+        let Some(history) = self.crypt_settings.history_mut() else {
+            return;
+        };
+        let undo = history
+            .last_undoable()
+            .map(|batch| (batch.id, format!("Undo {}", batch.title)));
+        let redo = history
+            .last_redoable()
+            .map(|batch| (batch.id, format!("Redo {}", batch.title)));
+
+        let mut result = None;
+        let undo_label = undo.as_ref().map_or("Undo", |(_, label)| label.as_str());
+        if ui
+            .add_enabled(undo.is_some(), egui::Button::new(undo_label).truncate())
+            .clicked()
+            && let Some((id, _)) = undo
+        {
+            result = Some(history.undo(id));
+        }
+        let redo_label = redo.as_ref().map_or("Redo", |(_, label)| label.as_str());
+        if ui
+            .add_enabled(redo.is_some(), egui::Button::new(redo_label).truncate())
+            .clicked()
+            && let Some((id, _)) = redo
+        {
+            result = Some(history.redo(id));
+        }
+        if ui.button("History").clicked() {
+            history.toggle_history();
+        }
+        if ui.button("Recently Deleted").clicked() {
+            self.file_browser.trash.toggle_trash();
+        }
+
+        if let Some(result) = result {
+            if let Err(e) = result {
+                error!("Some files could not be restored:\n{}", e);
+            }
+            self.file_browser.reset_cache();
+            self.file_browser.trash.refresh();
+        }
+    }
 }
 
 impl eframe::App for ImageViewerApp {
@@ -47,69 +128,147 @@ impl eframe::App for ImageViewerApp {
     fn ui(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
         let ctx = ui.ctx().clone();
         self.ui_settings.apply(&ctx);
+        let backdrop = self.crypt_settings.backdrop();
+        backdrop.prepare(&ctx, &self.crypt_settings);
 
         if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
-            self.file_browser.current_image = None;
-            debug!("Esc pressed, current_image reset to None");
+            if self.image_viewer.fullscreen || self.image_viewer.slideshow.running {
+                self.image_viewer.set_fullscreen(&ctx, false);
+                self.image_viewer.slideshow.running = false;
+            } else {
+                self.file_browser.current_image = None;
+                debug!("Esc pressed, current_image reset to None");
+            }
+        }
+        self.handle_navigation_keys(&ctx);
+
+        if self.image_viewer.slideshow.is_due(&ctx)
+            && !self
+                .file_browser
+                .show_neighbour(&ctx, &self.crypt_settings, 1, true)
+        {
+            self.image_viewer.slideshow.running = false;
         }
+        // Fullscreen shows only the image.
+        let image_only = self.image_viewer.fullscreen && self.file_browser.current_image.is_some();
 
-        Panel::top("top_panel").show(ui, |ui| {
-            ui.horizontal(|ui| {
-                ui.menu_button("Menu", |ui| {
-                    if ui.button("Open Folder...").clicked() {
-                        if let Some(path) = rfd::FileDialog::new().pick_folder() {
+        if !image_only {
+            Panel::top("top_panel").show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.menu_button("Menu", |ui| {
+                        #[cfg(target_arch = "wasm32")]
+                        if ui
+                            .button("Upload Files...")
+                            .on_hover_text("Upload a game folder as a .zip to keep its structure")
+                            .clicked()
+                        {
+                            crate::web::pick_files(&ctx, self.dropped_file.uploads.clone());
+                            ui.close();
+                        }
+                        #[cfg(not(target_arch = "wasm32"))]
+                        if ui.button("Open Folder...").clicked() {
+                            if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                                self.crypt_settings
+                                    .set_current_directory(path, Some(&mut self.file_browser));
+                            }
+                        }
+                        #[cfg(not(target_arch = "wasm32"))]
+                        if ui.button("Open Package...").clicked()
+                            && let Some(path) = rfd::FileDialog::new()
+                                .add_filter(
+                                    "Game packages",
+                                    &["nw", "asar", "exe", "zip", "rgss3a", "rgss2a", "rgssad"],
+                                )
+                                .pick_file()
+                        {
                             self.crypt_settings
                                 .set_current_directory(path, Some(&mut self.file_browser));
                         }
-                    }
-                    ui.separator();
-                    if ui.button("Crypt Settings").clicked() {
-                        self.crypt_settings.toggle_settings();
-                    }
-                    if ui.button("UI Settings").clicked() {
-                        self.ui_settings.toggle_ui_settings();
-                    }
-                    if !cfg!(target_arch = "wasm32") {
+                        if let Some(root) = self.crypt_settings.current_folder.clone() {
+                            FileBrowser::show_export_menu(ui, &root, &mut self.crypt_settings);
+                        }
+                        ui.separator();
+                        self.show_history_menu(ui);
                         ui.separator();
-                        if ui.button("Exit").clicked() {
-                            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                        if ui.button("Crypt Settings").clicked() {
+                            self.crypt_settings.toggle_settings();
+                        }
+                        if ui.button("UI Settings").clicked() {
+                            self.ui_settings.toggle_ui_settings();
+                        }
+                        if ui.button("Jobs").clicked() {
+                            self.file_browser.jobs.toggle_jobs();
                         }
-                    }
+                        if !cfg!(target_arch = "wasm32") {
+                            ui.separator();
+                            if ui.button("Exit").clicked() {
+                                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                            }
+                        }
+                    });
                 });
             });
-        });
+        }
 
         if self.ui_settings.show_ui_settings {
             use crate::components::ui_settings::ui::UiSettingsWindow;
-            UiSettingsWindow::show(&ctx, &mut self.ui_settings, &mut self.file_browser);
+            UiSettingsWindow::show(
+                &ctx,
+                &mut self.ui_settings,
+                &mut self.file_browser,
+                &mut self.crypt_settings,
+            );
         }
 
         if self.crypt_settings.show_settings() {
             CryptSettingsWindow::show(&ctx, &mut self.crypt_settings);
         }
 
-        Panel::left("files_panel")
-            .resizable(true)
-            .default_size(200.0)
-            .min_size(10.0)
-            .show(ui, |ui| {
-                self.file_browser.show(
-                    ui,
-                    &ctx,
-                    &mut self.crypt_settings,
-                    &self.ui_settings,
-                    &mut self.audio,
-                );
-            });
+        if let Some(history) = self.crypt_settings.history_mut() {
+            history.sync();
+            if history.show_history {
+                HistoryWindow::show(&ctx, history, &mut self.file_browser);
+            }
+        }
 
-        if self.audio.is_audio_loaded() {
-            Panel::bottom("audio_player").min_size(60.0).show(ui, |ui| {
-                self.audio.show(ui);
-            });
+        if self.file_browser.trash.show_trash
+            && let Some(root) = self.crypt_settings.current_folder.clone()
+        {
+            TrashWindow::show(&ctx, &root, &mut self.file_browser);
+        }
+
+        if self.file_browser.jobs.show_jobs {
+            JobsWindow::show(&ctx, &mut self.file_browser.jobs);
+        }
+
+        if !image_only {
+            Panel::left("files_panel")
+                .resizable(true)
+                .default_size(200.0)
+                .min_size(10.0)
+                .show(ui, |ui| {
+                    self.file_browser.show(
+                        ui,
+                        &ctx,
+                        &mut self.crypt_settings,
+                        &self.ui_settings,
+                        &mut self.audio,
+                    );
+                });
+
+            if self.audio.is_audio_loaded() {
+                Panel::bottom("audio_player").min_size(60.0).show(ui, |ui| {
+                    self.audio.show(ui);
+                });
+            }
         }
 
-        self.image_viewer
-            .show(ui, &mut self.crypt_settings, &mut self.file_browser);
+        self.image_viewer.show(
+            ui,
+            &mut self.crypt_settings,
+            &mut self.file_browser,
+            &backdrop,
+        );
 
         if self.ui_settings.show_logger {
             egui::Window::new("Log")
@@ -3,7 +3,9 @@ use rodio::{
     buffer::SamplesBuffer, source::Source,
 };
 use rpgm_enc::{Decrypter, FileExtension};
-use std::{path::Path, time::Duration};
+use std::{io::Read, path::Path, time::Duration};
+
+use crate::components::crypt_manager::CryptManager;
 
 pub mod ui;
 
@@ -11,6 +13,7 @@ pub mod ui;
 pub struct TrackMetadata {
     pub filename: String,
     pub duration: Duration,
+    pub recovered_without_key: bool,
 }
 
 pub struct AudioState {
@@ -57,8 +60,8 @@ impl AudioState {
     pub fn play_audio(
         &mut self,
         filename: &str,
-        raw: &[u8],
-        decrypter: &Decrypter,
+        mut source: impl Read,
+        decrypter: Option<&Decrypter>,
     ) -> Result<(), String> {
         self.stop_audio();
 
@@ -76,20 +79,25 @@ impl AudioState {
             .and_then(|e| e.to_str())
             .unwrap_or("");
 
-        let data = if let Some(ext) = FileExtension::from_str(ext_str) {
-            if ext.is_encrypted() {
-                let decrypted = decrypter
-                    .decrypt(raw, ext)
-                    .map_err(|e| format!("Failed to decrypt audio: {}", e))?;
-                decrypter
-                    .restore_header(&decrypted, ext)
-                    .map_err(|e| format!("Failed to restore audio header: {}", e))?
-            } else {
-                raw.to_vec()
+        let mut data = Vec::new();
+        let mut recovered_without_key = false;
+        match FileExtension::from_str(ext_str) {
+            Some(ext) if ext.is_encrypted() => {
+                let decrypted =
+                    CryptManager::decrypt_or_recover(Path::new(filename), source, decrypter, ext)
+                        .map_err(|e| format!("Failed to decrypt audio: {}", e))?;
+                if decrypted.recovered_without_key {
+                    log::info!("Recovered without key: {}", filename);
+                    recovered_without_key = true;
+                }
+                data = decrypted.data;
             }
-        } else {
-            raw.to_vec()
-        };
+            _ => {
+                source
+                    .read_to_end(&mut data)
+                    .map_err(|e| format!("Failed to read audio: {}", e))?;
+            }
+        }
 
         let cursor = std::io::Cursor::new(data);
         let decoder = Decoder::try_from(cursor)
@@ -113,6 +121,7 @@ impl AudioState {
         self.current_metadata = TrackMetadata {
             filename: filename.to_string(),
             duration,
+            recovered_without_key,
         };
         self.player = Some(player);
         self.current_audio_name = Some(filename.to_string());
@@ -15,6 +15,9 @@ impl AudioState {
                     ui.add(egui::Label::new(
                         RichText::new(&metadata.filename).size(18.0).strong(),
                     ));
+                    if metadata.recovered_without_key {
+                        ui.colored_label(ui.visuals().warn_fg_color, "⚠ Recovered without key");
+                    }
                 });
 
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
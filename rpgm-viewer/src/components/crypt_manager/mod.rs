@@ -1,17 +1,62 @@
+pub mod plan;
+
 use std::{
     collections::HashMap,
+    io::{BufRead, BufWriter, Read, Write},
     path::{Path, PathBuf},
 };
 
-use log::info;
+use log::{info, warn};
+
+use crate::vfs;
 
 use crate::components::file_browser;
 
 use super::{
     crypt_settings::CryptSettings,
     file_browser::{FileBrowser, file_entry::FileEntry},
+    history::{History, Recorder},
+    image_viewer::backdrop::Backdrop,
 };
 
+pub use plan::{BatchMode, BatchPlan, ConflictPolicy, PlanAction, PlannedFile};
+
+/// Upper bound on the files read when voting on a folder's key.
+const MAX_KEY_SAMPLES: usize = 64;
+/// Bytes read from each of them, enough for the first OGG page.
+const KEY_SAMPLE_BYTES: u64 = 64 * 1024;
+
+/// Keeps a copy of what is read while `recorded` is set, so the start of a
+/// stream can be read again.
+struct RecordingReader<R> {
+    inner: R,
+    recorded: Option<Vec<u8>>,
+}
+
+impl<R: Read> Read for RecordingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(recorded) = &mut self.recorded {
+            recorded.extend_from_slice(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+/// Content of an encrypted file and how it was obtained.
+pub struct Decrypted {
+    pub data: Vec<u8>,
+    /// The key was unknown or wrong and `Decrypter::recover_without_key` was used.
+    pub recovered_without_key: bool,
+}
+
+/// The form assets take in a zip export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZipExport {
+    Decrypted,
+    Encrypted,
+}
+
 #[derive(serde::Deserialize, serde::Serialize, Default)]
 pub struct CryptManager {
     settings: HashMap<PathBuf, CryptSettings>,
@@ -67,7 +112,7 @@ impl CryptManager {
         }
     }
 
-    fn ext_from_path(path: &Path) -> Option<rpgm_enc::FileExtension> {
+    pub(crate) fn ext_from_path(path: &Path) -> Option<rpgm_enc::FileExtension> {
         let ext_str = path.extension()?.to_str()?;
         rpgm_enc::FileExtension::from_str(ext_str)
     }
@@ -80,7 +125,7 @@ impl CryptManager {
         }
 
         info!("Attempting to extract key from file: {:?}", path);
-        let file_data = match std::fs::read(path) {
+        let file_data = match vfs::read(path) {
             Ok(d) => d,
             Err(e) => {
                 info!("Failed to read file: {:?}, {}", path, e);
@@ -104,8 +149,36 @@ impl CryptManager {
         info!("Setting encryption key: {}", key.as_str());
         if let Some(crypt_settings) = self.get_mut_settings() {
             crypt_settings.encryption_key = Some(key.clone());
-            crypt_settings.decrypter = Some(rpgm_enc::Decrypter::new(Some(key.clone())));
+            let mut decrypter = rpgm_enc::Decrypter::new(Some(key.clone()));
+            crypt_settings.header_params.apply_to(&mut decrypter);
+            crypt_settings.decrypter = Some(decrypter);
+            crypt_settings.key_warning = None;
+        }
+    }
+
+    pub fn update_header_params(&mut self, params: rpgm_enc::HeaderParams) {
+        info!(
+            "Setting fake header: len {}, signature {}, version {}, remain {}",
+            params.len, params.signature, params.version, params.remain
+        );
+        if let Some(crypt_settings) = self.get_mut_settings() {
+            if let Some(decrypter) = &mut crypt_settings.decrypter {
+                params.apply_to(decrypter);
+            }
+            crypt_settings.header_params = params;
+        }
+    }
+
+    /// The folder's decrypter, or one without a key for keyless recovery.
+    pub fn decrypter_or_keyless(&self) -> rpgm_enc::Decrypter {
+        if let Some(decrypter) = self.get_decrypter() {
+            return decrypter.clone();
+        }
+        let mut keyless = rpgm_enc::Decrypter::new(None);
+        if let Some(settings) = self.get_settings() {
+            settings.header_params.apply_to(&mut keyless);
         }
+        keyless
     }
 
     pub fn set_current_directory(&mut self, path: PathBuf, file_browser: Option<&mut FileBrowser>) {
@@ -114,30 +187,164 @@ impl CryptManager {
             browser.reset_cache();
         }
 
+        // Settings of a folder opened before are kept with the app settings,
+        // which the web build stores in the browser. Only detection runs again.
+        let mut settings = self.settings.remove(&path).unwrap_or_default();
+        let saved_key = settings.encryption_key.clone();
+        settings.decrypt_path.get_or_insert_with(|| path.clone());
+        settings.crypt_path.get_or_insert_with(|| path.clone());
+        settings.key_warning = None;
+
         self.current_folder = Some(path.clone());
-        let mut settings = CryptSettings::default();
-        settings.decrypt_path = Some(path.clone());
-        settings.crypt_path = Some(path.clone());
         self.settings.insert(path.clone(), settings);
 
-        if let Some(settings) = self.settings.get(&path) {
-            if settings.encryption_key.is_none() {
-                let walker = walkdir::WalkDir::new(&path)
-                    .into_iter()
-                    .filter_map(|e| e.ok());
-
-                for entry in walker {
-                    let file_path = entry.path().to_path_buf();
-                    let ext_str = file_path.extension().and_then(|e| e.to_str()).unwrap_or("");
-
-                    if matches!(ext_str, "png_" | "rpgmvp" | "ogg_" | "rpgmvo") {
-                        if let Some(key) = self.try_extract_key(&file_path) {
-                            info!("Found key in: {} -> {}", file_path.display(), key.as_str());
-                            self.update_encryption_key(&key);
-                            break;
-                        }
-                    }
-                }
+        let project_key = self.load_project_config(&path).or(saved_key);
+        self.find_consensus_key(&path, project_key);
+    }
+
+    /// Reads `System.json` and the core script of the game in `path`, if any,
+    /// to set the engine version and encryption flags. Returns the project's key.
+    fn load_project_config(&mut self, path: &Path) -> Option<rpgm_enc::Key> {
+        let roots = [path.join("www"), path.to_path_buf()];
+
+        let system_config = roots.iter().find_map(|root| {
+            let file = root.join("data").join("System.json");
+            let json = String::from_utf8(vfs::read(&file).ok()?).ok()?;
+            info!("Reading project settings from {}", file.display());
+            rpgm_enc::SystemConfig::from_json(json.trim_start_matches('\u{feff}'))
+        });
+
+        let core_script = roots.iter().find_map(|root| {
+            [
+                ("rmmz_core.js", rpgm_enc::RPGMakerVersion::MZ),
+                ("rpg_core.js", rpgm_enc::RPGMakerVersion::MV),
+            ]
+            .into_iter()
+            .find_map(|(name, version)| {
+                let file = root.join("js").join(name);
+                let content = vfs::read(&file).ok()?;
+                Some((file, version, content))
+            })
+        });
+
+        let mut key = system_config.as_ref().and_then(|config| config.key.clone());
+        if let Some((file, version, content)) = &core_script {
+            info!("Detected RPG Maker {:?} from {}", version, file.display());
+            if key.is_none() {
+                key = rpgm_enc::Key::from_rpg_core(&String::from_utf8_lossy(content));
+            }
+        }
+        if let Some(key) = &key {
+            info!("Found project key: {}", key.as_str());
+        }
+
+        if let Some(settings) = self.get_mut_settings() {
+            if let Some((_, version, _)) = core_script {
+                settings.rpgmaker_version = version;
+            }
+            settings.system_config = system_config;
+        }
+        key
+    }
+
+    /// Infers the fake header, then votes on the key across the encrypted
+    /// files under `path` and warns when some of them do not decrypt with the winner.
+    /// The project's own key is preferred unless another one fits more files.
+    fn find_consensus_key(&mut self, path: &Path, project_key: Option<rpgm_enc::Key>) {
+        let mut samples = Vec::new();
+        for file in vfs::walk_files(path) {
+            if samples.len() >= MAX_KEY_SAMPLES {
+                break;
+            }
+            let Some(ext) = Self::ext_from_path(&file).filter(|ext| ext.is_encrypted()) else {
+                continue;
+            };
+
+            let mut data = Vec::new();
+            let read = vfs::open(&file)
+                .and_then(|reader| reader.take(KEY_SAMPLE_BYTES).read_to_end(&mut data));
+            if let Err(e) = read {
+                info!("Failed to read file: {:?}, {}", file, e);
+                continue;
+            }
+            samples.push((file.display().to_string(), data, ext));
+        }
+
+        let headers: Vec<(&[u8], rpgm_enc::FileExtension)> = samples
+            .iter()
+            .map(|(_, data, ext)| (&data[..], *ext))
+            .collect();
+        // Parameters set in the settings window win over inferred ones.
+        let has_params = self
+            .get_settings()
+            .is_some_and(|settings| !settings.header_params.is_default());
+        if !has_params
+            && let Some(params) = rpgm_enc::HeaderParams::infer(&headers)
+            && !params.is_default()
+        {
+            info!("Files use a non-standard fake header");
+            self.update_header_params(params);
+        }
+
+        let params = self
+            .get_settings()
+            .map(|settings| settings.header_params.clone())
+            .unwrap_or_default();
+        let mut consensus = rpgm_enc::KeyConsensus::with_header_params(&params);
+        for (name, data, ext) in &samples {
+            consensus.add(name.as_str(), data, *ext);
+        }
+        if let Some(key) = &project_key {
+            consensus.propose(key.clone());
+        }
+
+        let ranked = consensus.rank();
+        for candidate in &ranked {
+            info!(
+                "Key candidate {} - {} votes, {:.0}% of {} files",
+                candidate.key.as_str(),
+                candidate.votes,
+                candidate.confidence * 100.0,
+                consensus.len()
+            );
+        }
+
+        let top = ranked.first();
+        let best = match project_key
+            .as_ref()
+            .and_then(|key| ranked.iter().find(|candidate| candidate.key == *key))
+        {
+            Some(project) if top.is_some_and(|top| project.confidence >= top.confidence) => {
+                Some(project)
+            }
+            Some(project) => {
+                warn!(
+                    "Project key {} fits fewer files than the detected one",
+                    project.key.as_str()
+                );
+                top
+            }
+            None => top,
+        };
+        let Some(best) = best else {
+            info!("No key found in {}", path.display());
+            return;
+        };
+        self.update_encryption_key(&best.key);
+
+        if !best.failing_files.is_empty() {
+            let warning = format!(
+                "{} of {} files do not decrypt with key {}, they may use a different key",
+                best.failing_files.len(),
+                consensus.len(),
+                best.key.as_str()
+            );
+            warn!("{}", warning);
+            for file in &best.failing_files {
+                warn!("Key mismatch: {}", file);
+            }
+            if let Some(settings) = self.get_mut_settings() {
+                settings.key_warning = Some(warning);
             }
         }
     }
@@ -150,40 +357,109 @@ impl CryptManager {
     }
 
     pub fn encrypt_file(&self, path: &Path) -> Result<(), String> {
-        let file_data = std::fs::read(path).map_err(|e| e.to_string())?;
+        let file_data = vfs::read(path).map_err(|e| e.to_string())?;
         let ext = Self::ext_from_path(path).ok_or("Unknown file extension")?;
         let decrypter = self.get_decrypter().ok_or("No encryption key set")?;
         let encrypted_data = decrypter
             .encrypt(&file_data, ext)
             .map_err(|e| e.to_string())?;
-        std::fs::write(path, encrypted_data).map_err(|e| e.to_string())?;
+        vfs::write(path, &encrypted_data).map_err(|e| e.to_string())?;
         Ok(())
     }
 
     pub fn decrypt_file(&self, path: &Path) -> Result<Vec<u8>, String> {
-        let file_data = std::fs::read(path).map_err(|e| e.to_string())?;
-        let ext = Self::ext_from_path(path).ok_or("Unknown file extension")?;
-        let decrypter = self.get_decrypter().ok_or("No decryption key set")?;
-        let decrypted_content = decrypter
-            .decrypt(&file_data, ext)
-            .map_err(|e| format!("Decryption failed: {}", e))?;
-        Ok(decrypted_content)
+        self.read_decrypted(path, false)
     }
 
     pub fn decrypt_file_with_header(&self, path: &Path) -> Result<Vec<u8>, String> {
-        let file_data = std::fs::read(path).map_err(|e| e.to_string())?;
+        self.read_decrypted(path, true)
+    }
+
+    fn read_decrypted(&self, path: &Path, restore_header: bool) -> Result<Vec<u8>, String> {
+        let file = vfs::open(path).map_err(|e| e.to_string())?;
         let ext = Self::ext_from_path(path).ok_or("Unknown file extension")?;
         let decrypter = self.get_decrypter().ok_or("No decryption key set")?;
 
-        let decrypted_content = decrypter
-            .decrypt(&file_data, ext)
-            .map_err(|e| format!("Decryption failed: {}", e))?;
+        let mut reader =
+            rpgm_enc::DecryptReader::new(file, decrypter.clone(), ext).with_verification();
+        if restore_header {
+            reader = reader.with_restored_header();
+        }
+        let mut content = Vec::new();
+        reader
+            .read_to_end(&mut content)
+            .map_err(Self::decryption_error)?;
+        Ok(content)
+    }
 
-        let restored_content = decrypter
-            .restore_header(&decrypted_content, ext)
-            .map_err(|e| format!("Header restoration failed: {}", e))?;
+    /// Reports a wrong key as a key mismatch instead of a generic failure.
+    fn decryption_error(e: std::io::Error) -> String {
+        match rpgm_enc::Error::from_io(&e) {
+            Some(rpgm_enc::Error::WrongKey) => "Key mismatch".to_string(),
+            _ => format!("Decryption failed: {}", e),
+        }
+    }
 
-        Ok(restored_content)
+    /// Decrypts and restores `source`, falling back to keyless recovery when
+    /// there is no key or it does not match the file. With a key the file is
+    /// streamed and only the start needed to detect a mismatch is kept.
+    pub fn decrypt_or_recover(
+        path: &Path,
+        source: impl Read,
+        decrypter: Option<&rpgm_enc::Decrypter>,
+        ext: rpgm_enc::FileExtension,
+    ) -> std::io::Result<Decrypted> {
+        let mut source = RecordingReader {
+            inner: source,
+            recorded: Some(Vec::new()),
+        };
+
+        if let Some(decrypter) = decrypter.filter(|d| d.key.is_some()) {
+            let mut reader = rpgm_enc::DecryptReader::new(source, decrypter.clone(), ext)
+                .with_verification()
+                .with_restored_header();
+            let mut data = Vec::new();
+            let mut chunk = [0u8; 8192];
+            // The key is verified on the first read, after that the start
+            // is no longer needed.
+            let result = reader.read(&mut chunk).and_then(|n| {
+                data.extend_from_slice(&chunk[..n]);
+                reader.get_mut().recorded = None;
+                reader.read_to_end(&mut data)
+            });
+            match result {
+                Ok(_) => {
+                    return Ok(Decrypted {
+                        data,
+                        recovered_without_key: false,
+                    });
+                }
+                Err(e)
+                    if matches!(
+                        rpgm_enc::Error::from_io(&e),
+                        Some(rpgm_enc::Error::WrongKey)
+                    ) =>
+                {
+                    warn!(
+                        "Key mismatch for {}, recovering without key",
+                        path.display()
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+            source = reader.into_inner();
+        }
+
+        let mut raw = source.recorded.take().unwrap_or_default();
+        source.inner.read_to_end(&mut raw)?;
+        let keyless = decrypter
+            .cloned()
+            .unwrap_or_else(|| rpgm_enc::Decrypter::new(None));
+        let data = keyless.recover_without_key(&raw, ext)?;
+        Ok(Decrypted {
+            data,
+            recovered_without_key: true,
+        })
     }
 
     pub fn is_file_encrypted(&self, path: &Path) -> bool {
@@ -195,174 +471,475 @@ impl CryptManager {
         })
     }
 
-    pub fn encrypt_folder(
+    /// Snapshot of the settings encrypting and decrypting need.
+    pub fn crypt_batch(&self) -> Result<CryptBatch, String> {
+        let root = self.current_folder.clone().ok_or("No root folder set")?;
+        let crypt_settings = self.get_settings().ok_or("No settings set")?;
+        let decrypter = self.get_decrypter().ok_or("No encryption key set")?;
+        Ok(CryptBatch {
+            crypt_path: crypt_settings
+                .crypt_path
+                .clone()
+                .unwrap_or_else(|| root.clone()),
+            decrypt_path: crypt_settings
+                .decrypt_path
+                .clone()
+                .unwrap_or_else(|| root.clone()),
+            version: crypt_settings.rpgmaker_version,
+            decrypter: decrypter.clone(),
+            root,
+        })
+    }
+
+    /// Plans encrypting or decrypting every asset under `path` that is not
+    /// in the wanted form yet.
+    pub fn plan_folder(&self, path: &Path, mode: BatchMode) -> Result<BatchPlan, String> {
+        let batch = self.crypt_batch()?;
+        let policy = self.conflict_policy();
+        let sources = FileEntry::recursive_collect_all_entries_flat(path, 0)
+            .into_iter()
+            .filter(|entry| !entry.is_folder && entry.is_encrypted == (mode == BatchMode::Decrypt))
+            .map(|entry| entry.path)
+            .collect();
+        Ok(BatchPlan::new(
+            batch,
+            mode,
+            path.to_path_buf(),
+            sources,
+            policy,
+        ))
+    }
+
+    pub fn conflict_policy(&self) -> ConflictPolicy {
+        self.get_settings()
+            .map(|settings| settings.conflict_policy)
+            .unwrap_or_default()
+    }
+
+    pub fn encrypt_image(
         &mut self,
         path: &std::path::Path,
         file_browser: &mut FileBrowser,
     ) -> Result<(), String> {
-        let entries = FileEntry::recursive_collect_all_entries_flat(path, 0);
-        let mut errors = Vec::new();
-
-        for entry in entries {
-            if !entry.is_folder && !entry.is_encrypted {
-                if let Err(e) = self.encrypt_image(&entry.path, file_browser) {
-                    errors.push(format!("Failed to encrypt {}: {}", entry.path.display(), e));
-                }
-            }
-        }
-
-        if errors.is_empty() {
-            Ok(())
-        } else {
-            Err(errors.join("\n"))
-        }
+        self.process_file(path, BatchMode::Encrypt)?;
+        file_browser.reset_cache();
+        Ok(())
     }
 
-    pub fn decrypt_folder(
+    pub fn decrypt_image(
         &mut self,
         path: &std::path::Path,
         file_browser: &mut FileBrowser,
     ) -> Result<(), String> {
-        let entries = FileEntry::recursive_collect_all_entries_flat(path, 0);
+        self.process_file(path, BatchMode::Decrypt)?;
+        file_browser.reset_cache();
+        Ok(())
+    }
+
+    /// Encrypts or decrypts a single file, following the conflict policy.
+    fn process_file(&mut self, path: &Path, mode: BatchMode) -> Result<u64, String> {
+        let batch = self.crypt_batch()?;
+        let plan = BatchPlan::new(
+            batch.clone(),
+            mode,
+            path.to_path_buf(),
+            vec![path.to_path_buf()],
+            self.conflict_policy(),
+        );
+        let file = &plan.files[0];
+        if let PlanAction::Skip(reason) = &file.action {
+            return Err(format!("Skipped: {}", reason));
+        }
+        let recorder = self
+            .history_mut()
+            .map(|history| history.begin(plan.title()));
+        batch.run(file, recorder.as_ref())
+    }
+
+    /// The backdrop of the current project, ready to be painted.
+    pub fn backdrop(&self) -> Backdrop {
+        match (&self.current_folder, self.get_settings()) {
+            (Some(root), Some(settings)) => settings.backdrop.in_project(root),
+            _ => Backdrop::default(),
+        }
+    }
+
+    /// The undo journal of the current project.
+    pub fn history_mut(&mut self) -> Option<&mut History> {
+        let root = self.current_folder.clone()?;
+        let history = &mut self.settings.get_mut(&root)?.history;
+        history.set_root(&root);
+        Some(history)
+    }
+
+    /// Streams every asset under `folder` into a zip at `output`, keeping the
+    /// folder structure. Assets are converted to `mode` on the way, other
+    /// files are copied when `export_other_files` is set. Files that fail are
+    /// left out and reported once the archive is complete.
+    pub fn export_zip(
+        &self,
+        folder: &Path,
+        output: &Path,
+        mode: ZipExport,
+    ) -> Result<usize, String> {
+        let settings = self.get_settings().ok_or("No settings set")?;
+        let version = settings.rpgmaker_version;
+        let include_other = settings.export_other_files;
+        let decrypter = self.get_decrypter().ok_or("No encryption key set")?;
+
+        let mut files = vfs::walk_files(folder);
+        files.retain(|file| file != output);
+        files.sort();
+
+        let out = vfs::create(output).map_err(|e| e.to_string())?;
+        let mut zip = rpgm_enc::ZipWriter::new(BufWriter::new(out));
         let mut errors = Vec::new();
+        let mut count = 0;
+
+        for file in &files {
+            let Ok(relative) = file.strip_prefix(folder) else {
+                continue;
+            };
+            let mut name = relative.to_path_buf();
+            let ext = Self::ext_from_path(file);
+            if ext.is_none() && !include_other {
+                continue;
+            }
 
-        for entry in entries {
-            if !entry.is_folder && entry.is_encrypted {
-                if let Err(e) = self.decrypt_image(&entry.path, file_browser) {
-                    errors.push(format!("Failed to decrypt {}: {}", entry.path.display(), e));
+            let result = match ext {
+                Some(ext) if mode == ZipExport::Decrypted && ext.is_encrypted() => {
+                    name.set_extension(ext.convert(true, version).to_str());
+                    Self::export_decrypted(&mut zip, &zip_name(&name), file, decrypter, ext)
                 }
+                Some(ext)
+                    if mode == ZipExport::Encrypted
+                        && !ext.is_encrypted()
+                        && ext != rpgm_enc::FileExtension::XYZ =>
+                {
+                    name.set_extension(ext.convert(false, version).to_str());
+                    Self::export_encrypted(&mut zip, &zip_name(&name), file, decrypter)
+                }
+                _ => vfs::open(file)
+                    .and_then(|source| zip.write_entry(&zip_name(&name), source))
+                    .map(|_| ())
+                    .map_err(|e| e.to_string()),
+            };
+            match result {
+                Ok(()) => count += 1,
+                Err(e) => errors.push(format!("Failed to export {}: {}", file.display(), e)),
             }
         }
 
+        zip.finish()
+            .and_then(|mut out| out.flush())
+            .map_err(|e| e.to_string())?;
+        info!("Exported {} files to {}", count, output.display());
+
         if errors.is_empty() {
-            Ok(())
+            Ok(count)
         } else {
             Err(errors.join("\n"))
         }
     }
 
-    pub fn encrypt_image(
-        &mut self,
-        path: &std::path::Path,
-        file_browser: &mut FileBrowser,
+    fn export_decrypted(
+        zip: &mut rpgm_enc::ZipWriter<impl Write>,
+        name: &str,
+        path: &Path,
+        decrypter: &rpgm_enc::Decrypter,
+        ext: rpgm_enc::FileExtension,
     ) -> Result<(), String> {
-        let root = self.current_folder.clone().ok_or("No root folder set")?;
-        let crypt_settings = self.get_settings().ok_or("No settings set")?;
-        let rpgmaker_version = crypt_settings.rpgmaker_version;
-        let crypt_path = crypt_settings
-            .crypt_path
-            .clone()
-            .unwrap_or_else(|| root.clone());
-        let decrypter = self.get_decrypter().ok_or("No encryption key set")?;
-
-        info!("Starting encryption of file: {}", path.display());
-        let file_data = std::fs::read(path).map_err(|e| e.to_string())?;
-        info!("Read file content, size: {}", file_data.len());
+        let source = vfs::open(path).map_err(|e| e.to_string())?;
+        let mut reader = std::io::BufReader::new(
+            rpgm_enc::DecryptReader::new(source, decrypter.clone(), ext)
+                .with_verification()
+                .with_restored_header(),
+        );
+        // Check the header and key before starting the entry.
+        reader.fill_buf().map_err(Self::decryption_error)?;
+        zip.write_entry(name, reader)
+            .map_err(|e| format!("Decryption failed: {}", e))?;
+        Ok(())
+    }
 
-        let ext = Self::ext_from_path(path).ok_or("Unknown file extension")?;
+    fn export_encrypted(
+        zip: &mut rpgm_enc::ZipWriter<impl Write>,
+        name: &str,
+        path: &Path,
+        decrypter: &rpgm_enc::Decrypter,
+    ) -> Result<(), String> {
+        if vfs::metadata(path).map_err(|e| e.to_string())?.len == 0 {
+            return Err(rpgm_enc::Error::EmptyFile.to_string());
+        }
+        let mut source = vfs::open(path).map_err(|e| e.to_string())?;
+        let mut entry = zip.start_entry(name).map_err(|e| e.to_string())?;
+        let mut writer = rpgm_enc::EncryptWriter::new(&mut entry, decrypter.clone());
+        std::io::copy(&mut source, &mut writer)
+            .and_then(|_| writer.finish())
+            .map_err(|e| format!("Encryption failed: {}", e))?;
+        entry.finish().map_err(|e| e.to_string())
+    }
+}
 
-        let encrypted_data = decrypter
-            .encrypt(&file_data, ext)
-            .map_err(|e| e.to_string())?;
-        info!(
-            "Data encrypted successfully, size: {}",
-            encrypted_data.len()
-        );
+/// What encrypting and decrypting need, detached from the manager so that
+/// batches can run on worker threads.
+#[derive(Clone)]
+pub struct CryptBatch {
+    root: PathBuf,
+    crypt_path: PathBuf,
+    decrypt_path: PathBuf,
+    version: rpgm_enc::RPGMakerVersion,
+    decrypter: rpgm_enc::Decrypter,
+}
 
-        let new_ext = ext.convert(false, rpgmaker_version);
-        info!("Converted to encrypted extension: {:?}", new_ext);
-
-        let output_path = {
-            let relative_path = path
-                .strip_prefix(&root)
-                .map_err(|e| format!("Failed to get relative path: {}", e))?;
-            let mut full_path = crypt_path.join(relative_path);
-            if let Some(parent) = full_path.parent() {
-                std::fs::create_dir_all(parent)
-                    .map_err(|e| format!("Failed to create directories: {}", e))?;
+impl CryptBatch {
+    /// Where `path` goes when encrypted or decrypted.
+    pub fn output_for(&self, path: &Path, mode: BatchMode) -> Result<PathBuf, String> {
+        let ext = CryptManager::ext_from_path(path).ok_or("Unknown file extension")?;
+        let (output, new_ext) = match mode {
+            BatchMode::Encrypt => {
+                if ext == rpgm_enc::FileExtension::XYZ {
+                    return Err("XYZ images cannot be encrypted".to_string());
+                }
+                (&self.crypt_path, ext.convert(false, self.version))
+            }
+            BatchMode::Decrypt => {
+                if !ext.is_encrypted() {
+                    return Err("File is not encrypted".to_string());
+                }
+                (&self.decrypt_path, ext.convert(true, self.version))
             }
-            full_path.set_extension(new_ext.to_str());
-            info!("Final output path: {}", full_path.display());
-            full_path
         };
 
-        std::fs::write(&output_path, &encrypted_data).map_err(|e| e.to_string())?;
+        let relative_path = path
+            .strip_prefix(&self.root)
+            .map_err(|e| format!("Failed to get relative path: {}", e))?;
+        let mut full_path = output.join(relative_path);
+        full_path.set_extension(new_ext.to_str());
+        Ok(full_path)
+    }
 
-        if output_path != path {
-            let _ = std::fs::remove_file(path);
+    /// Carries out a planned file, backing up what it changes with
+    /// `recorder`. Returns the number of bytes written.
+    pub fn run(&self, file: &PlannedFile, recorder: Option<&Recorder>) -> Result<u64, String> {
+        let ext = CryptManager::ext_from_path(&file.source).ok_or("Unknown file extension")?;
+        info!("Final output path: {}", file.output.display());
+        if let Some(recorder) = recorder {
+            let backup = |path: &Path| {
+                recorder
+                    .record(path)
+                    .map_err(|e| format!("Backup failed: {}", e))
+            };
+            backup(&file.output)?;
+            if file.removes_source {
+                backup(&file.source)?;
+            }
+        }
+        let written = if ext.is_encrypted() {
+            self.decrypt(&file.source, &file.output, ext)?
+        } else {
+            self.encrypt(&file.source, &file.output, ext)?
+        };
+        if file.removes_source {
+            vfs::remove(&file.source)
+                .map_err(|e| format!("Written, but the original could not be removed: {}", e))?;
         }
+        Ok(written)
+    }
 
+    fn encrypt(
+        &self,
+        path: &Path,
+        output_path: &Path,
+        ext: rpgm_enc::FileExtension,
+    ) -> Result<u64, String> {
+        info!("Starting encryption of file: {}", path.display());
+        if vfs::metadata(path).map_err(|e| e.to_string())?.len == 0 {
+            return Err(rpgm_enc::Error::EmptyFile.to_string());
+        }
+        let encrypted_ext = CryptManager::ext_from_path(output_path).unwrap_or(ext);
+
+        let written = self.write_verified(
+            path,
+            output_path,
+            |output| {
+                let mut source = vfs::open(path).map_err(|e| e.to_string())?;
+                let mut writer = rpgm_enc::EncryptWriter::new(output, self.decrypter.clone());
+                let written = std::io::copy(&mut source, &mut writer)
+                    .map_err(|e| format!("Encryption failed: {}", e))?;
+                writer
+                    .finish()
+                    .map_err(|e| format!("Encryption failed: {}", e))?;
+                Ok(written)
+            },
+            |temp| {
+                // The output has to decrypt back to the original bytes.
+                let decrypted = rpgm_enc::DecryptReader::new(
+                    vfs::open(temp)?,
+                    self.decrypter.clone(),
+                    encrypted_ext,
+                )
+                .with_verification();
+                same_content(decrypted, vfs::open(path)?)
+            },
+        )?;
         info!(
             "Successfully wrote encrypted file to: {}",
             output_path.display()
         );
-        file_browser.reset_cache();
-        Ok(())
+        Ok(written)
     }
 
-    pub fn decrypt_image(
-        &mut self,
-        path: &std::path::Path,
-        file_browser: &mut FileBrowser,
-    ) -> Result<(), String> {
-        let root = self.current_folder.clone().ok_or("No root folder set")?;
-        let crypt_settings = self.get_settings().ok_or("No settings set")?;
-        let decrypt_path = crypt_settings
-            .decrypt_path
-            .clone()
-            .unwrap_or_else(|| root.clone());
-        let decrypter = self.get_decrypter().ok_or("No encryption key set")?;
-
-        let file_data = std::fs::read(path).map_err(|e| e.to_string())?;
+    fn decrypt(
+        &self,
+        path: &Path,
+        output_path: &Path,
+        ext: rpgm_enc::FileExtension,
+    ) -> Result<u64, String> {
+        info!("Detected file type: {:?}", ext);
+        let decrypted = || -> std::io::Result<_> {
+            Ok(std::io::BufReader::new(
+                rpgm_enc::DecryptReader::new(vfs::open(path)?, self.decrypter.clone(), ext)
+                    .with_verification()
+                    .with_restored_header(),
+            ))
+        };
+        let mut reader = decrypted().map_err(|e| e.to_string())?;
+        // Check the header and key before touching the output file.
+        reader.fill_buf().map_err(CryptManager::decryption_error)?;
+
+        let written = self.write_verified(
+            path,
+            output_path,
+            |mut output| {
+                let written = std::io::copy(&mut reader, &mut output)
+                    .map_err(|e| format!("Decryption failed: {}", e))?;
+                output.flush().map_err(|e| e.to_string())?;
+                Ok(written)
+            },
+            |temp| same_content(vfs::open(temp)?, decrypted()?),
+        )?;
         info!(
-            "Original encrypted data first 32 bytes: {:02X?}",
-            &file_data[..32.min(file_data.len())]
+            "Successfully wrote decrypted file to: {}",
+            output_path.display()
         );
+        Ok(written)
+    }
 
-        let ext = Self::ext_from_path(path).ok_or("Unknown file extension")?;
-        if !ext.is_encrypted() {
-            return Err("File is not encrypted".to_string());
+    /// Writes `output` through a temporary file that replaces it only once
+    /// `verify` accepted it, with the time and permissions of `source`.
+    fn write_verified(
+        &self,
+        source: &Path,
+        output: &Path,
+        write: impl FnOnce(Box<dyn Write + Send>) -> Result<u64, String>,
+        verify: impl FnOnce(&Path) -> std::io::Result<bool>,
+    ) -> Result<u64, String> {
+        let temp = vfs::temp_path(output);
+        let result = vfs::create(&temp)
+            .map_err(|e| e.to_string())
+            .and_then(write)
+            .and_then(|written| {
+                match verify(&temp) {
+                    Ok(true) => {}
+                    Ok(false) => return Err("Verification failed: content differs".to_string()),
+                    Err(e) => return Err(format!("Verification failed: {}", e)),
+                }
+                vfs::copy_attributes(source, &temp).map_err(|e| e.to_string())?;
+                vfs::rename(&temp, output).map_err(|e| e.to_string())?;
+                Ok(written)
+            });
+        if result.is_err() {
+            let _ = vfs::remove(&temp);
         }
-        info!("Detected file type: {:?}", ext);
+        result
+    }
+}
 
-        let decrypted_content = decrypter
-            .decrypt(&file_data, ext)
-            .map_err(|e| format!("Decryption failed: {}", e))?;
-        info!(
-            "Decrypted content first 32 bytes: {:02X?}",
-            &decrypted_content[..32.min(decrypted_content.len())]
-        );
+/// Whether both readers yield the same bytes.
+fn same_content(mut a: impl Read, mut b: impl Read) -> std::io::Result<bool> {
+    const CHUNK: u64 = 64 * 1024;
+    let (mut chunk_a, mut chunk_b) = (Vec::new(), Vec::new());
+    loop {
+        chunk_a.clear();
+        chunk_b.clear();
+        (&mut a).take(CHUNK).read_to_end(&mut chunk_a)?;
+        (&mut b).take(CHUNK).read_to_end(&mut chunk_b)?;
+        if chunk_a != chunk_b {
+            return Ok(false);
+        }
+        if chunk_a.is_empty() {
+            return Ok(true);
+        }
+    }
+}
 
-        let restored_content = decrypter
-            .restore_header(&decrypted_content, ext)
-            .map_err(|e| format!("Header restoration failed: {}", e))?;
-        info!(
-            "Restored content first 32 bytes: {:02X?}",
-            &restored_content[..32.min(restored_content.len())]
-        );
+/// `path` as a zip entry name, with `/` as separator.
+fn zip_name(path: &Path) -> String {
+    path.iter()
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
 
-        let new_ext = ext.convert(true, crypt_settings.rpgmaker_version);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rpgm_enc::{Decrypter, FileExtension, Key};
 
-        let output_path = {
-            let relative_path = path
-                .strip_prefix(&root)
-                .map_err(|e| format!("Failed to get relative path: {}", e))?;
-            let mut full_path = decrypt_path.join(relative_path);
-            if let Some(parent) = full_path.parent() {
-                std::fs::create_dir_all(parent)
-                    .map_err(|e| format!("Failed to create directories: {}", e))?;
-            }
-            full_path.set_extension(new_ext.to_str());
-            info!("Final output path: {}", full_path.display());
-            full_path
-        };
+    const KEY: &str = "0123456789abcdef0123456789abcdef";
 
-        std::fs::write(&output_path, &restored_content).map_err(|e| e.to_string())?;
-        info!(
-            "Successfully wrote decrypted file to: {}",
-            output_path.display()
-        );
-        file_browser.reset_cache();
-        Ok(())
+    fn png() -> Vec<u8> {
+        let mut data = rpgm_enc::PNG_HEADER_BYTES.to_vec();
+        data.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        data.extend((0..20_000).map(|i| i as u8));
+        data
+    }
+
+    fn encrypted() -> Vec<u8> {
+        Decrypter::new(Key::new(KEY))
+            .encrypt(&png(), FileExtension::PNG_)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_decrypt_with_key() {
+        let decrypter = Decrypter::new(Key::new(KEY));
+        let decrypted = CryptManager::decrypt_or_recover(
+            Path::new("a.png_"),
+            encrypted().as_slice(),
+            Some(&decrypter),
+            FileExtension::PNG_,
+        )
+        .unwrap();
+        assert!(!decrypted.recovered_without_key);
+        assert_eq!(decrypted.data, png());
+    }
+
+    #[test]
+    fn test_recover_with_wrong_key() {
+        let decrypter = Decrypter::new(Key::new("fedcba9876543210fedcba9876543210"));
+        let decrypted = CryptManager::decrypt_or_recover(
+            Path::new("a.png_"),
+            encrypted().as_slice(),
+            Some(&decrypter),
+            FileExtension::PNG_,
+        )
+        .unwrap();
+        assert!(decrypted.recovered_without_key);
+        assert_eq!(decrypted.data, png());
+    }
+
+    #[test]
+    fn test_recover_without_key() {
+        let decrypted = CryptManager::decrypt_or_recover(
+            Path::new("a.png_"),
+            encrypted().as_slice(),
+            None,
+            FileExtension::PNG_,
+        )
+        .unwrap();
+        assert!(decrypted.recovered_without_key);
+        assert_eq!(decrypted.data, png());
     }
 }
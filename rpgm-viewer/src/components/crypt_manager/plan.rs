@@ -0,0 +1,364 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::components::history::Recorder;
+use crate::components::jobs::Task;
+use crate::vfs;
+
+use super::CryptBatch;
+
+/// What to do when an output file already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+pub enum ConflictPolicy {
+    #[default]
+    Overwrite,
+    Skip,
+    /// Writes `name (1).ext` next to it instead.
+    Rename,
+    /// Overwrites outputs older than their source and skips the rest.
+    IfNewer,
+}
+
+impl ConflictPolicy {
+    pub const ALL: [Self; 4] = [Self::Overwrite, Self::Skip, Self::Rename, Self::IfNewer];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Overwrite => "Overwrite",
+            Self::Skip => "Skip",
+            Self::Rename => "Rename",
+            Self::IfNewer => "Only if newer",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchMode {
+    Encrypt,
+    Decrypt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlanAction {
+    Create,
+    Overwrite,
+    /// The output exists, a free name is used instead.
+    Rename,
+    Skip(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PlannedFile {
+    pub source: PathBuf,
+    pub output: PathBuf,
+    pub action: PlanAction,
+    /// The source is deleted once the output is written.
+    pub removes_source: bool,
+}
+
+/// Every file a batch would touch and what happens to it, shown before
+/// anything is written.
+pub struct BatchPlan {
+    pub mode: BatchMode,
+    pub folder: PathBuf,
+    pub policy: ConflictPolicy,
+    pub files: Vec<PlannedFile>,
+    sources: Vec<PathBuf>,
+    batch: CryptBatch,
+}
+
+impl BatchPlan {
+    pub fn new(
+        batch: CryptBatch,
+        mode: BatchMode,
+        folder: PathBuf,
+        sources: Vec<PathBuf>,
+        policy: ConflictPolicy,
+    ) -> Self {
+        let mut plan = Self {
+            mode,
+            folder,
+            policy,
+            files: Vec::new(),
+            sources,
+            batch,
+        };
+        plan.update();
+        plan
+    }
+
+    pub fn set_policy(&mut self, policy: ConflictPolicy) {
+        if policy != self.policy {
+            self.policy = policy;
+            self.update();
+        }
+    }
+
+    /// Plans every source again, as the policy or the files changed.
+    pub fn update(&mut self) {
+        let mut taken = HashSet::new();
+        self.files = self
+            .sources
+            .iter()
+            .map(|source| plan_file(&self.batch, self.mode, self.policy, source, &mut taken))
+            .collect();
+    }
+
+    pub fn title(&self) -> String {
+        let verb = match self.mode {
+            BatchMode::Encrypt => "Encrypt",
+            BatchMode::Decrypt => "Decrypt",
+        };
+        format!("{} {}", verb, self.folder.display())
+    }
+
+    pub fn count(&self, f: impl Fn(&PlanAction) -> bool) -> usize {
+        self.files.iter().filter(|file| f(&file.action)).count()
+    }
+
+    /// The files to process and the task doing it, skipped files left out.
+    /// Changes are backed up with `recorder`.
+    pub fn into_job(self, recorder: Option<Recorder>) -> (String, Vec<PathBuf>, Task) {
+        let title = self.title();
+        let files: HashMap<PathBuf, PlannedFile> = self
+            .files
+            .into_iter()
+            .filter(|file| !matches!(file.action, PlanAction::Skip(_)))
+            .map(|file| (file.source.clone(), file))
+            .collect();
+        let sources = self
+            .sources
+            .into_iter()
+            .filter(|source| files.contains_key(source))
+            .collect();
+        let batch = self.batch;
+        let task: Task = Arc::new(move |path| {
+            let file = files.get(path).ok_or("Not in the plan")?;
+            batch.run(file, recorder.as_ref())
+        });
+        (title, sources, task)
+    }
+}
+
+fn plan_file(
+    batch: &CryptBatch,
+    mode: BatchMode,
+    policy: ConflictPolicy,
+    source: &Path,
+    taken: &mut HashSet<PathBuf>,
+) -> PlannedFile {
+    let mut planned = PlannedFile {
+        source: source.to_path_buf(),
+        output: PathBuf::new(),
+        action: PlanAction::Create,
+        removes_source: false,
+    };
+    match batch.output_for(source, mode) {
+        Ok(output) => planned.output = output,
+        Err(e) => {
+            planned.action = PlanAction::Skip(e);
+            return planned;
+        }
+    }
+    planned.removes_source = mode == BatchMode::Encrypt && planned.output != source;
+
+    let exists = |path: &Path| taken.contains(path) || vfs::metadata(path).is_ok();
+    if exists(&planned.output) {
+        planned.action = match policy {
+            ConflictPolicy::Overwrite => PlanAction::Overwrite,
+            ConflictPolicy::Skip => PlanAction::Skip("Output exists".to_string()),
+            ConflictPolicy::Rename => {
+                planned.output = (1..)
+                    .map(|n| numbered(&planned.output, n))
+                    .find(|path| !exists(path))
+                    .unwrap();
+                PlanAction::Rename
+            }
+            ConflictPolicy::IfNewer => {
+                let modified = |path: &Path| vfs::metadata(path).ok().and_then(|m| m.modified);
+                match (modified(source), modified(&planned.output)) {
+                    (Some(source), Some(output)) if source <= output => {
+                        PlanAction::Skip("Output is up to date".to_string())
+                    }
+                    _ => PlanAction::Overwrite,
+                }
+            }
+        };
+    }
+    if !matches!(planned.action, PlanAction::Skip(_)) {
+        taken.insert(planned.output.clone());
+    }
+    planned
+}
+
+/// `dir/name.ext` as `dir/name (n).ext`.
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{} ({}).{}", stem, n, ext.to_string_lossy()),
+        None => format!("{} ({})", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A batch over `/memory/<name>/game`, decrypting to `/memory/<name>/out`
+    /// and encrypting in place.
+    fn batch(name: &str) -> (CryptBatch, PathBuf) {
+        let dir = Path::new(vfs::MEMORY_ROOT).join(name);
+        let root = dir.join("game");
+        let batch = CryptBatch {
+            root: root.clone(),
+            crypt_path: root,
+            decrypt_path: dir.join("out"),
+            version: rpgm_enc::RPGMakerVersion::MV,
+            decrypter: rpgm_enc::Decrypter::new(rpgm_enc::Key::new(
+                "0123456789abcdef0123456789abcdef",
+            )),
+        };
+        (batch, dir)
+    }
+
+    fn plan(
+        batch: &CryptBatch,
+        mode: BatchMode,
+        policy: ConflictPolicy,
+        source: &Path,
+    ) -> PlannedFile {
+        plan_file(batch, mode, policy, source, &mut HashSet::new())
+    }
+
+    #[test]
+    fn test_overwrite() {
+        let (batch, dir) = batch("plan-overwrite");
+        let source = dir.join("game/img/a.rpgmvp");
+        vfs::write(&source, b"source").unwrap();
+
+        let planned = plan(
+            &batch,
+            BatchMode::Decrypt,
+            ConflictPolicy::Overwrite,
+            &source,
+        );
+        assert_eq!(planned.output, dir.join("out/img/a.png"));
+        assert_eq!(planned.action, PlanAction::Create);
+
+        vfs::write(&planned.output, b"output").unwrap();
+        let planned = plan(
+            &batch,
+            BatchMode::Decrypt,
+            ConflictPolicy::Overwrite,
+            &source,
+        );
+        assert_eq!(planned.output, dir.join("out/img/a.png"));
+        assert_eq!(planned.action, PlanAction::Overwrite);
+    }
+
+    #[test]
+    fn test_skip() {
+        let (batch, dir) = batch("plan-skip");
+        let source = dir.join("game/img/a.rpgmvp");
+        vfs::write(&source, b"source").unwrap();
+        vfs::write(&dir.join("out/img/a.png"), b"output").unwrap();
+
+        let planned = plan(&batch, BatchMode::Decrypt, ConflictPolicy::Skip, &source);
+        assert_eq!(
+            planned.action,
+            PlanAction::Skip("Output exists".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rename_avoids_taken_outputs() {
+        let (batch, dir) = batch("plan-rename");
+        let sources = [dir.join("game/img/a.rpgmvp"), dir.join("game/img/a.png_")];
+        for source in &sources {
+            vfs::write(source, b"source").unwrap();
+        }
+        vfs::write(&dir.join("out/img/a.png"), b"output").unwrap();
+        vfs::write(&dir.join("out/img/a (1).png"), b"output").unwrap();
+
+        // Both sources decrypt to `a.png`, the second may not reuse the
+        // name given to the first.
+        let mut taken = HashSet::new();
+        let outputs: Vec<PlannedFile> = sources
+            .iter()
+            .map(|source| {
+                plan_file(
+                    &batch,
+                    BatchMode::Decrypt,
+                    ConflictPolicy::Rename,
+                    source,
+                    &mut taken,
+                )
+            })
+            .collect();
+        assert_eq!(outputs[0].action, PlanAction::Rename);
+        assert_eq!(outputs[0].output, dir.join("out/img/a (2).png"));
+        assert_eq!(outputs[1].action, PlanAction::Rename);
+        assert_eq!(outputs[1].output, dir.join("out/img/a (3).png"));
+    }
+
+    #[test]
+    fn test_if_newer() {
+        let (batch, dir) = batch("plan-if-newer");
+        let source = dir.join("game/img/a.rpgmvp");
+        let output = dir.join("out/img/a.png");
+
+        vfs::write(&source, b"source").unwrap();
+        vfs::write(&output, b"output").unwrap();
+        let planned = plan(&batch, BatchMode::Decrypt, ConflictPolicy::IfNewer, &source);
+        assert_eq!(
+            planned.action,
+            PlanAction::Skip("Output is up to date".to_string())
+        );
+
+        vfs::write(&source, b"changed").unwrap();
+        let planned = plan(&batch, BatchMode::Decrypt, ConflictPolicy::IfNewer, &source);
+        assert_eq!(planned.action, PlanAction::Overwrite);
+    }
+
+    #[test]
+    fn test_removes_source() {
+        let (batch, dir) = batch("plan-removes-source");
+        let image = dir.join("game/img/a.png");
+        let encrypted = dir.join("game/img/b.rpgmvp");
+        vfs::write(&image, b"image").unwrap();
+        vfs::write(&encrypted, b"encrypted").unwrap();
+
+        let planned = plan(
+            &batch,
+            BatchMode::Encrypt,
+            ConflictPolicy::Overwrite,
+            &image,
+        );
+        assert_eq!(planned.output, dir.join("game/img/a.rpgmvp"));
+        assert!(planned.removes_source);
+
+        let planned = plan(
+            &batch,
+            BatchMode::Decrypt,
+            ConflictPolicy::Overwrite,
+            &encrypted,
+        );
+        assert!(!planned.removes_source);
+
+        let planned = plan(
+            &batch,
+            BatchMode::Decrypt,
+            ConflictPolicy::Overwrite,
+            &image,
+        );
+        assert_eq!(
+            planned.action,
+            PlanAction::Skip("File is not encrypted".to_string())
+        );
+        assert!(!planned.removes_source);
+    }
+}
@@ -13,6 +13,22 @@ pub struct CryptSettings {
     pub(crate) rpgmaker_version: rpgm_enc::RPGMakerVersion,
     pub(crate) show_settings: bool,
     pub(crate) decrypter: Option<rpgm_enc::Decrypter>,
+    #[serde(default)]
+    pub(crate) system_config: Option<rpgm_enc::SystemConfig>,
+    #[serde(default)]
+    pub(crate) header_params: rpgm_enc::HeaderParams,
+    /// Whether zip exports also take files that are not images or audio.
+    #[serde(default)]
+    pub(crate) export_other_files: bool,
+    #[serde(default)]
+    pub(crate) conflict_policy: crate::components::crypt_manager::ConflictPolicy,
+    #[serde(default)]
+    pub(crate) history: crate::components::history::History,
+    /// Shown behind transparent images and thumbnails of the project.
+    #[serde(default)]
+    pub(crate) backdrop: crate::components::image_viewer::backdrop::Backdrop,
+    #[serde(skip)]
+    pub(crate) key_warning: Option<String>,
 }
 
 impl CryptSettings {
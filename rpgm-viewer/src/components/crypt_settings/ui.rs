@@ -23,6 +23,10 @@ impl CryptSettingsWindow {
                 let decrypt_path = crypt_settings.decrypt_path.clone();
                 let crypt_path = crypt_settings.crypt_path.clone();
                 let mut show_settings = crypt_settings.show_settings;
+                let key_warning = crypt_settings.key_warning.clone();
+                let system_config = crypt_settings.system_config.clone();
+                let initial_header_params = crypt_settings.header_params.clone();
+                let mut header_params = initial_header_params.clone();
 
                 let mut new_key_hex = None;
                 let mut new_decrypt_path = decrypt_path.clone();
@@ -38,6 +42,9 @@ impl CryptSettingsWindow {
                                 new_key_hex = Some(key_hex);
                             }
                         });
+                        if let Some(warning) = &key_warning {
+                            ui.colored_label(ui.visuals().warn_fg_color, warning);
+                        }
 
                         ui.separator();
 
@@ -58,6 +65,40 @@ impl CryptSettingsWindow {
                                     );
                                 });
                         });
+                        if let Some(config) = &system_config {
+                            let state = |encrypted| if encrypted { "encrypted" } else { "plain" };
+                            ui.label(format!(
+                                "System.json: images {}, audio {}",
+                                state(config.has_encrypted_images),
+                                state(config.has_encrypted_audio)
+                            ));
+                        }
+
+                        ui.separator();
+
+                        ui.label("Fake Header:");
+                        egui::Grid::new("fake_header")
+                            .num_columns(2)
+                            .show(ui, |ui| {
+                                ui.label("Length:");
+                                ui.add(egui::DragValue::new(&mut header_params.len).range(1..=64));
+                                ui.end_row();
+
+                                for (label, value) in [
+                                    ("Signature:", &mut header_params.signature),
+                                    ("Version:", &mut header_params.version),
+                                    ("Remain:", &mut header_params.remain),
+                                ] {
+                                    ui.label(label);
+                                    if ui.text_edit_singleline(value).changed() {
+                                        value.retain(|c| c.is_ascii_hexdigit());
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                        if ui.button("Reset Header").clicked() {
+                            header_params = rpgm_enc::HeaderParams::default();
+                        }
 
                         ui.separator();
 
@@ -70,6 +111,7 @@ impl CryptSettingsWindow {
                             if ui.text_edit_singleline(&mut path).changed() {
                                 new_decrypt_path = Some(PathBuf::from(path));
                             }
+                            #[cfg(not(target_arch = "wasm32"))]
                             if ui.button("Browse...").clicked() {
                                 if let Some(path) =
                                     rfd::FileDialog::new().set_directory(&root).pick_folder()
@@ -88,6 +130,7 @@ impl CryptSettingsWindow {
                             if ui.text_edit_singleline(&mut path).changed() {
                                 new_crypt_path = Some(PathBuf::from(path));
                             }
+                            #[cfg(not(target_arch = "wasm32"))]
                             if ui.button("Browse...").clicked() {
                                 if let Some(path) =
                                     rfd::FileDialog::new().set_directory(&root).pick_folder()
@@ -109,6 +152,9 @@ impl CryptSettingsWindow {
                     crypt_settings.decrypt_path = new_decrypt_path;
                     crypt_settings.crypt_path = new_crypt_path;
                 }
+                if header_params != initial_header_params {
+                    settings.update_header_params(header_params);
+                }
                 if let Some(key_hex) = new_key_hex {
                     settings.handle_key_hex_input(key_hex);
                 }
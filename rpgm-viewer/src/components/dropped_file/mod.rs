@@ -1,8 +1,17 @@
 pub mod ui;
-use std::path::PathBuf;
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+/// Files read in the browser, as name and content.
+pub(crate) type Uploads = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
 
 #[derive(Default, serde::Deserialize, serde::Serialize, Clone)]
 pub struct DroppedFile {
     #[serde(skip)]
     pending_load: Option<PathBuf>,
+    /// Files picked in the browser, waiting to be imported.
+    #[serde(skip)]
+    pub(crate) uploads: Uploads,
 }
@@ -1,10 +1,23 @@
-use log::{debug, trace};
+use std::{path::Path, sync::Arc};
+
+use log::{debug, error, info, trace};
 
 use crate::components::{
     crypt_manager::CryptManager, file_browser::FileBrowser, image_viewer::ImageViewer,
 };
 
 use super::DroppedFile;
+use crate::vfs::{
+    self,
+    archive::{self, ArchiveFs},
+};
+
+const IMAGE_EXTENSIONS: [&str; 9] = [
+    "png", "jpg", "jpeg", "gif", "bmp", "webp", "png_", "rpgmvp", "xyz",
+];
+
+/// Folder in memory collecting files dropped without a path, as in the browser.
+const DROPPED_PROJECT: &str = "Dropped Files";
 
 impl DroppedFile {
     pub fn show(
@@ -15,33 +28,111 @@ impl DroppedFile {
     ) {
         // handle pending loads from prev frame
         if let Some(path) = self.pending_load.take() {
-            if let Some(texture) = ImageViewer::load_image(&path, ctx, None) {
-                file_browser.current_image = Some((path, texture));
+            if let Some(image) =
+                ImageViewer::load_image(&path, ctx, Some(crypt_manager.decrypter_or_keyless()))
+            {
+                file_browser.current_image = Some(image);
             }
         }
 
+        let mut imports: Vec<(String, Vec<u8>)> = self.uploads.lock().unwrap().drain(..).collect();
+
+        // Browsers only hand over the files themselves, so a dropped folder
+        // would lose its structure.
+        if cfg!(target_arch = "wasm32") && ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+            Self::show_drop_hint(ctx);
+        }
+
         // Handle dnd
         if !ctx.input(|i| i.raw.dropped_files.is_empty()) {
             ctx.input(|i| {
                 for file in &i.raw.dropped_files {
                     let path = file.path();
+                    if path.as_os_str().is_empty() {
+                        if let Some(bytes) = file.bytes() {
+                            debug!("Dropped file without path: {}", file.name());
+                            imports.push((file.name().to_string(), bytes.to_vec()));
+                        }
+                        continue;
+                    }
+
                     debug!("Dropped path: {}", path.display());
-                    if path.is_dir() {
+                    if vfs::is_dir(path) {
                         trace!("Setting current directory: {}", path.display());
                         crypt_manager.set_current_directory(path.to_path_buf(), Some(file_browser));
                     } else if let Some(ext) = path.extension() {
                         trace!("Dropped file extension: {}", ext.to_string_lossy());
-                        if let Some(ext_str) = ext.to_str() {
-                            if ["png", "jpg", "jpeg", "gif", "bmp", "webp", "png_", "rpgmvp"]
-                                .contains(&ext_str.to_lowercase().as_str())
-                            {
-                                trace!("Scheduling image load for next frame");
-                                self.pending_load = Some(path.to_path_buf());
-                            }
+                        if Self::is_image(path) {
+                            trace!("Scheduling image load for next frame");
+                            self.pending_load = Some(path.to_path_buf());
                         }
                     }
                 }
             });
         }
+
+        if !imports.is_empty() {
+            self.import(imports, crypt_manager, file_browser);
+        }
+    }
+
+    fn show_drop_hint(ctx: &egui::Context) {
+        egui::Area::new(egui::Id::new("drop_hint"))
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label("Drop files, or the game folder as a .zip");
+                    ui.weak("Folders cannot be dropped in the browser");
+                });
+            });
+    }
+
+    fn is_image(path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+    }
+
+    /// Keeps files that have no path on disk in memory. Archives are opened as
+    /// a project of their own, other files are gathered in one folder.
+    fn import(
+        &mut self,
+        files: Vec<(String, Vec<u8>)>,
+        crypt_manager: &mut CryptManager,
+        file_browser: &mut FileBrowser,
+    ) {
+        let memory_root = Path::new(vfs::MEMORY_ROOT);
+        let project = memory_root.join(DROPPED_PROJECT);
+        let mut opened = None;
+
+        for (name, data) in files {
+            info!("Importing {} ({} bytes)", name, data.len());
+            if archive::has_archive_extension(Path::new(&name)) {
+                let root = memory_root.join(&name);
+                match ArchiveFs::from_bytes(root.clone(), data) {
+                    Ok(fs) => {
+                        vfs::mount(root.clone(), Arc::new(fs));
+                        opened = Some(root);
+                    }
+                    Err(e) => error!("Failed to open {}: {}", name, e),
+                }
+                continue;
+            }
+
+            let path = project.join(&name);
+            if let Err(e) = vfs::write(&path, &data) {
+                error!("Failed to import {}: {}", name, e);
+                continue;
+            }
+            if Self::is_image(&path) {
+                self.pending_load = Some(path);
+            }
+            opened.get_or_insert_with(|| project.clone());
+        }
+
+        if let Some(folder) = opened {
+            crypt_manager.set_current_directory(folder, Some(file_browser));
+        }
     }
 }
@@ -1,3 +1,5 @@
+use crate::vfs;
+
 #[derive(Default, serde::Deserialize, serde::Serialize, Clone)]
 pub struct FileEntry {
     pub path: std::path::PathBuf,
@@ -17,27 +19,15 @@ impl FileEntry {
     ) -> Vec<FileEntry> {
         let mut all_entries = Vec::new();
 
-        if let Ok(dir_entries) = std::fs::read_dir(path) {
+        if let Some(children) = Self::read_children(path) {
             let mut folders = Vec::new();
             let mut files = Vec::new();
 
-            for entry in dir_entries.filter_map(|e| e.ok()) {
-                let path = entry.path();
-                if path.is_dir() {
+            for (path, is_folder) in children {
+                if is_folder {
                     folders.push(path);
-                } else if path.is_file() {
-                    if let Some(ext) = path.extension() {
-                        if let Some(ext_str) = ext.to_str() {
-                            if [
-                                "png", "png_", "rpgmvp", "m4a", "m4a_", "rpgmvm", "ogg", "ogg_",
-                                "rpgmvo", "jpg", "jpeg", "gif", "bmp", "webp",
-                            ]
-                            .contains(&ext_str.to_lowercase().as_str())
-                            {
-                                files.push(path);
-                            }
-                        }
-                    }
+                } else if Self::is_supported_file(&path) {
+                    files.push(path);
                 }
             }
 
@@ -71,24 +61,10 @@ impl FileEntry {
     pub fn collect_entries(path: &std::path::Path) -> Vec<FileEntry> {
         let mut entries = Vec::new();
 
-        if let Ok(dir_entries) = std::fs::read_dir(path) {
-            for entry in dir_entries.filter_map(|e| e.ok()) {
-                let path = entry.path();
-                if path.is_dir() {
-                    entries.push(FileEntry::new(path, true));
-                } else if path.is_file() {
-                    if let Some(ext) = path.extension() {
-                        if let Some(ext_str) = ext.to_str() {
-                            if [
-                                "png", "png_", "rpgmvp", "m4a", "m4a_", "rpgmvm", "ogg", "ogg_",
-                                "rpgmvo", "jpg", "jpeg", "gif", "bmp", "webp",
-                            ]
-                            .contains(&ext_str.to_lowercase().as_str())
-                            {
-                                entries.push(FileEntry::new(path, false));
-                            }
-                        }
-                    }
+        if let Some(children) = Self::read_children(path) {
+            for (path, is_folder) in children {
+                if is_folder || Self::is_supported_file(&path) {
+                    entries.push(FileEntry::new(path, is_folder));
                 }
             }
         }
@@ -110,27 +86,15 @@ impl FileEntry {
     ) -> Vec<FileEntry> {
         let mut all_entries = Vec::new();
 
-        if let Ok(dir_entries) = std::fs::read_dir(path) {
+        if let Some(children) = Self::read_children(path) {
             let mut folders = Vec::new();
             let mut files = Vec::new();
 
-            for entry in dir_entries.filter_map(|e| e.ok()) {
-                let path = entry.path();
-                if path.is_dir() {
+            for (path, is_folder) in children {
+                if is_folder {
                     folders.push(path);
-                } else if path.is_file() {
-                    if let Some(ext) = path.extension() {
-                        if let Some(ext_str) = ext.to_str() {
-                            if [
-                                "png", "png_", "rpgmvp", "m4a", "m4a_", "rpgmvm", "ogg", "ogg_",
-                                "rpgmvo", "jpg", "jpeg", "gif", "bmp", "webp",
-                            ]
-                            .contains(&ext_str.to_lowercase().as_str())
-                            {
-                                files.push(path);
-                            }
-                        }
-                    }
+                } else if Self::is_supported_file(&path) {
+                    files.push(path);
                 }
             }
 
@@ -160,6 +124,30 @@ impl FileEntry {
 }
 
 impl FileEntry {
+    /// Children of a folder as `(path, is_folder)`. Archives are listed as folders.
+    fn read_children(path: &std::path::Path) -> Option<Vec<(std::path::PathBuf, bool)>> {
+        let children = vfs::list(path).ok()?;
+        Some(
+            children
+                .into_iter()
+                .filter(|(path, _)| !path.ends_with(vfs::trash::LOCAL_TRASH))
+                .map(|(path, kind)| (path, kind.is_folder()))
+                .collect(),
+        )
+    }
+
+    fn is_supported_file(path: &std::path::Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                [
+                    "png", "png_", "rpgmvp", "m4a", "m4a_", "rpgmvm", "ogg", "ogg_", "rpgmvo",
+                    "jpg", "jpeg", "gif", "bmp", "webp", "xyz",
+                ]
+                .contains(&ext.to_lowercase().as_str())
+            })
+    }
+
     pub fn new(path: std::path::PathBuf, is_folder: bool) -> Self {
         let is_encrypted = if !is_folder {
             path.extension().map_or(false, |ext| {
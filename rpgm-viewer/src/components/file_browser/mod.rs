@@ -2,8 +2,14 @@ pub mod file_entry;
 pub mod thumbnail_cache;
 pub mod ui;
 use std::path::{Path, PathBuf};
-use std::time::{Duration, SystemTime};
+use web_time::{Duration, SystemTime};
 
+use crate::components::crypt_manager::BatchPlan;
+use crate::components::image_viewer::LoadedImage;
+use crate::components::image_viewer::backdrop::Backdrop;
+use crate::components::image_viewer::preload::Preloader;
+use crate::components::jobs::JobQueue;
+use crate::components::trash::TrashView;
 use crate::components::ui_settings::UiSettings;
 use file_entry::FileEntry;
 use log::info;
@@ -15,7 +21,7 @@ pub struct FileBrowser {
     #[serde(skip)]
     search_results_cache: Option<(String, Vec<FileEntry>)>,
     #[serde(skip)]
-    pub current_image: Option<(PathBuf, egui::TextureHandle)>,
+    pub current_image: Option<LoadedImage>,
     #[serde(skip)]
     thumbnail_cache: ThumbnailCache,
     #[serde(skip)]
@@ -23,7 +29,7 @@ pub struct FileBrowser {
     #[serde(skip)]
     last_expanded_state: Vec<PathBuf>,
     #[serde(skip)]
-    last_update_time: Option<SystemTime>,
+    last_change_stamp: Option<u64>,
     #[serde(skip)]
     last_cache_check: Option<SystemTime>,
     #[serde(skip)]
@@ -34,6 +40,22 @@ pub struct FileBrowser {
     last_thumbnail_compression_size: u32,
     #[serde(skip)]
     pub show_delete_confirmation: Option<(PathBuf, bool)>,
+    #[serde(skip)]
+    pub(crate) jobs: JobQueue,
+    /// Batch waiting for confirmation in the preview dialog.
+    #[serde(skip)]
+    pub(crate) pending_plan: Option<BatchPlan>,
+    /// Folder waiting to be packed and the base key typed for it.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    pub(crate) pending_pack: Option<(PathBuf, String)>,
+    #[serde(skip)]
+    pub(crate) trash: TrashView,
+    #[serde(skip)]
+    preloader: Preloader,
+    /// Backdrop of the current project, shown behind thumbnails.
+    #[serde(skip)]
+    backdrop: Backdrop,
 }
 
 impl Default for FileBrowser {
@@ -44,7 +66,7 @@ impl Default for FileBrowser {
             search_results_cache: None,
             current_image: None,
             entries_cache: None,
-            last_update_time: None,
+            last_change_stamp: None,
             last_expanded_state: Vec::new(),
             thumbnail_cache: ThumbnailCache::new(),
             all_thumbnails_loaded: false,
@@ -52,6 +74,13 @@ impl Default for FileBrowser {
             last_thumbnail_compression_size: ui_settings.get_thumbnail_compression_size(),
             last_cache_check: None,
             show_delete_confirmation: None,
+            jobs: JobQueue::default(),
+            pending_plan: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            pending_pack: None,
+            trash: TrashView::default(),
+            preloader: Preloader::default(),
+            backdrop: Backdrop::default(),
         }
     }
 }
@@ -61,6 +90,7 @@ impl FileBrowser {
         self.entries_cache = None;
         self.search_results_cache = None;
         self.all_thumbnails_loaded = false;
+        self.preloader.clear();
     }
 
     pub fn check_and_update_cache(&mut self, root: &Path, ui_settings: &UiSettings) {
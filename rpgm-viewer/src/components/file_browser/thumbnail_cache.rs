@@ -1,13 +1,15 @@
 use log::{debug, error, info, trace};
 use std::{
     collections::{HashMap, HashSet},
+    io::Read,
     path::{Path, PathBuf},
     sync::{Arc, mpsc},
-    thread,
-    time::SystemTime,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use super::file_entry::FileEntry;
+use crate::components::{crypt_manager::CryptManager, image_viewer::ImageViewer};
+use crate::vfs;
 
 pub struct ThumbnailTask {
     pub path: PathBuf,
@@ -20,9 +22,17 @@ pub struct ThumbnailResult {
     pub texture_data: Option<(Vec<u8>, [usize; 2])>,
 }
 
+/// Thumbnails made per frame on the web, where there is no worker thread.
+#[cfg(target_arch = "wasm32")]
+const WEB_THUMBNAILS_PER_FRAME: usize = 4;
+
 struct ThreadChannels {
     sender: mpsc::Sender<ThumbnailTask>,
     receiver: mpsc::Receiver<ThumbnailResult>,
+    /// Ends of the channels the worker thread would use, drained by
+    /// `process_results` instead.
+    #[cfg(target_arch = "wasm32")]
+    worker: (mpsc::Receiver<ThumbnailTask>, mpsc::Sender<ThumbnailResult>),
 }
 
 #[derive(Default)]
@@ -37,31 +47,37 @@ pub struct ThumbnailCache {
 impl ThumbnailCache {
     pub fn new() -> Self {
         info!("Creating new ThumbnailCache");
-        let (task_tx, task_rx) = mpsc::channel();
-        let (result_tx, result_rx) = mpsc::channel();
-
-        Self::start_worker_thread(task_rx, result_tx.clone());
-
-        let channels = Arc::new(ThreadChannels {
-            sender: task_tx,
-            receiver: result_rx,
-        });
-
         Self {
             cache: HashMap::new(),
             pending_loads: HashSet::new(),
             failed_loads: HashSet::new(),
-            channels: Some(channels),
+            channels: Some(Self::spawn_channels()),
             worker_running: true,
         }
     }
 
+    fn spawn_channels() -> Arc<ThreadChannels> {
+        let (task_tx, task_rx) = mpsc::channel();
+        let (result_tx, result_rx) = mpsc::channel();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        Self::start_worker_thread(task_rx, result_tx);
+
+        Arc::new(ThreadChannels {
+            sender: task_tx,
+            receiver: result_rx,
+            #[cfg(target_arch = "wasm32")]
+            worker: (task_rx, result_tx),
+        })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
     fn start_worker_thread(
         task_rx: mpsc::Receiver<ThumbnailTask>,
         result_tx: mpsc::Sender<ThumbnailResult>,
     ) {
         info!("Starting background thread for thumbnail processing");
-        thread::spawn(move || {
+        std::thread::spawn(move || {
             debug!("Background thread started");
             while let Ok(task) = task_rx.recv() {
                 debug!("Received thumbnail processing task: {:?}", task.path);
@@ -79,55 +95,51 @@ impl ThumbnailCache {
         let path = task.path.clone();
         trace!("Processing file: {:?}", path);
 
-        let result = match std::fs::read(&task.path) {
-            Ok(file_data) => {
-                trace!("File successfully read: {} bytes", file_data.len());
-
-                let ext_str = task.path.extension().and_then(|e| e.to_str()).unwrap_or("");
-                let ext = rpgm_enc::FileExtension::from_str(ext_str);
-
-                let image_data = if let Some(ext) = ext {
-                    if ext.is_encrypted() {
-                        trace!("File is encrypted, performing decryption");
-                        match task.decrypter.decrypt(&file_data, ext) {
-                            Ok(content) => {
-                                trace!("Decryption successful: {} bytes", content.len());
-                                match task.decrypter.restore_header(&content, ext) {
-                                    Ok(restored) => restored,
-                                    Err(_) => content,
-                                }
-                            }
-                            Err(e) => {
-                                error!("Error during decryption: {:?}, {:?}", path, e);
-                                return ThumbnailResult {
-                                    path,
-                                    texture_data: None,
-                                };
-                            }
-                        }
-                    } else {
-                        file_data
-                    }
-                } else {
-                    file_data
-                };
+        let ext_str = task.path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let ext = rpgm_enc::FileExtension::from_str(ext_str);
 
-                match image::load_from_memory(&image_data) {
-                    Ok(img) => {
-                        let thumbnail = img.thumbnail(task.compression_size, task.compression_size);
-                        let image_buffer = thumbnail.to_rgb8();
-                        let dimensions = [thumbnail.width() as usize, thumbnail.height() as usize];
-                        trace!("Thumbnail created: {}x{}", dimensions[0], dimensions[1]);
-                        Some((image_buffer.as_raw().to_vec(), dimensions))
-                    }
-                    Err(e) => {
-                        error!("Error loading image: {:?}, error: {:?}", path, e);
-                        None
-                    }
+        let read_result = vfs::open(&task.path).and_then(|mut file| match ext {
+            Some(ext) if ext.is_encrypted() => {
+                trace!("File is encrypted, performing decryption");
+                let decrypted =
+                    CryptManager::decrypt_or_recover(&path, file, Some(&task.decrypter), ext)?;
+                if decrypted.recovered_without_key {
+                    debug!("Thumbnail recovered without key: {:?}", path);
                 }
+                Ok(decrypted.data)
+            }
+            _ => {
+                let mut data = Vec::new();
+                file.read_to_end(&mut data)?;
+                Ok(data)
             }
+        });
+        let image_data = match read_result {
+            Ok(data) => data,
             Err(e) => {
                 error!("Error reading file: {:?}, {:?}", path, e);
+                return ThumbnailResult {
+                    path,
+                    texture_data: None,
+                };
+            }
+        };
+        trace!("File successfully read: {} bytes", image_data.len());
+
+        let decoded = match ext {
+            Some(ext) => ImageViewer::decode_image(&image_data, ext),
+            None => image::load_from_memory(&image_data).map_err(|e| e.to_string()),
+        };
+        let result = match decoded {
+            Ok(img) => {
+                let thumbnail = img.thumbnail(task.compression_size, task.compression_size);
+                let image_buffer = thumbnail.to_rgba8();
+                let dimensions = [thumbnail.width() as usize, thumbnail.height() as usize];
+                trace!("Thumbnail created: {}x{}", dimensions[0], dimensions[1]);
+                Some((image_buffer.as_raw().to_vec(), dimensions))
+            }
+            Err(e) => {
+                error!("Error loading image: {:?}, error: {:?}", path, e);
                 None
             }
         };
@@ -141,15 +153,7 @@ impl ThumbnailCache {
     fn ensure_initialized(&mut self) {
         if self.channels.is_none() {
             info!("Initializing ThumbnailCache channels");
-            let (task_tx, task_rx) = mpsc::channel();
-            let (result_tx, result_rx) = mpsc::channel();
-
-            Self::start_worker_thread(task_rx, result_tx.clone());
-
-            self.channels = Some(Arc::new(ThreadChannels {
-                sender: task_tx,
-                receiver: result_rx,
-            }));
+            self.channels = Some(Self::spawn_channels());
             self.worker_running = true;
         }
     }
@@ -195,6 +199,17 @@ impl ThumbnailCache {
             .expect("Channels should be initialized");
         let receiver = &channels.receiver;
 
+        #[cfg(target_arch = "wasm32")]
+        {
+            let (tasks, results) = &channels.worker;
+            for task in tasks.try_iter().take(WEB_THUMBNAILS_PER_FRAME) {
+                let _ = results.send(Self::process_thumbnail_task(task));
+            }
+            if self.has_pending_loads() {
+                ctx.request_repaint();
+            }
+        }
+
         let mut results = Vec::new();
         while let Ok(result) = receiver.try_recv() {
             debug!("Received thumbnail loading result: {:?}", result.path);
@@ -209,7 +224,10 @@ impl ThumbnailCache {
                         "thumb_{}",
                         result.path.file_name().unwrap().to_string_lossy()
                     ),
-                    egui::ColorImage::from_rgb([dimensions[0], dimensions[1]], &raw_data),
+                    egui::ColorImage::from_rgba_unmultiplied(
+                        [dimensions[0], dimensions[1]],
+                        &raw_data,
+                    ),
                     egui::TextureOptions {
                         magnification: egui::TextureFilter::Linear,
                         minification: egui::TextureFilter::Linear,
@@ -217,9 +235,7 @@ impl ThumbnailCache {
                     },
                 );
 
-                let modified_time = std::fs::metadata(&result.path)
-                    .and_then(|m| m.modified())
-                    .unwrap_or(SystemTime::now());
+                let modified_time = Self::modified_time(&result.path).unwrap_or(UNIX_EPOCH);
 
                 self.insert(result.path.clone(), texture.clone(), modified_time);
                 loaded_thumbnails.push((result.path.clone(), texture));
@@ -234,7 +250,7 @@ impl ThumbnailCache {
 
     pub fn get(&mut self, path: &Path) -> Option<egui::TextureHandle> {
         if let Some((texture, modified_time)) = self.cache.get(path) {
-            if let Ok(current_modified) = std::fs::metadata(path).and_then(|m| m.modified()) {
+            if let Some(current_modified) = Self::modified_time(path) {
                 if *modified_time == current_modified {
                     return Some(texture.clone());
                 } else {
@@ -251,15 +267,13 @@ impl ThumbnailCache {
         let mut to_remove = Vec::new();
 
         for (path, (_, modified_time)) in self.cache.iter() {
-            match std::fs::metadata(path) {
-                Ok(metadata) => {
-                    if let Ok(current_modified) = metadata.modified() {
-                        if *modified_time != current_modified {
-                            to_remove.push(path.clone());
-                        }
+            match Self::modified_time(path) {
+                Some(current_modified) => {
+                    if *modified_time != current_modified {
+                        to_remove.push(path.clone());
                     }
                 }
-                Err(_) => {
+                None => {
                     to_remove.push(path.clone());
                 }
             }
@@ -285,6 +299,13 @@ impl ThumbnailCache {
         }
     }
 
+    /// Files without a modification time count as unchanged.
+    fn modified_time(path: &Path) -> Option<SystemTime> {
+        vfs::metadata(path)
+            .ok()
+            .map(|metadata| metadata.modified.unwrap_or(UNIX_EPOCH))
+    }
+
     pub fn is_pending(&self, path: &Path) -> bool {
         self.pending_loads.contains(path)
     }
@@ -3,9 +3,12 @@ use super::file_entry::FileEntry;
 use std::path::{Path, PathBuf};
 
 use crate::components::audio::AudioState;
-use crate::components::crypt_manager::CryptManager;
+use crate::components::crypt_manager::{
+    BatchMode, ConflictPolicy, CryptManager, PlanAction, ZipExport,
+};
 use crate::components::image_viewer::ImageViewer;
 use crate::components::ui_settings::UiSettings;
+use crate::vfs;
 use log::{debug, error, info, trace};
 use rpgm_enc::Decrypter;
 
@@ -29,6 +32,11 @@ impl FileBrowser {
             self.last_thumbnail_compression_size = current_thumbnail_size;
         }
 
+        if self.jobs.update(ctx) {
+            self.reset_cache();
+        }
+        self.backdrop = crypt_manager.backdrop();
+
         ui.heading("Files");
         self.show_search_bar(ui);
         ui.separator();
@@ -41,7 +49,10 @@ impl FileBrowser {
             self.show_file_list(ui, ctx, entries, crypt_manager, ui_settings, audio);
         }
 
-        self.show_delete_confirmation_dialog(ctx);
+        self.show_delete_confirmation_dialog(ctx, crypt_manager);
+        self.show_plan_dialog(ctx, crypt_manager);
+        #[cfg(not(target_arch = "wasm32"))]
+        self.show_pack_dialog(ctx);
     }
 
     fn show_search_bar(&mut self, ui: &mut egui::Ui) -> bool {
@@ -85,13 +96,12 @@ impl FileBrowser {
             return;
         };
         let expanded_folders = crypt_settings.get_expanded_folders();
-        let dir_metadata = std::fs::metadata(root).ok().and_then(|m| m.modified().ok());
+        let change_stamp = vfs::watch(root);
 
         let needs_update = self.entries_cache.is_none()
             || self.last_expanded_state != expanded_folders
-            || self.last_update_time.map_or(true, |last| {
-                dir_metadata.map_or(true, |current| current > last)
-            });
+            || change_stamp.is_none()
+            || change_stamp != self.last_change_stamp;
 
         if needs_update {
             let mut new_entries =
@@ -99,7 +109,7 @@ impl FileBrowser {
             self.preserve_thumbnails(&mut new_entries, ui_settings);
             self.entries_cache = Some(new_entries);
             self.last_expanded_state = expanded_folders.clone();
-            self.last_update_time = dir_metadata;
+            self.last_change_stamp = change_stamp;
             self.all_thumbnails_loaded = false;
         }
     }
@@ -292,13 +302,13 @@ impl FileBrowser {
             && entry.thumbnail.is_none()
             && ui.is_rect_visible(row_response.response.rect)
         {
-            if let Some(decrypter) = crypt_manager.get_decrypter() {
-                self.thumbnail_cache.request_thumbnail(
-                    &entry.path,
-                    decrypter,
-                    ui_settings.get_thumbnail_compression_size(),
-                );
-            }
+            // Without a key the worker recovers the image without one.
+            let decrypter = crypt_manager.decrypter_or_keyless();
+            self.thumbnail_cache.request_thumbnail(
+                &entry.path,
+                &decrypter,
+                ui_settings.get_thumbnail_compression_size(),
+            );
         }
     }
 
@@ -323,6 +333,13 @@ impl FileBrowser {
         entry: &FileEntry,
         crypt_manager: &mut CryptManager,
     ) {
+        Self::show_export_menu(ui, &entry.path, crypt_manager);
+
+        if vfs::is_read_only(&entry.path) {
+            ui.label("Read-only archive");
+            return;
+        }
+
         if ui.button("Extract Key...").clicked() {
             let entries = FileEntry::collect_entries(&entry.path);
             for entry in entries {
@@ -334,22 +351,34 @@ impl FileBrowser {
             ui.close();
         }
 
-        ui.separator();
-
-        if ui.button("Encrypt All Files").clicked() {
-            match crypt_manager.encrypt_folder(&entry.path, self) {
-                Ok(_) => info!("Successfully encrypted folder: {:?}", entry.path),
-                Err(e) => error!("Failed to encrypt folder {:?}: {}", entry.path, e),
-            }
+        #[cfg(not(target_arch = "wasm32"))]
+        if ui.button("Pack Archive...").clicked() {
+            self.pending_pack = Some((entry.path.clone(), String::new()));
             ui.close();
         }
 
-        if ui.button("Decrypt All Files").clicked() {
-            match crypt_manager.decrypt_folder(&entry.path, self) {
-                Ok(_) => info!("Successfully decrypted folder: {:?}", entry.path),
-                Err(e) => error!("Failed to decrypt folder {:?}: {}", entry.path, e),
+        // The files of an archive are read-only, only the archive itself
+        // can be deleted.
+        let is_archive =
+            vfs::metadata(&entry.path).is_ok_and(|m| m.kind == vfs::EntryKind::Archive);
+        if !is_archive {
+            ui.separator();
+
+            if ui.button("Encrypt All Files...").clicked() {
+                match crypt_manager.plan_folder(&entry.path, BatchMode::Encrypt) {
+                    Ok(plan) => self.pending_plan = Some(plan),
+                    Err(e) => error!("Failed to encrypt folder {:?}: {}", entry.path, e),
+                }
+                ui.close();
+            }
+
+            if ui.button("Decrypt All Files...").clicked() {
+                match crypt_manager.plan_folder(&entry.path, BatchMode::Decrypt) {
+                    Ok(plan) => self.pending_plan = Some(plan),
+                    Err(e) => error!("Failed to decrypt folder {:?}: {}", entry.path, e),
+                }
+                ui.close();
             }
-            ui.close();
         }
 
         ui.separator();
@@ -360,20 +389,82 @@ impl FileBrowser {
         }
     }
 
+    /// Zip export of `folder`, offered on folders and the project root.
+    pub(crate) fn show_export_menu(
+        ui: &mut egui::Ui,
+        folder: &Path,
+        crypt_manager: &mut CryptManager,
+    ) {
+        ui.menu_button("Export as ZIP", |ui| {
+            if let Some(settings) = crypt_manager.get_mut_settings() {
+                ui.checkbox(&mut settings.export_other_files, "Include non-media files");
+                ui.separator();
+            }
+            let mut mode = None;
+            if ui.button("Decrypted...").clicked() {
+                mode = Some(ZipExport::Decrypted);
+            }
+            if ui.button("Encrypted...").clicked() {
+                mode = Some(ZipExport::Encrypted);
+            }
+            if let Some(mode) = mode {
+                Self::export_zip(folder, mode, crypt_manager);
+                ui.close();
+            }
+        });
+    }
+
+    /// Asks where to save the zip, or downloads it in the browser.
+    fn export_zip(folder: &Path, mode: ZipExport, crypt_manager: &CryptManager) {
+        let name = format!(
+            "{}.zip",
+            folder
+                .file_name()
+                .map_or("Export".into(), |name| name.to_string_lossy())
+        );
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let Some(output) = rfd::FileDialog::new()
+            .add_filter("Zip archive", &["zip"])
+            .set_file_name(&name)
+            .save_file()
+        else {
+            return;
+        };
+        #[cfg(target_arch = "wasm32")]
+        let output = Path::new(vfs::MEMORY_ROOT).join(".export").join(&name);
+
+        match crypt_manager.export_zip(folder, &output, mode) {
+            Ok(count) => info!("Exported {} files to {:?}", count, output),
+            Err(e) => error!("Failed to export {:?}: {}", folder, e),
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        if let Ok(data) = vfs::read(&output) {
+            if let Err(e) = crate::web::download(&name, &data) {
+                error!("Failed to download {}: {}", name, e);
+            }
+            let _ = vfs::remove(&output);
+        }
+    }
+
     fn show_file_icon(&self, ui: &mut egui::Ui, entry: &FileEntry, ui_settings: &UiSettings) {
         if ui_settings.show_thumbnails {
             if let Some(texture) = entry.thumbnail.as_ref() {
                 let display_size = ui_settings.thumbnail_size;
-                ui.add(
+                let texture_size = texture.size_vec2();
+                let size = texture_size * (display_size / texture_size.max_elem());
+                let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+                if ui.is_rect_visible(rect) {
+                    self.backdrop.paint(ui.painter(), rect);
                     egui::Image::new(texture)
-                        .fit_to_exact_size(egui::vec2(display_size, display_size))
-                        .maintain_aspect_ratio(true)
                         .texture_options(egui::TextureOptions {
                             magnification: egui::TextureFilter::Linear,
                             minification: egui::TextureFilter::Linear,
                             ..Default::default()
-                        }),
-                );
+                        })
+                        .paint_at(ui, rect);
+                }
                 return;
             }
         }
@@ -397,7 +488,7 @@ impl FileBrowser {
         path.extension().map_or(false, |ext| {
             matches!(
                 ext.to_str().unwrap_or(""),
-                "png" | "png_" | "rpgmvp" | "jpg" | "jpeg" | "gif" | "bmp" | "webp"
+                "png" | "png_" | "rpgmvp" | "jpg" | "jpeg" | "gif" | "bmp" | "webp" | "xyz"
             )
         })
     }
@@ -419,43 +510,111 @@ impl FileBrowser {
         audio: &mut AudioState,
     ) {
         if self.is_audio_file(&entry.path) {
-            if let Some(decrypter) = crypt_manager.get_decrypter() {
-                match std::fs::read(&entry.path) {
-                    Ok(raw_bytes) => {
-                        if let Err(e) = audio.play_audio(&entry.name(), &raw_bytes, decrypter) {
-                            error!("Failed to play audio file {:?}: {}", entry.path, e);
-                        }
+            match vfs::open(&entry.path) {
+                Ok(file) => {
+                    let decrypter = crypt_manager.decrypter_or_keyless();
+                    if let Err(e) = audio.play_audio(&entry.name(), file, Some(&decrypter)) {
+                        error!("Failed to play audio file {:?}: {}", entry.path, e);
                     }
-                    Err(e) => {
-                        error!("Failed to read audio file {:?}: {}", entry.path, e);
-                    }
-                }
-            } else {
-                error!(
-                    "Cannot play encrypted audio {:?}: No decryption key set",
-                    entry.path
-                );
-            }
-        } else {
-            let decrypter = crypt_manager.get_decrypter().cloned();
-            match ImageViewer::load_image(&entry.path, ctx, decrypter) {
-                Some(texture) => {
-                    self.current_image = Some((entry.path.clone(), texture));
                 }
-                None => {
-                    info!("Failed to load image, resetting to welcome screen");
-                    self.current_image = None;
+                Err(e) => {
+                    error!("Failed to read audio file {:?}: {}", entry.path, e);
                 }
             }
+        } else {
+            self.open_image(&entry.path, ctx, crypt_manager);
         }
     }
 
+    /// Shows the image at `path` and preloads the ones around it.
+    fn open_image(&mut self, path: &Path, ctx: &egui::Context, crypt_manager: &CryptManager) {
+        let decrypter = crypt_manager.decrypter_or_keyless();
+        self.current_image = self
+            .preloader
+            .take(path)
+            .or_else(|| ImageViewer::load_image(path, ctx, Some(decrypter.clone())));
+        if self.current_image.is_none() {
+            info!("Failed to load image, resetting to welcome screen");
+            return;
+        }
+
+        let images = self.listed_images();
+        if let Some(index) = images.iter().position(|image| image == path) {
+            let len = images.len();
+            let neighbours = [(index + len - 1) % len, (index + 1) % len]
+                .into_iter()
+                .filter(|&i| i != index)
+                .map(|i| images[i].clone())
+                .collect();
+            self.preloader.preload(neighbours, ctx, &decrypter);
+        }
+    }
+
+    /// Images in the list as shown, filtered by the search.
+    fn listed_images(&self) -> Vec<PathBuf> {
+        let entries = if self.search_query.is_empty() {
+            self.entries_cache.as_deref()
+        } else {
+            self.search_results_cache
+                .as_ref()
+                .map(|(_, entries)| entries.as_slice())
+        };
+        entries
+            .unwrap_or_default()
+            .iter()
+            .filter(|entry| !entry.is_folder && self.is_image_file(&entry.path))
+            .map(|entry| entry.path.clone())
+            .collect()
+    }
+
+    /// Opens the image `step` places away from the current one in the list.
+    /// Past either end it wraps around with `wrap`, otherwise nothing happens.
+    pub fn show_neighbour(
+        &mut self,
+        ctx: &egui::Context,
+        crypt_manager: &CryptManager,
+        step: isize,
+        wrap: bool,
+    ) -> bool {
+        let Some(current) = self.current_image.as_ref().map(|image| image.path.clone()) else {
+            return false;
+        };
+        let images = self.listed_images();
+        let Some(index) = images.iter().position(|image| *image == current) else {
+            return false;
+        };
+        let target = index as isize + step;
+        let target = if wrap {
+            target.rem_euclid(images.len() as isize)
+        } else if (0..images.len() as isize).contains(&target) {
+            target
+        } else {
+            return false;
+        };
+        let path = images[target as usize].clone();
+        self.open_image(&path, ctx, crypt_manager);
+        true
+    }
+
     fn show_file_context_menu(
         &mut self,
         ui: &mut egui::Ui,
         entry: &FileEntry,
         crypt_manager: &mut CryptManager,
     ) {
+        #[cfg(target_arch = "wasm32")]
+        if ui.button("⬇ Download").clicked() {
+            if let Err(e) = Self::download(&entry.path, crypt_manager) {
+                error!("Failed to download {:?}: {}", entry.path, e);
+            }
+            ui.close();
+        }
+
+        if vfs::is_read_only(&entry.path) {
+            ui.label("Read-only archive");
+            return;
+        }
+
         let can_extract_key = entry.path.extension().map_or(false, |ext| {
             matches!(
                 ext.to_str().unwrap_or(""),
@@ -480,13 +639,13 @@ impl FileBrowser {
                 }
                 ui.close();
             }
-        } else {
-            if ui.button("Encrypt").clicked() {
-                if let Err(e) = crypt_manager.encrypt_image(&entry.path, self) {
-                    error!("Failed to encrypt {:?}: {}", entry.path, e);
-                }
-                ui.close();
+        } else if CryptManager::ext_from_path(&entry.path) != Some(rpgm_enc::FileExtension::XYZ)
+            && ui.button("Encrypt").clicked()
+        {
+            if let Err(e) = crypt_manager.encrypt_image(&entry.path, self) {
+                error!("Failed to encrypt {:?}: {}", entry.path, e);
             }
+            ui.close();
         }
 
         ui.separator();
@@ -497,7 +656,215 @@ impl FileBrowser {
         }
     }
 
-    fn show_delete_confirmation_dialog(&mut self, ctx: &egui::Context) {
+    /// Saves the file through the browser, decrypted when it is encrypted.
+    #[cfg(target_arch = "wasm32")]
+    fn download(path: &Path, crypt_manager: &CryptManager) -> Result<(), String> {
+        let mut name = PathBuf::from(path.file_name().ok_or("No file name")?);
+        let mut data = vfs::read(path).map_err(|e| e.to_string())?;
+
+        if let Some(ext) = CryptManager::ext_from_path(path).filter(|ext| ext.is_encrypted()) {
+            let decrypter = crypt_manager.decrypter_or_keyless();
+            data = CryptManager::decrypt_or_recover(path, data.as_slice(), Some(&decrypter), ext)
+                .map_err(|e| e.to_string())?
+                .data;
+            let version = crypt_manager
+                .get_settings()
+                .map(|settings| settings.rpgmaker_version)
+                .unwrap_or_default();
+            name.set_extension(ext.convert(true, version).to_str());
+        }
+
+        crate::web::download(&name.to_string_lossy(), &data)
+    }
+
+    /// Lists what a batch would do and runs it once confirmed.
+    fn show_plan_dialog(&mut self, ctx: &egui::Context, crypt_manager: &mut CryptManager) {
+        let Some(plan) = &mut self.pending_plan else {
+            return;
+        };
+        let mut open = true;
+        let mut run = false;
+        let mut cancel = false;
+
+        egui::Window::new(plan.title())
+            .id(egui::Id::new("batch_plan"))
+            .open(&mut open)
+            .default_width(520.0)
+            .show(ctx, |ui| {
+                let mut policy = plan.policy;
+                ui.horizontal(|ui| {
+                    ui.label("When the output exists:");
+                    egui::ComboBox::new("conflict_policy", "")
+                        .selected_text(policy.label())
+                        .show_ui(ui, |ui| {
+                            for option in ConflictPolicy::ALL {
+                                ui.selectable_value(&mut policy, option, option.label());
+                            }
+                        });
+                });
+                if policy != plan.policy {
+                    plan.set_policy(policy);
+                    if let Some(settings) = crypt_manager.get_mut_settings() {
+                        settings.conflict_policy = policy;
+                    }
+                }
+
+                let skipped = plan.count(|a| matches!(a, PlanAction::Skip(_)));
+                ui.label(format!(
+                    "{} files: {} new, {} overwritten, {} renamed, {} skipped",
+                    plan.files.len(),
+                    plan.count(|a| *a == PlanAction::Create),
+                    plan.count(|a| *a == PlanAction::Overwrite),
+                    plan.count(|a| *a == PlanAction::Rename),
+                    skipped
+                ));
+                if plan.files.iter().any(|file| file.removes_source) {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        "⚠ Originals are deleted once encrypted",
+                    );
+                }
+                ui.separator();
+
+                let root = crypt_manager.current_folder.clone().unwrap_or_default();
+                let relative = |path: &Path| {
+                    path.strip_prefix(&root)
+                        .unwrap_or(path)
+                        .display()
+                        .to_string()
+                };
+                egui::ScrollArea::vertical()
+                    .max_height(320.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("plan_files").striped(true).show(ui, |ui| {
+                            for file in &plan.files {
+                                ui.label(relative(&file.source));
+                                match &file.action {
+                                    PlanAction::Skip(reason) => {
+                                        ui.weak(format!("skip: {}", reason));
+                                    }
+                                    action => {
+                                        let text = format!("→ {}", relative(&file.output));
+                                        if *action == PlanAction::Overwrite {
+                                            ui.colored_label(
+                                                ui.visuals().warn_fg_color,
+                                                format!("{} (overwrite)", text),
+                                            );
+                                        } else {
+                                            ui.label(text);
+                                        }
+                                    }
+                                }
+                                ui.end_row();
+                            }
+                        });
+                    });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        cancel = true;
+                    }
+                    if ui
+                        .add_enabled(skipped < plan.files.len(), egui::Button::new("Run"))
+                        .clicked()
+                    {
+                        run = true;
+                    }
+                });
+            });
+
+        if run && let Some(plan) = self.pending_plan.take() {
+            let recorder = crypt_manager
+                .history_mut()
+                .map(|history| history.begin(plan.title()));
+            let (title, files, task) = plan.into_job(recorder);
+            self.jobs.push(title, files, task);
+        } else if cancel || !open {
+            self.pending_plan = None;
+        }
+    }
+
+    /// Asks for the base key of a new archive, then where to save it. An
+    /// empty key lets the writer pick a random one.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_pack_dialog(&mut self, ctx: &egui::Context) {
+        let Some((folder, base_key)) = &mut self.pending_pack else {
+            return;
+        };
+        let seed = match base_key.trim().trim_start_matches("0x") {
+            "" => Ok(None),
+            hex => u32::from_str_radix(hex, 16).map(Some),
+        };
+        let mut open = true;
+        let mut close = false;
+
+        egui::Window::new("Pack Archive")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(folder.to_string_lossy().to_string());
+                ui.horizontal(|ui| {
+                    ui.label("Base key:");
+                    ui.add(
+                        egui::TextEdit::singleline(base_key)
+                            .hint_text("random")
+                            .desired_width(100.0),
+                    );
+                });
+                ui.weak("Hexadecimal, only used by .rgss3a archives");
+                if seed.is_err() {
+                    ui.colored_label(ui.visuals().error_fg_color, "Not a hexadecimal key");
+                }
+                ui.add_space(10.0);
+
+                ui.horizontal(|ui| {
+                    if ui.button("Cancel").clicked() {
+                        close = true;
+                    }
+                    if ui
+                        .add_enabled(seed.is_ok(), egui::Button::new("Pack..."))
+                        .clicked()
+                        && let Some(output) = rfd::FileDialog::new()
+                            .add_filter("RGSS Archive", &["rgss3a", "rgss2a", "rgssad"])
+                            .set_file_name("Game.rgss3a")
+                            .save_file()
+                    {
+                        match vfs::archive::pack(folder, &output, seed.clone().ok().flatten()) {
+                            Ok(count) => info!("Packed {} files into {:?}", count, output),
+                            Err(e) => error!("Failed to pack {:?}: {}", folder, e),
+                        }
+                        close = true;
+                    }
+                });
+            });
+
+        if close || !open {
+            self.pending_pack = None;
+        }
+    }
+
+    /// Records a deletion in the undo history. Undoing it restores the item
+    /// from the trash, so nothing is copied.
+    fn record_deletion(item: vfs::trash::TrashedItem, crypt_manager: &mut CryptManager) {
+        if let Some(history) = crypt_manager.history_mut() {
+            let name = item
+                .original
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            history
+                .begin(format!("Delete {}", name))
+                .record_trashed(item);
+        }
+    }
+
+    fn show_delete_confirmation_dialog(
+        &mut self,
+        ctx: &egui::Context,
+        crypt_manager: &mut CryptManager,
+    ) {
         let mut delete_confirmed = false;
         let mut cancel_clicked = false;
 
@@ -512,7 +879,7 @@ impl FileBrowser {
                     ui.vertical_centered(|ui| {
                         ui.heading("⚠️Warning");
                         ui.label(format!(
-                            "Are you sure you want to delete {}?",
+                            "Move {} to the trash?",
                             if is_folder {
                                 "this folder"
                             } else {
@@ -527,19 +894,17 @@ impl FileBrowser {
                                 cancel_clicked = true;
                             }
                             if ui.button("Delete").clicked() {
-                                if is_folder {
-                                    if let Err(e) = std::fs::remove_dir_all(&path) {
-                                        error!("Failed to delete folder {:?}: {}", path, e);
-                                    } else {
-                                        info!("Successfully deleted folder: {:?}", path);
+                                let root = crypt_manager.current_folder.clone();
+                                let root = root.as_deref().unwrap_or(&path);
+                                let kind = if is_folder { "folder" } else { "file" };
+                                match vfs::trash::move_to_trash(&path, root) {
+                                    Ok(item) => {
+                                        info!("Successfully deleted {}: {:?}", kind, path);
+                                        Self::record_deletion(item, crypt_manager);
                                         delete_confirmed = true;
                                     }
-                                } else {
-                                    if let Err(e) = std::fs::remove_file(&path) {
-                                        error!("Failed to delete file {:?}: {}", path, e);
-                                    } else {
-                                        info!("Successfully deleted file: {:?}", path);
-                                        delete_confirmed = true;
+                                    Err(e) => {
+                                        error!("Failed to delete {} {:?}: {}", kind, path, e);
                                     }
                                 }
                             }
@@ -557,14 +922,14 @@ impl FileBrowser {
                 if !is_folder {
                     self.thumbnail_cache.remove(path);
 
-                    if let Some((current_path, _)) = &self.current_image {
-                        if current_path == path {
+                    if let Some(image) = &self.current_image {
+                        if image.path == *path {
                             self.current_image = None;
                         }
                     }
                 } else {
-                    if let Some((current_path, _)) = &self.current_image {
-                        if current_path.starts_with(path) {
+                    if let Some(image) = &self.current_image {
+                        if image.path.starts_with(path) {
                             self.current_image = None;
                         }
                     }
@@ -572,6 +937,7 @@ impl FileBrowser {
             }
 
             self.reset_cache();
+            self.trash.refresh();
             self.show_delete_confirmation = None;
         }
     }
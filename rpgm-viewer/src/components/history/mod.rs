@@ -0,0 +1,528 @@
+pub mod ui;
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use log::{info, warn};
+
+use crate::vfs::{self, trash::TrashedItem};
+
+/// One file as it was before a batch changed it.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Change {
+    pub path: PathBuf,
+    /// Backup of the file before the change, `None` when it did not exist.
+    before: Option<PathBuf>,
+    /// Backup taken when the change was undone, used to redo it.
+    after: Option<PathBuf>,
+    bytes: u64,
+    after_bytes: u64,
+    /// Set when `path` was moved to the trash. Undo restores it from there
+    /// instead of from a backup.
+    #[serde(default)]
+    trashed: Option<TrashedItem>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Batch {
+    pub id: u64,
+    pub title: String,
+    pub time: String,
+    pub changes: Vec<Change>,
+    pub undone: bool,
+}
+
+impl Batch {
+    pub fn bytes(&self) -> u64 {
+        self.changes
+            .iter()
+            .map(|change| change.bytes + change.after_bytes)
+            .sum()
+    }
+
+    /// True when the backups needed to undo or redo the batch are all there.
+    fn has_backups(&self) -> bool {
+        self.changes.iter().all(|change| {
+            if let Some(item) = &change.trashed {
+                return self.undone || item.is_in_trash();
+            }
+            let backup = if self.undone {
+                &change.after
+            } else {
+                &change.before
+            };
+            backup
+                .as_deref()
+                .is_none_or(|backup| vfs::metadata(backup).is_ok())
+        })
+    }
+}
+
+type Incoming = Arc<Mutex<Vec<(u64, Change)>>>;
+
+/// Backs files up before a batch changes them. Clones share the batch, so
+/// the workers of a job can record concurrently.
+#[derive(Clone)]
+pub struct Recorder {
+    batch_id: u64,
+    staging: PathBuf,
+    next: Arc<AtomicUsize>,
+    incoming: Incoming,
+}
+
+impl Recorder {
+    /// Call before `path` is written or removed.
+    pub fn record(&self, path: &Path) -> io::Result<()> {
+        let index = self.next.fetch_add(1, Ordering::Relaxed);
+        let backup = self
+            .staging
+            .join(self.batch_id.to_string())
+            .join(format!("{}.before", index));
+        let (before, bytes) = snapshot(path, &backup)?;
+        self.incoming.lock().unwrap().push((
+            self.batch_id,
+            Change {
+                path: path.to_path_buf(),
+                before,
+                after: None,
+                bytes,
+                after_bytes: 0,
+                trashed: None,
+            },
+        ));
+        Ok(())
+    }
+
+    /// Call after `item` was moved to the trash, which keeps it in place of
+    /// a backup.
+    pub fn record_trashed(&self, item: TrashedItem) {
+        self.incoming.lock().unwrap().push((
+            self.batch_id,
+            Change {
+                path: item.original.clone(),
+                before: None,
+                after: None,
+                bytes: 0,
+                after_bytes: 0,
+                trashed: Some(item),
+            },
+        ));
+    }
+}
+
+/// Copies `path` to `backup`. Returns `None` when there is no such file.
+fn snapshot(path: &Path, backup: &Path) -> io::Result<(Option<PathBuf>, u64)> {
+    match vfs::metadata(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((None, 0)),
+        Err(e) => return Err(e),
+        Ok(metadata) if metadata.kind.is_folder() => {
+            return Err(io::Error::other("Folders are backed up file by file"));
+        }
+        Ok(_) => {}
+    }
+    let mut output = vfs::create(backup)?;
+    let bytes = io::copy(&mut vfs::open(path)?, &mut output)?;
+    output.flush()?;
+    Ok((Some(backup.to_path_buf()), bytes))
+}
+
+/// Puts the backup back at `path`, or removes `path` when there is none.
+fn restore(path: &Path, backup: Option<&Path>) -> io::Result<()> {
+    match backup {
+        Some(backup) => {
+            let temp = vfs::temp_path(path);
+            let mut output = vfs::create(&temp)?;
+            io::copy(&mut vfs::open(backup)?, &mut output)?;
+            output.flush()?;
+            drop(output);
+            vfs::rename(&temp, path)
+        }
+        None => match vfs::remove(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        },
+    }
+}
+
+/// Where the backups of the project at `root` go, outside of the project.
+fn staging_dir(root: &Path) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    root.hash(&mut hasher);
+    let name = format!("{:016x}", hasher.finish());
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let base = eframe::storage_dir(crate::APP_NAME)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("history");
+    #[cfg(target_arch = "wasm32")]
+    let base = Path::new(vfs::MEMORY_ROOT).join(".history");
+    base.join(name)
+}
+
+/// Journal of the file changes made in a project, with backups to undo and
+/// redo them. Saved with the project settings.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct History {
+    pub(crate) batches: Vec<Batch>,
+    next_id: u64,
+    /// Undone batches, the last one is redone first.
+    redo: Vec<u64>,
+    /// Oldest batches are dropped once their backups take more than this.
+    pub(crate) cap_mb: u64,
+    staging: PathBuf,
+    pub(crate) show_history: bool,
+    #[serde(skip)]
+    incoming: Incoming,
+    /// Set once batches without their backups were dropped this session.
+    #[serde(skip)]
+    checked: bool,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            batches: Vec::new(),
+            next_id: 0,
+            redo: Vec::new(),
+            cap_mb: 1024,
+            staging: PathBuf::new(),
+            show_history: false,
+            incoming: Incoming::default(),
+            checked: false,
+        }
+    }
+}
+
+impl History {
+    /// Places the backups of a new journal for the project at `root`.
+    pub fn set_root(&mut self, root: &Path) {
+        if self.staging.as_os_str().is_empty() {
+            self.staging = staging_dir(root);
+        }
+        if !self.checked {
+            self.checked = true;
+            self.drop_missing_backups();
+        }
+    }
+
+    /// Forgets batches whose backups are gone. The journal is saved with the
+    /// settings, but on the web the backups only live in memory, so they
+    /// are lost on reload.
+    fn drop_missing_backups(&mut self) {
+        let (kept, missing): (Vec<Batch>, Vec<Batch>) = std::mem::take(&mut self.batches)
+            .into_iter()
+            .partition(Batch::has_backups);
+        self.batches = kept;
+        for batch in missing {
+            info!(
+                "Dropped history of \"{}\", its backups are gone",
+                batch.title
+            );
+            self.redo.retain(|&id| id != batch.id);
+            self.remove_backups(batch.id);
+        }
+    }
+
+    /// Starts a batch. Changes recorded with the returned recorder belong to it.
+    pub fn begin(&mut self, title: impl Into<String>) -> Recorder {
+        self.sync();
+        let id = self.next_id;
+        self.next_id += 1;
+        self.batches.push(Batch {
+            id,
+            title: title.into(),
+            time: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            changes: Vec::new(),
+            undone: false,
+        });
+        // Redoing past a new change would overwrite it.
+        self.redo.clear();
+
+        Recorder {
+            batch_id: id,
+            staging: self.staging.clone(),
+            next: Arc::new(AtomicUsize::new(0)),
+            incoming: self.incoming.clone(),
+        }
+    }
+
+    /// Takes in the changes recorded since the last call and drops old
+    /// batches over the size cap.
+    pub fn sync(&mut self) {
+        let incoming = std::mem::take(&mut *self.incoming.lock().unwrap());
+        if incoming.is_empty() {
+            return;
+        }
+        for (id, change) in incoming {
+            if let Some(batch) = self.batches.iter_mut().find(|batch| batch.id == id) {
+                batch.changes.push(change);
+            }
+        }
+        self.enforce_cap();
+    }
+
+    /// Drops the oldest batches while the backups take more than the cap.
+    /// The newest batch is always kept.
+    fn enforce_cap(&mut self) {
+        let cap = self.cap_mb * 1024 * 1024;
+        let mut total: u64 = self.batches.iter().map(Batch::bytes).sum();
+        while total > cap && self.batches.len() > 1 {
+            let oldest = self.batches.remove(0);
+            total -= oldest.bytes();
+            self.redo.retain(|&id| id != oldest.id);
+            self.remove_backups(oldest.id);
+            info!("Dropped history of \"{}\" over the size cap", oldest.title);
+        }
+    }
+
+    fn remove_backups(&self, id: u64) {
+        let dir = self.staging.join(id.to_string());
+        if let Err(e) = vfs::remove(&dir)
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            warn!("Failed to remove backups in {:?}: {}", dir, e);
+        }
+    }
+
+    pub fn last_undoable(&self) -> Option<&Batch> {
+        self.batches.iter().rev().find(|batch| !batch.undone)
+    }
+
+    pub fn last_redoable(&self) -> Option<&Batch> {
+        let id = *self.redo.last()?;
+        self.batches.iter().find(|batch| batch.id == id)
+    }
+
+    /// Restores every file of the batch as it was before it ran.
+    pub fn undo(&mut self, id: u64) -> Result<(), String> {
+        self.sync();
+        let staging = self.staging.join(id.to_string());
+        let batch = self
+            .batches
+            .iter_mut()
+            .find(|batch| batch.id == id && !batch.undone)
+            .ok_or("Nothing to undo")?;
+
+        let mut errors = Vec::new();
+        for (i, change) in batch.changes.iter_mut().enumerate().rev() {
+            if let Some(item) = &change.trashed {
+                if let Err(e) = vfs::trash::restore(item) {
+                    errors.push(format!("{}: {}", change.path.display(), e));
+                }
+                continue;
+            }
+            let backup = staging.join(format!("{}.after", i));
+            let result = snapshot(&change.path, &backup).and_then(|(after, bytes)| {
+                change.after = after;
+                change.after_bytes = bytes;
+                restore(&change.path, change.before.as_deref())
+            });
+            if let Err(e) = result {
+                errors.push(format!("{}: {}", change.path.display(), e));
+            }
+        }
+        batch.undone = true;
+        info!("Undid \"{}\"", batch.title);
+        self.redo.push(id);
+        // The backups taken to redo count against the cap too.
+        self.enforce_cap();
+        join_errors(errors)
+    }
+
+    /// Applies an undone batch again.
+    pub fn redo(&mut self, id: u64) -> Result<(), String> {
+        self.sync();
+        let batch = self
+            .batches
+            .iter_mut()
+            .find(|batch| batch.id == id && batch.undone)
+            .ok_or("Nothing to redo")?;
+
+        let mut errors = Vec::new();
+        for change in &mut batch.changes {
+            let result = match &change.trashed {
+                Some(item) => vfs::trash::move_back(item).map(|item| change.trashed = Some(item)),
+                None => restore(&change.path, change.after.as_deref()),
+            };
+            if let Err(e) = result {
+                errors.push(format!("{}: {}", change.path.display(), e));
+            }
+        }
+        batch.undone = false;
+        info!("Redid \"{}\"", batch.title);
+        self.redo.retain(|&redo| redo != id);
+        join_errors(errors)
+    }
+
+    /// Forgets every batch and deletes the backups.
+    pub fn clear(&mut self) {
+        self.sync();
+        for batch in std::mem::take(&mut self.batches) {
+            self.remove_backups(batch.id);
+        }
+        self.redo.clear();
+    }
+
+    pub fn toggle_history(&mut self) {
+        self.show_history = !self.show_history;
+    }
+}
+
+fn join_errors(errors: Vec<String>) -> Result<(), String> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A journal keeping its backups in memory under `/memory/<name>`.
+    fn history(name: &str) -> (History, PathBuf) {
+        let dir = Path::new(vfs::MEMORY_ROOT).join(name);
+        let history = History {
+            staging: dir.join("staging"),
+            checked: true,
+            ..Default::default()
+        };
+        (history, dir.join("game"))
+    }
+
+    fn read(path: &Path) -> Vec<u8> {
+        vfs::read(path).unwrap()
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let (mut history, game) = history("history-undo");
+        let edited = game.join("a.txt");
+        let created = game.join("b.txt");
+        vfs::write(&edited, b"old").unwrap();
+
+        let recorder = history.begin("Edit");
+        recorder.record(&edited).unwrap();
+        recorder.record(&created).unwrap();
+        vfs::write(&edited, b"new").unwrap();
+        vfs::write(&created, b"created").unwrap();
+
+        let id = history.last_undoable().unwrap().id;
+        history.undo(id).unwrap();
+        assert_eq!(read(&edited), b"old");
+        assert!(vfs::metadata(&created).is_err());
+        assert!(history.last_undoable().is_none());
+        assert!(history.undo(id).is_err());
+
+        assert_eq!(history.last_redoable().unwrap().id, id);
+        history.redo(id).unwrap();
+        assert_eq!(read(&edited), b"new");
+        assert_eq!(read(&created), b"created");
+        assert!(history.last_redoable().is_none());
+        assert_eq!(history.last_undoable().unwrap().id, id);
+    }
+
+    #[test]
+    fn test_new_batch_clears_redo() {
+        let (mut history, game) = history("history-redo");
+        let path = game.join("a.txt");
+        vfs::write(&path, b"old").unwrap();
+
+        history.begin("Edit").record(&path).unwrap();
+        let id = history.last_undoable().unwrap().id;
+        history.undo(id).unwrap();
+        history.begin("Other");
+        assert!(history.last_redoable().is_none());
+    }
+
+    #[test]
+    fn test_undo_deletion_restores_from_trash() {
+        let (mut history, game) = history("history-trash");
+        let path = game.join("img/a.png");
+        vfs::write(&path, b"image").unwrap();
+
+        let item = vfs::trash::move_to_trash(&game.join("img"), &game).unwrap();
+        history.begin("Delete img").record_trashed(item);
+        history.undo(0).unwrap();
+        assert_eq!(read(&path), b"image");
+        assert!(vfs::trash::list(&game).is_empty());
+        assert!(vfs::metadata(&history.staging).is_err());
+
+        history.redo(0).unwrap();
+        assert!(vfs::metadata(&path).is_err());
+        assert_eq!(vfs::trash::list(&game).len(), 1);
+
+        // Restored from the trash window, so there is nothing left to undo.
+        vfs::trash::restore(&vfs::trash::list(&game)[0]).unwrap();
+        assert!(history.undo(0).is_err());
+    }
+
+    #[test]
+    fn test_size_cap() {
+        let (mut history, game) = history("history-cap");
+        history.cap_mb = 1;
+        let first = game.join("a.bin");
+        let second = game.join("b.bin");
+        vfs::write(&first, &vec![1; 600 * 1024]).unwrap();
+        vfs::write(&second, &vec![2; 600 * 1024]).unwrap();
+
+        history.begin("First").record(&first).unwrap();
+        history.sync();
+        assert_eq!(history.batches.len(), 1);
+
+        history.begin("Second").record(&second).unwrap();
+        history.sync();
+        assert_eq!(history.batches.len(), 1);
+        assert_eq!(history.batches[0].title, "Second");
+        assert!(vfs::metadata(&history.staging.join("0")).is_err());
+    }
+
+    #[test]
+    fn test_size_cap_counts_undo_backups() {
+        let (mut history, game) = history("history-cap-undo");
+        history.cap_mb = 1;
+        let first = game.join("a.bin");
+        let second = game.join("b.bin");
+        vfs::write(&first, &vec![1; 400 * 1024]).unwrap();
+        vfs::write(&second, &vec![2; 400 * 1024]).unwrap();
+
+        history.begin("First").record(&first).unwrap();
+        history.begin("Second").record(&second).unwrap();
+        vfs::write(&second, &vec![3; 400 * 1024]).unwrap();
+        history.sync();
+        assert_eq!(history.batches.len(), 2);
+
+        // Undoing backs up the changed file, which goes over the cap.
+        history.undo(1).unwrap();
+        assert_eq!(history.batches.len(), 1);
+        assert_eq!(history.batches[0].title, "Second");
+        history.redo(1).unwrap();
+        assert_eq!(read(&second), vec![3; 400 * 1024]);
+    }
+
+    #[test]
+    fn test_drops_batches_without_backups() {
+        let (mut history, game) = history("history-missing");
+        let path = game.join("a.txt");
+        vfs::write(&path, b"old").unwrap();
+        history.begin("Edit").record(&path).unwrap();
+        history.sync();
+
+        // As after a reload on the web, where the backups were in memory.
+        vfs::remove(&history.staging).unwrap();
+        history.checked = false;
+        history.set_root(&game);
+        assert!(history.batches.is_empty());
+        assert!(history.last_undoable().is_none());
+    }
+}
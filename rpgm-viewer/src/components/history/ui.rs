@@ -0,0 +1,80 @@
+use log::error;
+
+use super::History;
+use crate::components::file_browser::FileBrowser;
+
+pub struct HistoryWindow;
+
+impl HistoryWindow {
+    pub fn show(ctx: &egui::Context, history: &mut History, file_browser: &mut FileBrowser) {
+        let mut show_history = history.show_history;
+        let mut undo = None;
+        let mut redo = None;
+        let mut clear = false;
+
+        egui::Window::new("History")
+            .open(&mut show_history)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                ui.add(
+                    egui::Slider::new(&mut history.cap_mb, 64..=16384)
+                        .logarithmic(true)
+                        .text("Backup Limit (MB)"),
+                );
+                ui.separator();
+
+                if history.batches.is_empty() {
+                    ui.label("No changes yet");
+                    return;
+                }
+
+                egui::ScrollArea::vertical()
+                    .max_height(400.0)
+                    .show(ui, |ui| {
+                        for batch in history.batches.iter().rev() {
+                            ui.horizontal(|ui| {
+                                let title = format!(
+                                    "{}  {} ({} files)",
+                                    batch.time,
+                                    batch.title,
+                                    batch.changes.len()
+                                );
+                                if batch.undone {
+                                    ui.weak(title);
+                                    if ui.small_button("Redo").clicked() {
+                                        redo = Some(batch.id);
+                                    }
+                                } else {
+                                    ui.label(title);
+                                    if ui.small_button("Undo").clicked() {
+                                        undo = Some(batch.id);
+                                    }
+                                }
+                            });
+                        }
+                    });
+
+                ui.separator();
+                if ui.button("Clear History").clicked() {
+                    clear = true;
+                }
+            });
+        history.show_history = show_history;
+
+        let result = if let Some(id) = undo {
+            history.undo(id)
+        } else if let Some(id) = redo {
+            history.redo(id)
+        } else {
+            if clear {
+                history.clear();
+            }
+            return;
+        };
+        if let Err(e) = result {
+            error!("Some files could not be restored:\n{}", e);
+        }
+        file_browser.reset_cache();
+        file_browser.trash.refresh();
+    }
+}
@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use rpgm_enc::{FileExtension, FileType};
+
+use super::ImageViewer;
+use crate::components::crypt_manager::CryptManager;
+use crate::vfs;
+
+/// Folders holding battle backgrounds and parallaxes across RPG Maker versions.
+const BACKDROP_FOLDERS: [&str; 7] = [
+    "battlebacks1",
+    "battlebacks2",
+    "battlebacks",
+    "parallaxes",
+    "panoramas",
+    "backdrop",
+    "panorama",
+];
+
+/// Size of a checkerboard square, in points.
+const CHECKER_SIZE: f32 = 8.0;
+
+/// What transparent pixels are shown on.
+#[derive(Debug, Clone, PartialEq, Default, serde::Deserialize, serde::Serialize)]
+pub enum Backdrop {
+    #[default]
+    Checkerboard,
+    Solid([u8; 3]),
+    /// An image of the project, usually a battleback or parallax. The path
+    /// is relative to the project root while stored in its settings.
+    Image(PathBuf),
+}
+
+impl Backdrop {
+    pub fn label(&self) -> String {
+        match self {
+            Self::Checkerboard => "Checkerboard".to_string(),
+            Self::Solid(_) => "Solid Color".to_string(),
+            Self::Image(path) => path
+                .file_name()
+                .map_or_else(String::new, |name| name.to_string_lossy().into_owned()),
+        }
+    }
+
+    /// The backdrop with its image path resolved against the project `root`.
+    pub fn in_project(&self, root: &Path) -> Self {
+        match self {
+            Self::Image(path) => Self::Image(root.join(path)),
+            other => other.clone(),
+        }
+    }
+
+    fn texture_id(path: &Path) -> egui::Id {
+        egui::Id::new(("backdrop", path))
+    }
+
+    /// Loads the backdrop image once, so painting it needs no decrypter.
+    pub fn prepare(&self, ctx: &egui::Context, crypt_manager: &CryptManager) {
+        let Self::Image(path) = self else {
+            return;
+        };
+        let id = Self::texture_id(path);
+        if ctx.data(|data| data.get_temp::<Option<egui::TextureHandle>>(id).is_some()) {
+            return;
+        }
+        let decrypter = Some(crypt_manager.decrypter_or_keyless());
+        let texture = ImageViewer::load_image(path, ctx, decrypter).map(|image| image.texture);
+        if texture.is_none() {
+            warn!("Backdrop {:?} could not be loaded", path);
+        }
+        ctx.data_mut(|data| data.insert_temp(id, texture));
+    }
+
+    /// Fills `rect`. Images missing or not loaded yet show the checkerboard.
+    pub fn paint(&self, painter: &egui::Painter, rect: egui::Rect) {
+        match self {
+            Self::Checkerboard => paint_checkerboard(painter, rect),
+            Self::Solid([r, g, b]) => {
+                painter.rect_filled(rect, 0.0, egui::Color32::from_rgb(*r, *g, *b));
+            }
+            Self::Image(path) => {
+                let texture = painter
+                    .ctx()
+                    .data(|data| {
+                        data.get_temp::<Option<egui::TextureHandle>>(Self::texture_id(path))
+                    })
+                    .flatten();
+                match texture {
+                    Some(texture) => {
+                        let uv = cover_uv(texture.size_vec2(), rect.size());
+                        painter.image(texture.id(), rect, uv, egui::Color32::WHITE);
+                    }
+                    None => paint_checkerboard(painter, rect),
+                }
+            }
+        }
+    }
+}
+
+fn paint_checkerboard(painter: &egui::Painter, rect: egui::Rect) {
+    let visible = rect.intersect(painter.clip_rect());
+    if !visible.is_positive() {
+        return;
+    }
+    painter.rect_filled(visible, 0.0, egui::Color32::from_gray(204));
+
+    // Squares stay put relative to `rect`, so they move along when panning.
+    let first = ((visible.min - rect.min) / CHECKER_SIZE).floor();
+    let mut mesh = egui::Mesh::default();
+    let mut y = first.y;
+    while rect.min.y + y * CHECKER_SIZE < visible.max.y {
+        let mut x = first.x;
+        while rect.min.x + x * CHECKER_SIZE < visible.max.x {
+            if (x + y) as i64 % 2 == 1 {
+                let square = egui::Rect::from_min_size(
+                    rect.min + egui::vec2(x, y) * CHECKER_SIZE,
+                    egui::Vec2::splat(CHECKER_SIZE),
+                );
+                mesh.add_colored_rect(square.intersect(visible), egui::Color32::from_gray(153));
+            }
+            x += 1.0;
+        }
+        y += 1.0;
+    }
+    painter.add(mesh);
+}
+
+/// Texture coordinates of the centered part of an image covering `size`.
+fn cover_uv(image_size: egui::Vec2, size: egui::Vec2) -> egui::Rect {
+    let scale = (size.x / image_size.x).max(size.y / image_size.y);
+    let visible = size / (image_size * scale);
+    egui::Rect::from_center_size(egui::pos2(0.5, 0.5), visible)
+}
+
+/// Battlebacks and parallaxes of the project at `root`.
+pub fn project_images(root: &Path) -> Vec<PathBuf> {
+    let mut images: Vec<PathBuf> = vfs::walk_files(root)
+        .into_iter()
+        .filter(|path| {
+            let in_backdrop_folder = path
+                .parent()
+                .and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().to_lowercase())
+                .is_some_and(|name| BACKDROP_FOLDERS.contains(&name.as_str()));
+            let is_image = path
+                .extension()
+                .and_then(|ext| FileExtension::from_str(&ext.to_string_lossy()))
+                .is_some_and(|ext| ext.get_file_type() == FileType::Image);
+            in_backdrop_folder && is_image
+        })
+        .collect();
+    images.sort();
+    images
+}
@@ -1,47 +1,207 @@
+pub mod backdrop;
+pub mod preload;
 pub mod ui;
 
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
-use log::{debug, error, trace};
+use log::{debug, error, info, trace};
 use rpgm_enc::{Decrypter, FileExtension};
+use web_time::Instant;
+
+use crate::components::crypt_manager::CryptManager;
+use crate::vfs;
+
+/// The image shown in the viewer.
+pub struct LoadedImage {
+    pub path: PathBuf,
+    pub texture: egui::TextureHandle,
+    /// The key was unknown or wrong and the file was recovered without it.
+    pub recovered_without_key: bool,
+    /// The decoded pixels, unpremultiplied as in the file.
+    pub pixels: Arc<image::RgbaImage>,
+    /// Copy of the texture without smoothing, made once the image is zoomed in.
+    nearest: Option<egui::TextureHandle>,
+}
+
+impl LoadedImage {
+    /// The texture to draw, unfiltered when `nearest` is set.
+    pub fn texture(&mut self, ctx: &egui::Context, nearest: bool) -> &egui::TextureHandle {
+        if !nearest {
+            return &self.texture;
+        }
+        self.nearest.get_or_insert_with(|| {
+            ctx.load_texture(
+                format!("{}#nearest", self.texture.name()),
+                color_image(&self.pixels),
+                egui::TextureOptions::NEAREST,
+            )
+        })
+    }
+
+    /// Color of the pixel at `x`, `y`, unpremultiplied.
+    pub fn pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        (x < self.pixels.width() && y < self.pixels.height()).then(|| self.pixels.get_pixel(x, y).0)
+    }
+}
+
+fn color_image(pixels: &image::RgbaImage) -> egui::ColorImage {
+    let size = [pixels.width() as _, pixels.height() as _];
+    egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_flat_samples().as_slice())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Zoom {
+    /// The whole image fits the panel.
+    #[default]
+    Fit,
+    /// Screen pixels per image pixel.
+    Scale(f32),
+}
+
+/// How an image is shown. Only the view changes, never the file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImageView {
+    pub zoom: Zoom,
+    /// Offset of the image center from the panel center, in points.
+    pub pan: egui::Vec2,
+    /// Clockwise quarter turns.
+    pub rotation: u8,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Pixels picked with the measuring tool.
+    pub selection: Option<egui::Rect>,
+}
+
+/// Settings of the pixel inspector.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Inspector {
+    /// Width and height of a tile or frame, in pixels.
+    pub grid_size: [u32; 2],
+    pub show_grid: bool,
+    /// Dragging selects a rectangle instead of panning.
+    #[serde(skip)]
+    pub measuring: bool,
+}
+
+impl Default for Inspector {
+    fn default() -> Self {
+        Self {
+            grid_size: [48, 48],
+            show_grid: false,
+            measuring: false,
+        }
+    }
+}
+
+/// Steps to the next image on a timer.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Slideshow {
+    pub interval_secs: f32,
+    #[serde(skip)]
+    pub running: bool,
+    #[serde(skip)]
+    last_step: Option<Instant>,
+}
+
+impl Default for Slideshow {
+    fn default() -> Self {
+        Self {
+            interval_secs: 3.0,
+            running: false,
+            last_step: None,
+        }
+    }
+}
+
+impl Slideshow {
+    pub fn toggle(&mut self) {
+        self.running = !self.running;
+        self.restart();
+    }
+
+    /// Waits a full interval again, as the image was just changed.
+    pub fn restart(&mut self) {
+        self.last_step = Some(Instant::now());
+    }
+
+    /// Whether it is time for the next image.
+    pub fn is_due(&mut self, ctx: &egui::Context) -> bool {
+        if !self.running {
+            return false;
+        }
+        let interval = web_time::Duration::from_secs_f32(self.interval_secs.max(0.1));
+        let elapsed = self.last_step.map_or(interval, |last| last.elapsed());
+        if elapsed >= interval {
+            self.restart();
+            return true;
+        }
+        ctx.request_repaint_after(interval - elapsed);
+        false
+    }
+}
 
 #[derive(serde::Deserialize, serde::Serialize, Default)]
-pub struct ImageViewer {}
+#[serde(default)]
+pub struct ImageViewer {
+    pub inspector: Inspector,
+    pub slideshow: Slideshow,
+    /// The window is fullscreen and only the image is shown.
+    #[serde(skip)]
+    pub fullscreen: bool,
+    /// View of every image shown this session, kept while navigating.
+    #[serde(skip)]
+    views: HashMap<PathBuf, ImageView>,
+}
 
 impl ImageViewer {
+    pub fn set_fullscreen(&mut self, ctx: &egui::Context, fullscreen: bool) {
+        self.fullscreen = fullscreen;
+        ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(fullscreen));
+    }
+
+    /// Decodes formats supported by `image`, plus RPG Maker 2000/2003 XYZ.
+    pub fn decode_image(data: &[u8], ext: FileExtension) -> Result<image::DynamicImage, String> {
+        if ext != FileExtension::XYZ {
+            return image::load_from_memory(data).map_err(|e| e.to_string());
+        }
+
+        let xyz = rpgm_enc::XyzImage::decode(data).map_err(|e| e.to_string())?;
+        image::RgbaImage::from_raw(xyz.width as u32, xyz.height as u32, xyz.to_rgba())
+            .map(image::DynamicImage::ImageRgba8)
+            .ok_or_else(|| "Invalid XYZ image size".to_string())
+    }
+
     pub fn load_image(
         path: &std::path::Path,
         ctx: &egui::Context,
         decrypter: Option<Decrypter>,
-    ) -> Option<egui::TextureHandle> {
-        let file_data = std::fs::read(path).ok()?;
-
+    ) -> Option<LoadedImage> {
         let ext_str = path.extension()?.to_str()?;
         let ext = FileExtension::from_str(ext_str)?;
 
-        let decrypter = match decrypter {
-            Some(d) => d,
-            None => {
-                let key = Decrypter::detect_key(&file_data, ext)?;
-                Decrypter::new(Some(key))
-            }
-        };
-
         debug!(
             "Image state: encrypted={}, ext={:?}",
             ext.is_encrypted(),
             ext
         );
 
+        let mut recovered_without_key = false;
         let image_data = if ext.is_encrypted() {
             trace!("File is encrypted, attempting to decrypt");
-            match decrypter.decrypt(&file_data, ext) {
-                Ok(content) => {
-                    trace!("Successfully decrypted content, size: {}", content.len());
-                    match decrypter.restore_header(&content, ext) {
-                        Ok(restored) => restored,
-                        Err(_) => content,
+            let file = vfs::open(path).ok()?;
+            match CryptManager::decrypt_or_recover(path, file, decrypter.as_ref(), ext) {
+                Ok(decrypted) => {
+                    trace!(
+                        "Successfully decrypted content, size: {}",
+                        decrypted.data.len()
+                    );
+                    if decrypted.recovered_without_key {
+                        info!("Recovered without key: {}", path.display());
+                        recovered_without_key = true;
                     }
+                    decrypted.data
                 }
                 Err(e) => {
                     error!("Decryption failed: {}", e);
@@ -50,28 +210,30 @@ impl ImageViewer {
             }
         } else {
             trace!("File is not encrypted, using original content");
-            file_data
+            vfs::read(path).ok()?
         };
 
-        match image::load_from_memory(&image_data) {
+        match Self::decode_image(&image_data, ext) {
             Ok(img) => {
                 debug!(
                     "Successfully loaded image: {}x{}",
                     img.width(),
                     img.height()
                 );
-                let size = [img.width() as _, img.height() as _];
-                let image_buffer = img.to_rgba8();
-                let pixels = image_buffer.as_flat_samples();
+                let pixels = Arc::new(img.to_rgba8());
                 trace!("Loading texture");
-                Some(
-                    ctx.load_texture(
-                        path.file_name().unwrap().to_string_lossy(),
-                        egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice()),
-                        egui::TextureOptions::default(),
-                    )
-                    .clone(),
-                )
+                let texture = ctx.load_texture(
+                    path.file_name().unwrap().to_string_lossy(),
+                    color_image(&pixels),
+                    egui::TextureOptions::default(),
+                );
+                Some(LoadedImage {
+                    path: path.to_path_buf(),
+                    texture,
+                    recovered_without_key,
+                    pixels,
+                    nearest: None,
+                })
             }
             Err(e) => {
                 error!("Failed to load image: {}", e);
@@ -0,0 +1,140 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, mpsc},
+};
+
+use log::debug;
+use rpgm_enc::Decrypter;
+
+use super::{ImageViewer, LoadedImage};
+
+type Wanted = Arc<Mutex<HashSet<PathBuf>>>;
+
+struct PreloadTask {
+    path: PathBuf,
+    ctx: egui::Context,
+    decrypter: Decrypter,
+}
+
+type PreloadResult = (PathBuf, Option<LoadedImage>);
+
+/// Loads the images next to the current one ahead of time, so stepping
+/// through a folder does not wait on decryption and decoding.
+pub struct Preloader {
+    ready: HashMap<PathBuf, LoadedImage>,
+    pending: HashSet<PathBuf>,
+    /// Images still worth loading, the worker skips the others.
+    wanted: Wanted,
+    sender: mpsc::Sender<PreloadTask>,
+    receiver: mpsc::Receiver<PreloadResult>,
+    /// Ends of the channels the worker thread would use, drained by
+    /// `receive` instead.
+    #[cfg(target_arch = "wasm32")]
+    worker: (mpsc::Receiver<PreloadTask>, mpsc::Sender<PreloadResult>),
+}
+
+impl Default for Preloader {
+    fn default() -> Self {
+        let (task_tx, task_rx) = mpsc::channel();
+        let (result_tx, result_rx) = mpsc::channel();
+        let wanted = Wanted::default();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        Self::start_worker_thread(task_rx, result_tx, wanted.clone());
+
+        Self {
+            ready: HashMap::new(),
+            pending: HashSet::new(),
+            wanted,
+            sender: task_tx,
+            receiver: result_rx,
+            #[cfg(target_arch = "wasm32")]
+            worker: (task_rx, result_tx),
+        }
+    }
+}
+
+impl Preloader {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn start_worker_thread(
+        task_rx: mpsc::Receiver<PreloadTask>,
+        result_tx: mpsc::Sender<PreloadResult>,
+        wanted: Wanted,
+    ) {
+        std::thread::spawn(move || {
+            while let Ok(task) = task_rx.recv() {
+                let image = if wanted.lock().unwrap().contains(&task.path) {
+                    Self::load(&task)
+                } else {
+                    None
+                };
+                if result_tx.send((task.path, image)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    fn load(task: &PreloadTask) -> Option<LoadedImage> {
+        debug!("Preloading {:?}", task.path);
+        let image = ImageViewer::load_image(&task.path, &task.ctx, Some(task.decrypter.clone()));
+        task.ctx.request_repaint();
+        image
+    }
+
+    /// Starts loading `paths` and forgets images loaded for earlier ones.
+    pub fn preload(&mut self, paths: Vec<PathBuf>, ctx: &egui::Context, decrypter: &Decrypter) {
+        let wanted: HashSet<PathBuf> = paths.into_iter().collect();
+        self.ready.retain(|path, _| wanted.contains(path));
+        for path in &wanted {
+            if self.ready.contains_key(path) || self.pending.contains(path) {
+                continue;
+            }
+            let task = PreloadTask {
+                path: path.clone(),
+                ctx: ctx.clone(),
+                decrypter: decrypter.clone(),
+            };
+            if self.sender.send(task).is_ok() {
+                self.pending.insert(path.clone());
+            }
+        }
+        *self.wanted.lock().unwrap() = wanted;
+    }
+
+    /// Collects finished loads. On the web one image is loaded per call.
+    pub fn receive(&mut self) {
+        #[cfg(target_arch = "wasm32")]
+        {
+            let (tasks, results) = &self.worker;
+            if let Ok(task) = tasks.try_recv() {
+                let wanted = self.wanted.lock().unwrap().contains(&task.path);
+                let image = if wanted { Self::load(&task) } else { None };
+                let _ = results.send((task.path, image));
+            }
+        }
+
+        let wanted = self.wanted.lock().unwrap();
+        while let Ok((path, image)) = self.receiver.try_recv() {
+            self.pending.remove(&path);
+            if let Some(image) = image
+                && wanted.contains(&path)
+            {
+                self.ready.insert(path, image);
+            }
+        }
+    }
+
+    /// The preloaded image at `path`, if it is ready.
+    pub fn take(&mut self, path: &Path) -> Option<LoadedImage> {
+        self.receive();
+        self.ready.remove(path)
+    }
+
+    /// Drops preloaded images, as the files may have changed.
+    pub fn clear(&mut self) {
+        self.ready.clear();
+        self.wanted.lock().unwrap().clear();
+    }
+}
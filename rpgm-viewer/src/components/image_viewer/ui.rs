@@ -1,8 +1,71 @@
-use log::info;
-
 use crate::components::{crypt_manager::CryptManager, file_browser::FileBrowser};
 
-use super::ImageViewer;
+use super::backdrop::Backdrop;
+use super::{ImageView, ImageViewer, Inspector, LoadedImage, Slideshow, Zoom};
+
+/// Where the image is drawn, mapping between screen points and texture
+/// coordinates through the view's rotation and flips.
+struct Placement {
+    shown: egui::Rect,
+    image_size: egui::Vec2,
+    /// Texture coordinates of the top left corner on screen.
+    origin: egui::Pos2,
+    /// Texture coordinates along the screen's x and y axes.
+    x_axis: egui::Vec2,
+    y_axis: egui::Vec2,
+}
+
+impl Placement {
+    fn new(shown: egui::Rect, image_size: egui::Vec2, view: &ImageView) -> Self {
+        let uvs = corner_uvs(view);
+        Self {
+            shown,
+            image_size,
+            origin: uvs[0],
+            x_axis: uvs[1] - uvs[0],
+            y_axis: uvs[3] - uvs[0],
+        }
+    }
+
+    /// The image pixel at `pos`, outside of the image too.
+    fn to_pixel(&self, pos: egui::Pos2) -> egui::Pos2 {
+        let t = (pos - self.shown.min) / self.shown.size();
+        let uv = self.origin + self.x_axis * t.x + self.y_axis * t.y;
+        (uv.to_vec2() * self.image_size).to_pos2()
+    }
+
+    fn to_screen(&self, pixel: egui::Pos2) -> egui::Pos2 {
+        let uv = pixel.to_vec2() / self.image_size;
+        let d = uv - self.origin.to_vec2();
+        self.shown.min + egui::vec2(d.dot(self.x_axis), d.dot(self.y_axis)) * self.shown.size()
+    }
+
+    /// Screen rectangle covering the pixels in `rect`.
+    fn rect_to_screen(&self, rect: egui::Rect) -> egui::Rect {
+        egui::Rect::from_two_pos(self.to_screen(rect.min), self.to_screen(rect.max))
+    }
+}
+
+/// Texture coordinates shown at the top left, top right, bottom right and
+/// bottom left corners.
+fn corner_uvs(view: &ImageView) -> [egui::Pos2; 4] {
+    let uvs = [
+        egui::pos2(0.0, 0.0),
+        egui::pos2(1.0, 0.0),
+        egui::pos2(1.0, 1.0),
+        egui::pos2(0.0, 1.0),
+    ];
+    std::array::from_fn(|i| {
+        let mut corner = i;
+        if view.flip_x {
+            corner = [1, 0, 3, 2][corner];
+        }
+        if view.flip_y {
+            corner = [3, 2, 1, 0][corner];
+        }
+        uvs[(corner + 4 - view.rotation as usize) % 4]
+    })
+}
 
 impl ImageViewer {
     pub fn show(
@@ -10,67 +73,416 @@ impl ImageViewer {
         ui: &mut egui::Ui,
         crypt_manager: &mut CryptManager,
         file_browser: &mut FileBrowser,
+        backdrop: &Backdrop,
     ) {
         let ctx = ui.ctx().clone();
         egui::CentralPanel::default().show(ui, |ui| {
-            if let Some((path, texture)) = &file_browser.current_image {
-                egui::containers::Frame::new().show(ui, |ui| {
-                    ui.with_layout(
-                        egui::Layout::centered_and_justified(egui::Direction::LeftToRight),
-                        |ui| {
-                            let available_size = ui.available_size();
-                            let texture_size = texture.size_vec2();
-                            if texture_size.x <= 0.0 || texture_size.y <= 0.0 {
-                                return;
-                            }
-                            let aspect_ratio = texture_size.x / texture_size.y;
-
-                            let mut size = available_size;
-                            if size.x * texture_size.y > size.y * texture_size.x {
-                                size.x = size.y * aspect_ratio;
-                            } else {
-                                size.y = size.x / aspect_ratio;
-                            }
-
-                            ui.add(egui::Image::new(texture).fit_to_exact_size(size));
-                        },
-                    );
-                });
+            if let Some(image) = &mut file_browser.current_image {
+                if image.recovered_without_key {
+                    ui.colored_label(ui.visuals().warn_fg_color, "⚠ Recovered without key");
+                }
+                let view = self.views.entry(image.path.clone()).or_default();
+                let mut fullscreen = self.fullscreen;
+                let step = Self::show_toolbar(
+                    ui,
+                    view,
+                    &mut self.inspector,
+                    &mut self.slideshow,
+                    &mut fullscreen,
+                    image.texture.size_vec2(),
+                );
+
+                let status_height = ui.spacing().interact_size.y + ui.spacing().item_spacing.y;
+                let canvas_size = ui.available_size() - egui::vec2(0.0, status_height);
+                let hovered =
+                    Self::show_canvas(ui, canvas_size, view, &self.inspector, image, backdrop);
+                Self::show_status(ui, view, &mut self.inspector, image, hovered);
+
+                if fullscreen != self.fullscreen {
+                    self.set_fullscreen(&ctx, fullscreen);
+                }
+                if let Some(step) = step {
+                    file_browser.show_neighbour(&ctx, crypt_manager, step, false);
+                    self.slideshow.restart();
+                }
             } else {
                 ui.vertical_centered(|ui| {
                     ui.add_space(ui.available_height() * 0.4);
                     ui.heading("Welcome to Image Viewer");
                     ui.add_space(20.0);
-                    if ui.button("📁 Open Folder...").clicked() {
-                        if let Some(path) = rfd::FileDialog::new().pick_folder() {
-                            crypt_manager.set_current_directory(path, Some(file_browser));
-                        }
-                    }
-                    ui.add_space(10.0);
-                    if ui.button("🖼 Open Image...").clicked() {
-                        if let Some(path) = rfd::FileDialog::new()
-                            .add_filter(
-                                "Images",
-                                &["png", "jpg", "jpeg", "gif", "bmp", "webp", "png_", "rpgmvp"],
-                            )
-                            .pick_file()
-                        {
-                            if let Some(decrypter) = crypt_manager.get_decrypter() {
-                                match Self::load_image(&path, &ctx, Some(decrypter.clone())) {
-                                    Some(texture) => {
-                                        file_browser.current_image =
-                                            Some((path.to_path_buf(), texture));
-                                    }
-                                    None => {
-                                        info!("Failed to load image, resetting to welcome screen");
-                                        file_browser.current_image = None;
-                                    }
-                                }
-                            }
-                        }
-                    }
+                    Self::show_open_buttons(ui, &ctx, crypt_manager, file_browser);
                 });
             }
         });
     }
+
+    /// Returns how many images to step by when one of the arrows was clicked.
+    fn show_toolbar(
+        ui: &mut egui::Ui,
+        view: &mut ImageView,
+        inspector: &mut Inspector,
+        slideshow: &mut Slideshow,
+        fullscreen: &mut bool,
+        texture_size: egui::Vec2,
+    ) -> Option<isize> {
+        let mut step = None;
+        ui.horizontal(|ui| {
+            if ui.button("⏴").on_hover_text("Previous (← or K)").clicked() {
+                step = Some(-1);
+            }
+            if ui.button("⏵").on_hover_text("Next (→ or J)").clicked() {
+                step = Some(1);
+            }
+            let slideshow_label = if slideshow.running { "⏸" } else { "▶" };
+            if ui
+                .button(slideshow_label)
+                .on_hover_text("Slideshow (Space)")
+                .clicked()
+            {
+                slideshow.toggle();
+            }
+            ui.add(
+                egui::DragValue::new(&mut slideshow.interval_secs)
+                    .range(0.5..=60.0)
+                    .speed(0.1)
+                    .suffix(" s"),
+            );
+            ui.toggle_value(fullscreen, "⛶")
+                .on_hover_text("Fullscreen (F11)");
+
+            ui.separator();
+            if ui.selectable_label(view.zoom == Zoom::Fit, "Fit").clicked() {
+                view.zoom = Zoom::Fit;
+                view.pan = egui::Vec2::ZERO;
+            }
+            for (label, scale) in [("1:1", 1.0), ("2x", 2.0), ("4x", 4.0)] {
+                if ui
+                    .selectable_label(view.zoom == Zoom::Scale(scale), label)
+                    .clicked()
+                {
+                    view.zoom = Zoom::Scale(scale);
+                    view.pan = egui::Vec2::ZERO;
+                }
+            }
+            if let Zoom::Scale(scale) = view.zoom {
+                ui.label(format!("{:.0}%", scale * 100.0));
+            }
+
+            ui.separator();
+            if ui.button("⟲").on_hover_text("Rotate left").clicked() {
+                view.rotation = (view.rotation + 3) % 4;
+            }
+            if ui.button("⟳").on_hover_text("Rotate right").clicked() {
+                view.rotation = (view.rotation + 1) % 4;
+            }
+            ui.toggle_value(&mut view.flip_x, "↔")
+                .on_hover_text("Flip horizontally");
+            ui.toggle_value(&mut view.flip_y, "↕")
+                .on_hover_text("Flip vertically");
+            if ui.button("Reset").clicked() {
+                *view = ImageView::default();
+            }
+
+            ui.separator();
+            ui.toggle_value(&mut inspector.measuring, "📏 Measure")
+                .on_hover_text("Drag to select a rectangle");
+            ui.toggle_value(&mut inspector.show_grid, "# Grid");
+            ui.weak(format!("{} × {}", texture_size.x, texture_size.y));
+        });
+        step
+    }
+
+    /// Draws the image, zoomed with the mouse wheel around the cursor and
+    /// panned by dragging. Double click fits it again. Returns the pixel
+    /// under the cursor.
+    fn show_canvas(
+        ui: &mut egui::Ui,
+        size: egui::Vec2,
+        view: &mut ImageView,
+        inspector: &Inspector,
+        image: &mut LoadedImage,
+        backdrop: &Backdrop,
+    ) -> Option<[u32; 2]> {
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+        let texture_size = image.texture.size_vec2();
+        if texture_size.x <= 0.0 || texture_size.y <= 0.0 {
+            return None;
+        }
+        let mut image_size = texture_size;
+        if view.rotation % 2 == 1 {
+            image_size = egui::vec2(image_size.y, image_size.x);
+        }
+
+        let pixels_per_point = ui.ctx().pixels_per_point();
+        let fit =
+            (rect.width() / image_size.x).min(rect.height() / image_size.y) * pixels_per_point;
+        let mut scale = match view.zoom {
+            Zoom::Fit => fit,
+            Zoom::Scale(scale) => scale,
+        };
+
+        if let Some(pointer) = response.hover_pos() {
+            let (scroll, zoom) = ui.input(|i| (i.smooth_scroll_delta.y, i.zoom_delta()));
+            let factor = zoom * (scroll / 200.0).exp();
+            if factor != 1.0 {
+                let new_scale = (scale * factor).clamp(0.05, 64.0);
+                // Keeps the pixel under the cursor in place.
+                let offset = pointer - rect.center() - view.pan;
+                view.pan += offset - offset * (new_scale / scale);
+                scale = new_scale;
+                view.zoom = Zoom::Scale(scale);
+            }
+        }
+        if response.dragged() && !inspector.measuring {
+            view.pan += response.drag_delta();
+            if view.zoom == Zoom::Fit {
+                view.zoom = Zoom::Scale(scale);
+            }
+        }
+        if response.double_clicked() {
+            view.zoom = Zoom::Fit;
+            view.pan = egui::Vec2::ZERO;
+            scale = fit;
+        }
+        if view.zoom == Zoom::Fit {
+            view.pan = egui::Vec2::ZERO;
+        }
+
+        let shown = egui::Rect::from_center_size(
+            rect.center() + view.pan,
+            image_size * scale / pixels_per_point,
+        );
+        let placement = Placement::new(shown, texture_size, view);
+
+        if inspector.measuring {
+            let press_origin = ui.input(|i| i.pointer.press_origin());
+            if response.dragged()
+                && let (Some(from), Some(to)) = (press_origin, response.interact_pointer_pos())
+            {
+                view.selection = Some(pixel_rect(&placement, from, to, texture_size));
+            } else if response.clicked() {
+                view.selection = None;
+            }
+        }
+
+        // Smoothing blurs pixel art once a pixel covers several on screen.
+        let texture = image.texture(ui.ctx(), scale >= 2.0);
+        let mut mesh = egui::Mesh::with_texture(texture.id());
+        let corners = [
+            shown.left_top(),
+            shown.right_top(),
+            shown.right_bottom(),
+            shown.left_bottom(),
+        ];
+        for (pos, uv) in corners.into_iter().zip(corner_uvs(view)) {
+            mesh.vertices.push(egui::epaint::Vertex {
+                pos,
+                uv,
+                color: egui::Color32::WHITE,
+            });
+        }
+        mesh.add_triangle(0, 1, 2);
+        mesh.add_triangle(0, 2, 3);
+        let painter = ui.painter_at(rect);
+        backdrop.paint(&painter, shown);
+        painter.add(mesh);
+
+        if inspector.show_grid {
+            Self::paint_grid(&painter, &placement, inspector.grid_size, texture_size);
+        }
+        if let Some(selection) = view.selection {
+            let stroke = egui::Stroke::new(1.0, ui.visuals().selection.stroke.color);
+            painter.rect_stroke(
+                placement.rect_to_screen(selection),
+                0.0,
+                stroke,
+                egui::StrokeKind::Middle,
+            );
+        }
+
+        if response.dragged() && !inspector.measuring {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grabbing);
+        } else if response.hovered() && inspector.measuring {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Crosshair);
+        } else if response.hovered() && view.zoom != Zoom::Fit {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grab);
+        }
+
+        let pixel = placement.to_pixel(response.hover_pos()?);
+        let inside = pixel.x >= 0.0
+            && pixel.y >= 0.0
+            && pixel.x < texture_size.x
+            && pixel.y < texture_size.y;
+        inside.then_some([pixel.x as u32, pixel.y as u32])
+    }
+
+    /// Lines between tiles, left out while they would be too dense to read.
+    fn paint_grid(
+        painter: &egui::Painter,
+        placement: &Placement,
+        grid_size: [u32; 2],
+        texture_size: egui::Vec2,
+    ) {
+        let [width, height] = grid_size.map(|n| n.max(1) as f32);
+        let tile = placement.rect_to_screen(egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(width, height),
+        ));
+        if tile.width() < 4.0 || tile.height() < 4.0 {
+            return;
+        }
+        let stroke = egui::Stroke::new(1.0, egui::Color32::from_white_alpha(96));
+        let mut x = width;
+        while x < texture_size.x {
+            painter.line_segment(
+                [
+                    placement.to_screen(egui::pos2(x, 0.0)),
+                    placement.to_screen(egui::pos2(x, texture_size.y)),
+                ],
+                stroke,
+            );
+            x += width;
+        }
+        let mut y = height;
+        while y < texture_size.y {
+            painter.line_segment(
+                [
+                    placement.to_screen(egui::pos2(0.0, y)),
+                    placement.to_screen(egui::pos2(texture_size.x, y)),
+                ],
+                stroke,
+            );
+            y += height;
+        }
+    }
+
+    /// Cursor position and color, its tile, and the measured selection.
+    fn show_status(
+        ui: &mut egui::Ui,
+        view: &mut ImageView,
+        inspector: &mut Inspector,
+        image: &LoadedImage,
+        hovered: Option<[u32; 2]>,
+    ) {
+        ui.horizontal(|ui| {
+            ui.label("Tile");
+            for size in &mut inspector.grid_size {
+                ui.add(egui::DragValue::new(size).range(1..=4096).suffix(" px"));
+            }
+            ui.separator();
+
+            if let Some([x, y]) = hovered
+                && let Some([r, g, b, a]) = image.pixel(x, y)
+            {
+                ui.monospace(format!("{}, {}", x, y));
+                let (swatch, _) = ui.allocate_exact_size(
+                    egui::Vec2::splat(ui.spacing().interact_size.y * 0.6),
+                    egui::Sense::hover(),
+                );
+                ui.painter().rect_filled(
+                    swatch,
+                    2.0,
+                    egui::Color32::from_rgba_unmultiplied(r, g, b, a),
+                );
+                ui.monospace(format!(
+                    "RGBA {} {} {} {}  #{:02X}{:02X}{:02X}{:02X}",
+                    r, g, b, a, r, g, b, a
+                ));
+
+                let [width, height] = inspector.grid_size.map(|n| n.max(1));
+                let (column, row) = (x / width, y / height);
+                let columns = image.pixels.width().div_ceil(width);
+                ui.monospace(format!(
+                    "Tile {}, {} (#{})",
+                    column,
+                    row,
+                    row * columns + column
+                ));
+            }
+
+            if let Some(selection) = view.selection {
+                ui.separator();
+                let bounds = format!(
+                    "{}, {}, {}, {}",
+                    selection.min.x,
+                    selection.min.y,
+                    selection.width(),
+                    selection.height()
+                );
+                ui.monospace(format!(
+                    "Selection {}, {}  {} × {}",
+                    selection.min.x,
+                    selection.min.y,
+                    selection.width(),
+                    selection.height()
+                ));
+                if ui
+                    .small_button("📋")
+                    .on_hover_text("Copy x, y, width, height")
+                    .clicked()
+                {
+                    ui.ctx().copy_text(bounds);
+                }
+                if ui.small_button("✖").on_hover_text("Clear").clicked() {
+                    view.selection = None;
+                }
+            }
+        });
+    }
+
+    /// Browsers cannot pick folders, files are uploaded from the menu instead.
+    #[cfg(target_arch = "wasm32")]
+    fn show_open_buttons(
+        ui: &mut egui::Ui,
+        _ctx: &egui::Context,
+        _crypt_manager: &mut CryptManager,
+        _file_browser: &mut FileBrowser,
+    ) {
+        ui.label("Drop game files or a zip here, or use Menu → Upload Files...");
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_open_buttons(
+        ui: &mut egui::Ui,
+        ctx: &egui::Context,
+        crypt_manager: &mut CryptManager,
+        file_browser: &mut FileBrowser,
+    ) {
+        if ui.button("📁 Open Folder...").clicked()
+            && let Some(path) = rfd::FileDialog::new().pick_folder()
+        {
+            crypt_manager.set_current_directory(path, Some(file_browser));
+        }
+        ui.add_space(10.0);
+        if ui.button("🖼 Open Image...").clicked()
+            && let Some(path) = rfd::FileDialog::new()
+                .add_filter(
+                    "Images",
+                    &[
+                        "png", "jpg", "jpeg", "gif", "bmp", "webp", "png_", "rpgmvp", "xyz",
+                    ],
+                )
+                .pick_file()
+        {
+            let decrypter = Some(crypt_manager.decrypter_or_keyless());
+            file_browser.current_image = Self::load_image(&path, ctx, decrypter);
+            if file_browser.current_image.is_none() {
+                log::info!("Failed to load image, resetting to welcome screen");
+            }
+        }
+    }
+}
+
+/// Whole pixels covered by the drag from `from` to `to`, inside the image.
+fn pixel_rect(
+    placement: &Placement,
+    from: egui::Pos2,
+    to: egui::Pos2,
+    texture_size: egui::Vec2,
+) -> egui::Rect {
+    let bounds = egui::Rect::from_min_size(egui::Pos2::ZERO, texture_size);
+    let rect = egui::Rect::from_two_pos(placement.to_pixel(from), placement.to_pixel(to));
+    let min = bounds.clamp(rect.min.floor());
+    let max = bounds.clamp(rect.max.floor() + egui::Vec2::splat(1.0));
+    egui::Rect::from_min_max(min, max)
 }
@@ -0,0 +1,404 @@
+pub mod ui;
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+};
+use web_time::{Duration, Instant};
+
+use log::info;
+
+/// Work done on one file, returning the number of bytes processed.
+pub type Task = Arc<dyn Fn(&Path) -> Result<u64, String> + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileOutcome {
+    Done { bytes: u64 },
+    Failed(String),
+    Cancelled,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileResult {
+    pub path: PathBuf,
+    pub outcome: FileOutcome,
+}
+
+/// State shared between a job and the workers running it.
+struct Shared {
+    files: Vec<PathBuf>,
+    task: Task,
+    next: AtomicUsize,
+    done: AtomicUsize,
+    bytes: AtomicU64,
+    paused: AtomicBool,
+    cancelled: AtomicBool,
+    results: Mutex<Vec<FileResult>>,
+}
+
+impl Shared {
+    /// Processes the next file. Returns false once every file was taken.
+    fn step(&self) -> bool {
+        let Some(path) = self.files.get(self.next.fetch_add(1, Ordering::Relaxed)) else {
+            return false;
+        };
+        let outcome = if self.cancelled.load(Ordering::Relaxed) {
+            FileOutcome::Cancelled
+        } else {
+            match (self.task)(path) {
+                Ok(bytes) => {
+                    self.bytes.fetch_add(bytes, Ordering::Relaxed);
+                    FileOutcome::Done { bytes }
+                }
+                Err(e) => FileOutcome::Failed(e),
+            }
+        };
+        self.results.lock().unwrap().push(FileResult {
+            path: path.clone(),
+            outcome,
+        });
+        self.done.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    fn is_finished(&self) -> bool {
+        self.done.load(Ordering::Relaxed) >= self.files.len()
+    }
+}
+
+/// A batch of files processed in the background.
+pub struct Job {
+    pub(crate) title: String,
+    shared: Arc<Shared>,
+    started: Option<Instant>,
+    /// Time spent running so far, not counting pauses.
+    elapsed: Duration,
+    /// Since when the job has been running unpaused.
+    resumed: Option<Instant>,
+    finished: bool,
+}
+
+impl Job {
+    fn new(title: String, files: Vec<PathBuf>, task: Task) -> Self {
+        Self {
+            title,
+            shared: Arc::new(Shared {
+                files,
+                task,
+                next: AtomicUsize::new(0),
+                done: AtomicUsize::new(0),
+                bytes: AtomicU64::new(0),
+                paused: AtomicBool::new(false),
+                cancelled: AtomicBool::new(false),
+                results: Mutex::new(Vec::new()),
+            }),
+            started: None,
+            elapsed: Duration::ZERO,
+            resumed: None,
+            finished: false,
+        }
+    }
+
+    fn start(&mut self) {
+        let now = Instant::now();
+        self.started = Some(now);
+        self.resumed = (!self.is_paused()).then_some(now);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let workers = std::thread::available_parallelism()
+                .map_or(1, |n| n.get())
+                .min(self.shared.files.len())
+                .max(1);
+            for _ in 0..workers {
+                let shared = self.shared.clone();
+                std::thread::spawn(move || {
+                    loop {
+                        while shared.paused.load(Ordering::Relaxed)
+                            && !shared.cancelled.load(Ordering::Relaxed)
+                        {
+                            std::thread::sleep(std::time::Duration::from_millis(50));
+                        }
+                        if !shared.step() {
+                            break;
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.shared.files.len()
+    }
+
+    pub fn done(&self) -> usize {
+        self.shared.done.load(Ordering::Relaxed).min(self.total())
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.shared.bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started.is_some()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        if paused == self.is_paused() || self.finished {
+            return;
+        }
+        self.shared.paused.store(paused, Ordering::Relaxed);
+        if paused {
+            self.elapsed += self.resumed.take().map_or(Duration::ZERO, |t| t.elapsed());
+        } else if self.started.is_some() {
+            self.resumed = Some(Instant::now());
+        }
+    }
+
+    /// Files not started yet are skipped and reported as cancelled.
+    pub fn cancel(&mut self) {
+        self.shared.cancelled.store(true, Ordering::Relaxed);
+        self.set_paused(false);
+    }
+
+    /// Running time, not counting pauses.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed + self.resumed.map_or(Duration::ZERO, |t| t.elapsed())
+    }
+
+    /// Bytes processed per second.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed().as_secs_f64();
+        if secs > 0.0 {
+            self.bytes() as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Estimated time left, from the time per file so far.
+    pub fn eta(&self) -> Option<Duration> {
+        let done = self.done();
+        if done == 0 || self.finished {
+            return None;
+        }
+        Some(
+            self.elapsed()
+                .mul_f64((self.total() - done) as f64 / done as f64),
+        )
+    }
+
+    /// Per-file results, in the order files finished.
+    pub fn results(&self) -> Vec<FileResult> {
+        self.shared.results.lock().unwrap().clone()
+    }
+
+    fn update(&mut self) -> bool {
+        if self.finished || !self.shared.is_finished() {
+            return false;
+        }
+        self.elapsed = self.elapsed();
+        self.resumed = None;
+        self.finished = true;
+
+        let results = self.results();
+        let count = |f: fn(&FileOutcome) -> bool| results.iter().filter(|r| f(&r.outcome)).count();
+        info!(
+            "{}: {} of {} files done, {} failed",
+            self.title,
+            count(|o| matches!(o, FileOutcome::Done { .. })),
+            self.total(),
+            count(|o| matches!(o, FileOutcome::Failed(_)))
+        );
+        true
+    }
+}
+
+/// Jobs run one after another, each on a pool of worker threads. Browsers
+/// have no threads, there a few files are processed every frame instead.
+#[derive(Default)]
+pub struct JobQueue {
+    pub(crate) jobs: Vec<Job>,
+    pub(crate) show_jobs: bool,
+}
+
+impl JobQueue {
+    #[cfg(target_arch = "wasm32")]
+    const FRAME_BUDGET: Duration = Duration::from_millis(10);
+
+    /// Queues `task` over `files` and opens the jobs panel.
+    pub fn push(&mut self, title: impl Into<String>, files: Vec<PathBuf>, task: Task) {
+        let title = title.into();
+        info!("Queued {} ({} files)", title, files.len());
+        self.jobs.push(Job::new(title, files, task));
+        self.show_jobs = true;
+    }
+
+    /// Advances the queue. Returns true when a job finished, as files on
+    /// disk have changed then.
+    pub fn update(&mut self, ctx: &egui::Context) -> bool {
+        let mut finished = false;
+        for job in &mut self.jobs {
+            finished |= job.update();
+        }
+
+        if let Some(job) = self.jobs.iter_mut().find(|job| !job.finished) {
+            if !job.is_started() {
+                job.start();
+            }
+            #[cfg(target_arch = "wasm32")]
+            if !job.is_paused() {
+                let start = Instant::now();
+                while start.elapsed() < Self::FRAME_BUDGET && job.shared.step() {}
+            }
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        }
+        finished
+    }
+
+    pub fn clear_finished(&mut self) {
+        self.jobs.retain(|job| !job.finished);
+    }
+
+    pub fn toggle_jobs(&mut self) {
+        self.show_jobs = !self.show_jobs;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs;
+
+    /// Writes `count` files under `/memory/<name>`, file `i` holding `i` bytes.
+    fn files(name: &str, count: usize) -> Vec<PathBuf> {
+        let dir = Path::new(vfs::MEMORY_ROOT).join(name);
+        (1..=count)
+            .map(|i| {
+                let path = dir.join(format!("{}.txt", i));
+                vfs::write(&path, &vec![b'x'; i]).unwrap();
+                path
+            })
+            .collect()
+    }
+
+    /// Copies each file next to itself as `.out`.
+    fn copy_task() -> Task {
+        Arc::new(|path: &Path| {
+            let data = vfs::read(path).map_err(|e| e.to_string())?;
+            vfs::write(&path.with_extension("out"), &data).map_err(|e| e.to_string())?;
+            Ok(data.len() as u64)
+        })
+    }
+
+    fn run(queue: &mut JobQueue) {
+        let ctx = egui::Context::default();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !queue.update(&ctx) {
+            assert!(Instant::now() < deadline, "job did not finish");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn outputs(files: &[PathBuf]) -> usize {
+        files
+            .iter()
+            .filter(|file| vfs::metadata(&file.with_extension("out")).is_ok())
+            .count()
+    }
+
+    #[test]
+    fn test_every_file_gets_a_result() {
+        let mut files = files("jobs-results", 20);
+        let missing = Path::new(vfs::MEMORY_ROOT).join("jobs-results/missing.txt");
+        files.push(missing.clone());
+
+        let mut queue = JobQueue::default();
+        queue.push("Copy", files.clone(), copy_task());
+        run(&mut queue);
+
+        let job = &queue.jobs[0];
+        assert!(job.is_finished());
+        assert_eq!(job.done(), files.len());
+        assert_eq!(job.bytes(), (1..=20).sum::<u64>());
+
+        let mut results = job.results();
+        results.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut expected = files.clone();
+        expected.sort();
+        assert_eq!(
+            results.iter().map(|r| r.path.clone()).collect::<Vec<_>>(),
+            expected
+        );
+        for result in &results {
+            if result.path == missing {
+                assert!(matches!(result.outcome, FileOutcome::Failed(_)));
+            } else {
+                let bytes = vfs::metadata(&result.path).unwrap().len;
+                assert_eq!(result.outcome, FileOutcome::Done { bytes });
+            }
+        }
+        assert_eq!(outputs(&files), 20);
+    }
+
+    #[test]
+    fn test_cancel_reports_remaining_files() {
+        let files = files("jobs-cancel", 10);
+        let mut queue = JobQueue::default();
+        queue.push("Copy", files.clone(), copy_task());
+        // Held before the workers start, so no file is processed.
+        queue.jobs[0].set_paused(true);
+        queue.update(&egui::Context::default());
+        queue.jobs[0].cancel();
+        run(&mut queue);
+
+        let job = &queue.jobs[0];
+        assert!(job.is_cancelled());
+        assert_eq!(job.done(), files.len());
+        assert_eq!(job.results().len(), files.len());
+        assert!(
+            job.results()
+                .iter()
+                .all(|r| r.outcome == FileOutcome::Cancelled)
+        );
+        assert_eq!(outputs(&files), 0);
+    }
+
+    #[test]
+    fn test_paused_job_waits_for_resume() {
+        let files = files("jobs-pause", 10);
+        let mut queue = JobQueue::default();
+        queue.push("Copy", files.clone(), copy_task());
+        queue.jobs[0].set_paused(true);
+
+        let ctx = egui::Context::default();
+        queue.update(&ctx);
+        assert!(queue.jobs[0].is_started());
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!queue.update(&ctx));
+        assert_eq!(queue.jobs[0].done(), 0);
+        assert!(queue.jobs[0].results().is_empty());
+        assert_eq!(outputs(&files), 0);
+
+        queue.jobs[0].set_paused(false);
+        run(&mut queue);
+        assert_eq!(queue.jobs[0].done(), files.len());
+        assert_eq!(outputs(&files), files.len());
+    }
+}
@@ -0,0 +1,136 @@
+use web_time::Duration;
+
+use super::{FileOutcome, Job, JobQueue};
+
+pub struct JobsWindow;
+
+impl JobsWindow {
+    pub fn show(ctx: &egui::Context, queue: &mut JobQueue) {
+        let mut show_jobs = queue.show_jobs;
+        egui::Window::new("Jobs")
+            .open(&mut show_jobs)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                if queue.jobs.is_empty() {
+                    ui.label("No jobs");
+                    return;
+                }
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for (i, job) in queue.jobs.iter_mut().enumerate() {
+                        ui.push_id(i, |ui| Self::show_job(ui, job));
+                        ui.separator();
+                    }
+                });
+
+                if queue.jobs.iter().any(|job| job.is_finished())
+                    && ui.button("Clear Finished").clicked()
+                {
+                    queue.clear_finished();
+                }
+            });
+        queue.show_jobs = show_jobs;
+    }
+
+    fn show_job(ui: &mut egui::Ui, job: &mut Job) {
+        ui.strong(&job.title);
+
+        let total = job.total().max(1);
+        ui.add(
+            egui::ProgressBar::new(job.done() as f32 / total as f32).text(format!(
+                "{} / {} files",
+                job.done(),
+                job.total()
+            )),
+        );
+
+        if job.is_finished() {
+            Self::show_results(ui, job);
+            return;
+        }
+        if !job.is_started() {
+            ui.label("Queued");
+        } else {
+            let eta = job.eta().map_or_else(|| "-".to_string(), format_duration);
+            ui.label(format!(
+                "{}/s, {} left",
+                format_bytes(job.throughput()),
+                eta
+            ));
+        }
+
+        ui.horizontal(|ui| {
+            if job.is_cancelled() {
+                ui.label("Cancelling...");
+                return;
+            }
+            let pause_label = if job.is_paused() {
+                "▶ Resume"
+            } else {
+                "⏸ Pause"
+            };
+            if ui.button(pause_label).clicked() {
+                job.set_paused(!job.is_paused());
+            }
+            if ui.button("⏹ Cancel").clicked() {
+                job.cancel();
+            }
+        });
+    }
+
+    fn show_results(ui: &mut egui::Ui, job: &Job) {
+        let results = job.results();
+        let failed = results
+            .iter()
+            .filter(|r| matches!(r.outcome, FileOutcome::Failed(_)))
+            .count();
+        let cancelled = results
+            .iter()
+            .filter(|r| r.outcome == FileOutcome::Cancelled)
+            .count();
+        ui.label(format!(
+            "{} done, {} failed, {} cancelled in {}",
+            results.len() - failed - cancelled,
+            failed,
+            cancelled,
+            format_duration(job.elapsed())
+        ));
+
+        ui.collapsing("Results", |ui| {
+            for result in &results {
+                let name = result.path.display().to_string();
+                match &result.outcome {
+                    FileOutcome::Done { bytes } => {
+                        ui.label(format!("✔ {} ({})", name, format_bytes(*bytes as f64)));
+                    }
+                    FileOutcome::Failed(e) => {
+                        ui.colored_label(ui.visuals().error_fg_color, format!("✖ {}: {}", name, e));
+                    }
+                    FileOutcome::Cancelled => {
+                        ui.weak(format!("– {}", name));
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
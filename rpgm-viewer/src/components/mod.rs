@@ -3,6 +3,9 @@ pub mod crypt_manager;
 pub mod crypt_settings;
 pub mod dropped_file;
 pub mod file_browser;
+pub mod history;
 pub mod image_viewer;
+pub mod jobs;
 pub mod logger;
+pub mod trash;
 pub mod ui_settings;
@@ -0,0 +1,29 @@
+pub mod ui;
+
+use std::path::Path;
+
+use crate::vfs::trash::{self, TrashedItem};
+
+/// State of the "Recently Deleted" window.
+#[derive(Default)]
+pub struct TrashView {
+    pub(crate) show_trash: bool,
+    /// Read from the trash when first needed.
+    items: Option<Vec<TrashedItem>>,
+}
+
+impl TrashView {
+    pub fn items(&mut self, root: &Path) -> &[TrashedItem] {
+        self.items.get_or_insert_with(|| trash::list(root))
+    }
+
+    /// Reads the trash again next time.
+    pub fn refresh(&mut self) {
+        self.items = None;
+    }
+
+    pub fn toggle_trash(&mut self) {
+        self.show_trash = !self.show_trash;
+        self.refresh();
+    }
+}
@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use log::error;
+
+use crate::components::file_browser::FileBrowser;
+use crate::vfs::trash;
+
+pub struct TrashWindow;
+
+impl TrashWindow {
+    pub fn show(ctx: &egui::Context, root: &Path, file_browser: &mut FileBrowser) {
+        let mut show_trash = file_browser.trash.show_trash;
+        let mut restore = None;
+        let mut purge = None;
+
+        egui::Window::new("Recently Deleted")
+            .open(&mut show_trash)
+            .default_width(360.0)
+            .show(ctx, |ui| {
+                if ui.button("⟳ Refresh").clicked() {
+                    file_browser.trash.refresh();
+                }
+                ui.separator();
+
+                let items = file_browser.trash.items(root);
+                if items.is_empty() {
+                    ui.label("Nothing deleted from this project");
+                    return;
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(400.0)
+                    .show(ui, |ui| {
+                        for item in items {
+                            let relative =
+                                item.original.strip_prefix(root).unwrap_or(&item.original);
+                            ui.horizontal(|ui| {
+                                ui.label(format!(
+                                    "{}  {}",
+                                    item.deleted.replace('T', " "),
+                                    relative.display()
+                                ));
+                                if ui.small_button("Restore").clicked() {
+                                    restore = Some(item.clone());
+                                }
+                                if ui
+                                    .small_button("🗑")
+                                    .on_hover_text("Delete forever")
+                                    .clicked()
+                                {
+                                    purge = Some(item.clone());
+                                }
+                            });
+                        }
+                    });
+            });
+        file_browser.trash.show_trash = show_trash;
+
+        if let Some(item) = restore {
+            if let Err(e) = trash::restore(&item) {
+                error!("Failed to restore {:?}: {}", item.original, e);
+            }
+            file_browser.reset_cache();
+            file_browser.trash.refresh();
+        }
+        if let Some(item) = purge {
+            if let Err(e) = trash::purge(&item) {
+                error!("Failed to delete {:?}: {}", item.original, e);
+            }
+            file_browser.trash.refresh();
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use std::time::Duration;
 pub mod ui;
+
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)]
 pub struct UiSettings {
@@ -1,10 +1,17 @@
 use super::UiSettings;
+use crate::components::crypt_manager::CryptManager;
 use crate::components::file_browser::FileBrowser;
+use crate::components::image_viewer::backdrop::{self, Backdrop};
 
 pub struct UiSettingsWindow;
 
 impl UiSettingsWindow {
-    pub fn show(ctx: &egui::Context, settings: &mut UiSettings, file_browser: &mut FileBrowser) {
+    pub fn show(
+        ctx: &egui::Context,
+        settings: &mut UiSettings,
+        file_browser: &mut FileBrowser,
+        crypt_manager: &mut CryptManager,
+    ) {
         egui::Window::new("UI Settings")
             .open(&mut settings.show_ui_settings)
             .show(ctx, |ui| {
@@ -32,6 +39,8 @@ impl UiSettingsWindow {
                     });
                 }
 
+                Self::show_backdrop(ui, crypt_manager);
+
                 ui.add(egui::Slider::new(&mut settings.ui_scale, 1.0..=3.0).text("UI Scale"));
 
                 ui.add(egui::Slider::new(&mut settings.font_size, 8.0..=32.0).text("Font Size"));
@@ -39,4 +48,52 @@ impl UiSettingsWindow {
                 ui.checkbox(&mut settings.show_logger, "Show Logger");
             });
     }
+
+    /// The backdrop is chosen per project, as it may be one of its images.
+    fn show_backdrop(ui: &mut egui::Ui, crypt_manager: &mut CryptManager) {
+        let Some(root) = crypt_manager.current_folder.clone() else {
+            return;
+        };
+        let Some(settings) = crypt_manager.get_mut_settings() else {
+            return;
+        };
+        let backdrop = &mut settings.backdrop;
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Backdrop")
+                .selected_text(backdrop.label())
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(backdrop, Backdrop::Checkerboard, "Checkerboard");
+                    if ui
+                        .selectable_label(matches!(backdrop, Backdrop::Solid(_)), "Solid Color")
+                        .clicked()
+                        && !matches!(backdrop, Backdrop::Solid(_))
+                    {
+                        *backdrop = Backdrop::Solid([32, 32, 32]);
+                    }
+                    // Listing the project once per session is enough.
+                    let id = egui::Id::new(("backdrop_images", &root));
+                    let images = ui.data_mut(|data| {
+                        data.get_temp_mut_or_insert_with(id, || backdrop::project_images(&root))
+                            .clone()
+                    });
+                    if !images.is_empty() {
+                        ui.separator();
+                    }
+                    for image in images {
+                        let Ok(relative) = image.strip_prefix(&root) else {
+                            continue;
+                        };
+                        let is_selected =
+                            matches!(backdrop, Backdrop::Image(path) if path == relative);
+                        let label = relative.display().to_string();
+                        if ui.selectable_label(is_selected, label).clicked() {
+                            *backdrop = Backdrop::Image(relative.to_path_buf());
+                        }
+                    }
+                });
+            if let Backdrop::Solid(color) = backdrop {
+                ui.color_edit_button_srgb(color);
+            }
+        });
+    }
 }
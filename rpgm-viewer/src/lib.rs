@@ -3,4 +3,10 @@
 mod app;
 mod components;
 mod theme;
+pub mod vfs;
+#[cfg(target_arch = "wasm32")]
+mod web;
 pub use app::ImageViewerApp;
+
+/// Name of the app window, which also names its storage folder.
+pub const APP_NAME: &str = "Image Viewer";
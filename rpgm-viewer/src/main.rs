@@ -23,7 +23,7 @@ fn main() -> eframe::Result {
         ..Default::default()
     };
     eframe::run_native(
-        "Image Viewer",
+        rpgm_viewer::APP_NAME,
         native_options,
         Box::new(|cc| Ok(Box::new(rpgm_viewer::ImageViewerApp::new(cc)))),
     )
@@ -0,0 +1,290 @@
+//! Archives and packages browsed as folders: RGSSAD archives, NW.js
+//! `package.nw` (also when appended to the executable), Electron
+//! `app.asar` and executables packed with Enigma Virtual Box. Their entries
+//! get paths below the archive, e.g. `Game.rgss3a/Graphics/Pictures/title.png`.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Cursor, Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use log::warn;
+use rpgm_enc::{AsarArchive, EvbArchive, RgssArchive, RgssVersion, RgssWriter, ZipArchive};
+
+use super::{EntryKind, Metadata, Vfs};
+
+trait ReadSeek: Read + Seek + Send {}
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+type Reader = Box<dyn ReadSeek>;
+
+enum Archive {
+    Rgss(RgssArchive<Reader>),
+    Zip(ZipArchive<Reader>),
+    Asar(AsarArchive<Reader>),
+    Evb(EvbArchive<Reader>),
+}
+
+impl Archive {
+    fn open(ext: &str, mut reader: impl FnMut() -> io::Result<Reader>) -> io::Result<Self> {
+        match ext {
+            "rgssad" | "rgss2a" | "rgss3a" => Ok(Self::Rgss(RgssArchive::open(reader()?)?)),
+            "asar" => Ok(Self::Asar(AsarArchive::open(reader()?)?)),
+            // nw.exe with the game appended as a zip, or packed with Enigma
+            // Virtual Box.
+            "exe" => match ZipArchive::open(reader()?) {
+                Ok(archive) => Ok(Self::Zip(archive)),
+                Err(_) => {
+                    let archive = EvbArchive::open(reader()?)?;
+                    let compressed = archive.entries().iter().filter(|e| e.compressed).count();
+                    if compressed > 0 {
+                        warn!(
+                            "{} files of the package are compressed and cannot be opened",
+                            compressed
+                        );
+                    }
+                    Ok(Self::Evb(archive))
+                }
+            },
+            _ => Ok(Self::Zip(ZipArchive::open(reader()?)?)),
+        }
+    }
+
+    /// Paths and sizes of every file in the archive.
+    fn files(&self) -> Vec<(PathBuf, u64)> {
+        match self {
+            Self::Rgss(archive) => archive
+                .entries()
+                .iter()
+                .map(|e| (e.components().collect(), e.size as u64))
+                .collect(),
+            Self::Zip(archive) => archive
+                .entries()
+                .iter()
+                .map(|e| (e.components().collect(), e.size))
+                .collect(),
+            Self::Asar(archive) => archive
+                .entries()
+                .iter()
+                .map(|e| (e.components().collect(), e.size))
+                .collect(),
+            Self::Evb(archive) => archive
+                .entries()
+                .iter()
+                .map(|e| (e.components().collect(), e.size))
+                .collect(),
+        }
+    }
+
+    fn read(&mut self, name: &str) -> io::Result<Vec<u8>> {
+        let not_found = || io::Error::new(io::ErrorKind::NotFound, "Not found in archive");
+        match self {
+            Self::Rgss(archive) => {
+                let entry = archive.find(name).cloned().ok_or_else(not_found)?;
+                archive.read(&entry)
+            }
+            Self::Zip(archive) => {
+                let entry = archive.find(name).cloned().ok_or_else(not_found)?;
+                archive.read(&entry)
+            }
+            Self::Asar(archive) => {
+                let entry = archive.find(name).cloned().ok_or_else(not_found)?;
+                archive.read(&entry)
+            }
+            Self::Evb(archive) => {
+                let entry = archive.find(name).cloned().ok_or_else(not_found)?;
+                archive.read(&entry)
+            }
+        }
+    }
+}
+
+pub fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+/// Whether files named like `path` may be archives. Executables only are
+/// when they contain a package.
+pub fn has_archive_extension(path: &Path) -> bool {
+    matches!(
+        extension(path).as_str(),
+        "rgssad" | "rgss2a" | "rgss3a" | "nw" | "asar" | "zip" | "exe"
+    )
+}
+
+/// A read-only file system over one archive, mounted at `root`.
+pub struct ArchiveFs {
+    root: PathBuf,
+    archive: Mutex<Archive>,
+    /// Relative paths and sizes of the files.
+    files: Vec<(PathBuf, u64)>,
+}
+
+impl ArchiveFs {
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let archive = Archive::open(&extension(path), || {
+            Ok(Box::new(BufReader::new(File::open(path)?)))
+        })?;
+        Ok(Self::new(path.to_path_buf(), archive))
+    }
+
+    /// Opens an archive loaded in memory, named after `root`.
+    pub fn from_bytes(root: PathBuf, data: Vec<u8>) -> io::Result<Self> {
+        let data: Arc<[u8]> = data.into();
+        let archive = Archive::open(&extension(&root), || {
+            Ok(Box::new(Cursor::new(data.clone())))
+        })?;
+        Ok(Self::new(root, archive))
+    }
+
+    fn new(root: PathBuf, archive: Archive) -> Self {
+        Self {
+            root,
+            files: archive.files(),
+            archive: Mutex::new(archive),
+        }
+    }
+
+    fn relative<'a>(&self, path: &'a Path) -> io::Result<&'a Path> {
+        path.strip_prefix(&self.root)
+            .map_err(|_| io::Error::new(io::ErrorKind::NotFound, "Not in this archive"))
+    }
+}
+
+impl Vfs for ArchiveFs {
+    fn list(&self, path: &Path) -> io::Result<Vec<(PathBuf, EntryKind)>> {
+        let inner = self.relative(path)?;
+        let depth = inner.components().count();
+
+        let mut children = BTreeMap::new();
+        for (file, _) in &self.files {
+            let Ok(rest) = file.strip_prefix(inner) else {
+                continue;
+            };
+            let mut rest = rest.iter();
+            let Some(name) = rest.next() else {
+                continue;
+            };
+            let kind = if rest.next().is_some() {
+                EntryKind::Folder
+            } else {
+                EntryKind::File
+            };
+            children.insert(path.join(name), kind);
+        }
+        if children.is_empty() && depth > 0 {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        Ok(children.into_iter().collect())
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(Cursor::new(self.read(path)?)))
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let inner = self.relative(path)?;
+        self.archive.lock().unwrap().read(&inner.to_string_lossy())
+    }
+
+    fn create(&self, _path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Err(super::read_only_error())
+    }
+
+    fn create_new(&self, _path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Err(super::read_only_error())
+    }
+
+    fn create_dir(&self, _path: &Path) -> io::Result<()> {
+        Err(super::read_only_error())
+    }
+
+    fn remove(&self, _path: &Path) -> io::Result<()> {
+        Err(super::read_only_error())
+    }
+
+    fn rename(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+        Err(super::read_only_error())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let inner = self.relative(path)?;
+        if inner.as_os_str().is_empty() {
+            return Ok(Metadata {
+                kind: EntryKind::Archive,
+                len: 0,
+                modified: None,
+            });
+        }
+        if let Some((_, len)) = self.files.iter().find(|(file, _)| file == inner) {
+            return Ok(Metadata {
+                kind: EntryKind::File,
+                len: *len,
+                modified: None,
+            });
+        }
+        if self.files.iter().any(|(file, _)| file.starts_with(inner)) {
+            return Ok(Metadata {
+                kind: EntryKind::Folder,
+                len: 0,
+                modified: None,
+            });
+        }
+        Err(io::ErrorKind::NotFound.into())
+    }
+
+    fn watch(&self, _path: &Path) -> Option<u64> {
+        Some(0)
+    }
+
+    fn is_read_only(&self, _path: &Path) -> bool {
+        true
+    }
+}
+
+/// Packs every file under `folder` into `output`. The format follows the
+/// extension, `.rgss3a` for VX Ace and v1 otherwise. v3 archives start from
+/// `seed` when given and from a random base key otherwise. Returns the file
+/// count.
+pub fn pack(folder: &Path, output: &Path, seed: Option<u32>) -> io::Result<usize> {
+    let version = match extension(output).as_str() {
+        "rgss3a" => RgssVersion::V3,
+        _ => RgssVersion::V1,
+    };
+
+    let mut files = super::walk_files(folder);
+    files.retain(|file| file != output);
+    files.sort();
+
+    let mut entries = Vec::new();
+    for file in &files {
+        let Ok(relative) = file.strip_prefix(folder) else {
+            continue;
+        };
+        let name = relative
+            .iter()
+            .map(|part| part.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("\\");
+        let size = u32::try_from(super::metadata(file)?.len)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "File too large to pack"))?;
+        entries.push((name, size));
+    }
+
+    let mut writer = RgssWriter::new(BufWriter::new(super::create(output)?), version);
+    if let Some(seed) = seed {
+        writer = writer.with_seed(seed);
+    }
+    writer
+        .write(&entries, |name| {
+            super::open(&folder.join(name.replace('\\', "/")))
+        })?
+        .flush()?;
+    Ok(entries.len())
+}
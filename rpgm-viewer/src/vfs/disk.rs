@@ -0,0 +1,217 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::debug;
+
+use super::{
+    EntryKind, Metadata, Vfs,
+    archive::{self, ArchiveFs},
+};
+
+type CachedArchive = (Option<SystemTime>, Option<Arc<ArchiveFs>>);
+
+/// The native file system. Archives on it are browsed as folders and kept
+/// open until they change.
+#[derive(Default)]
+pub struct DiskFs {
+    archives: Mutex<HashMap<PathBuf, CachedArchive>>,
+}
+
+impl DiskFs {
+    /// The archive at `path`, if it is one.
+    fn archive(&self, path: &Path) -> Option<Arc<ArchiveFs>> {
+        if !archive::has_archive_extension(path) {
+            return None;
+        }
+        let metadata = std::fs::metadata(path).ok().filter(|m| m.is_file())?;
+        let modified = metadata.modified().ok();
+
+        let mut archives = self.archives.lock().unwrap();
+        if let Some((cached_modified, archive)) = archives.get(path)
+            && *cached_modified == modified
+        {
+            return archive.clone();
+        }
+
+        let archive = match ArchiveFs::from_file(path) {
+            Ok(archive) => Some(Arc::new(archive)),
+            Err(e) => {
+                debug!("Not browsing {:?} as an archive: {}", path, e);
+                None
+            }
+        };
+        archives.insert(path.to_path_buf(), (modified, archive.clone()));
+        archive
+    }
+
+    /// The archive `path` is inside of, with the archive itself when `inclusive`.
+    fn containing_archive<'a>(
+        &self,
+        path: &'a Path,
+        inclusive: bool,
+    ) -> Option<(&'a Path, Arc<ArchiveFs>)> {
+        path.ancestors()
+            .skip(if inclusive { 0 } else { 1 })
+            .find_map(|ancestor| Some((ancestor, self.archive(ancestor)?)))
+    }
+
+    /// Refuses writes into archives and creates the missing parent folders.
+    fn prepare_write(&self, path: &Path) -> io::Result<()> {
+        if self.containing_archive(path, false).is_some() {
+            return Err(super::read_only_error());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(())
+    }
+}
+
+fn stamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+impl Vfs for DiskFs {
+    fn list(&self, path: &Path) -> io::Result<Vec<(PathBuf, EntryKind)>> {
+        if let Some((_, archive)) = self.containing_archive(path, true) {
+            return archive.list(path);
+        }
+
+        let children = std::fs::read_dir(path)?
+            .filter_map(|e| e.ok())
+            .map(|entry| entry.path())
+            .filter_map(|path| {
+                if path.is_dir() {
+                    Some((path, EntryKind::Folder))
+                } else if self.archive(&path).is_some() {
+                    Some((path, EntryKind::Archive))
+                } else if path.is_file() {
+                    Some((path, EntryKind::File))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        Ok(children)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        match self.containing_archive(path, false) {
+            Some((_, archive)) => archive.open(path),
+            None => Ok(Box::new(File::open(path)?)),
+        }
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match self.containing_archive(path, false) {
+            Some((_, archive)) => archive.read(path),
+            None => std::fs::read(path),
+        }
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        self.prepare_write(path)?;
+        Ok(Box::new(File::create(path)?))
+    }
+
+    fn create_new(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        self.prepare_write(path)?;
+        Ok(Box::new(
+            File::options().write(true).create_new(true).open(path)?,
+        ))
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        if self.containing_archive(path, false).is_some() {
+            return Err(super::read_only_error());
+        }
+        std::fs::create_dir_all(path)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        if self.containing_archive(path, false).is_some() {
+            return Err(super::read_only_error());
+        }
+        if path.is_dir() {
+            std::fs::remove_dir_all(path)
+        } else {
+            std::fs::remove_file(path)
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        if self.containing_archive(from, false).is_some()
+            || self.containing_archive(to, false).is_some()
+        {
+            return Err(super::read_only_error());
+        }
+        // Read-only files are moved as they are.
+        if from.is_file()
+            && let Ok(file) = File::options().write(true).open(from)
+        {
+            file.sync_all()?;
+        }
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(from, to)
+    }
+
+    fn copy_attributes(&self, from: &Path, to: &Path) -> io::Result<()> {
+        if let Some(modified) = self.metadata(from)?.modified {
+            File::options()
+                .write(true)
+                .open(to)?
+                .set_modified(modified)?;
+        }
+        if self.containing_archive(from, false).is_none() {
+            std::fs::set_permissions(to, std::fs::metadata(from)?.permissions())?;
+        }
+        Ok(())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        if let Some((archive_path, archive)) = self.containing_archive(path, false) {
+            let modified = std::fs::metadata(archive_path)?.modified().ok();
+            return Ok(Metadata {
+                modified,
+                ..archive.metadata(path)?
+            });
+        }
+
+        let metadata = std::fs::metadata(path)?;
+        let kind = if metadata.is_dir() {
+            EntryKind::Folder
+        } else if self.archive(path).is_some() {
+            EntryKind::Archive
+        } else {
+            EntryKind::File
+        };
+        Ok(Metadata {
+            kind,
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    fn watch(&self, path: &Path) -> Option<u64> {
+        let path = self
+            .containing_archive(path, true)
+            .map_or(path, |(archive_path, _)| archive_path);
+        let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+        Some(stamp(modified))
+    }
+
+    /// Only what is inside an archive, the archive file itself can still be
+    /// deleted or moved.
+    fn is_read_only(&self, path: &Path) -> bool {
+        self.containing_archive(path, false).is_some()
+    }
+}
@@ -0,0 +1,304 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Cursor, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{EntryKind, Metadata, Vfs};
+
+/// Data of a file and the generation it was last written in.
+type Files = RwLock<BTreeMap<PathBuf, (Arc<[u8]>, u64)>>;
+
+/// Bumps the generation, returning the new one.
+fn bump(generation: &AtomicU64) -> u64 {
+    generation.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// The generation a file was written in as its modification time. Only the
+/// order matters, and the clock is not available on the web.
+fn modified(generation: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_micros(generation)
+}
+
+/// Files kept in memory. Folders exist as long as they contain a file.
+#[derive(Default)]
+pub struct MemoryFs {
+    files: Arc<Files>,
+    /// Bumped on every change, used as the stamp of every path.
+    generation: Arc<AtomicU64>,
+}
+
+/// Stores the written data when dropped.
+struct MemoryWriter {
+    path: PathBuf,
+    data: Vec<u8>,
+    files: Arc<Files>,
+    generation: Arc<AtomicU64>,
+}
+
+impl Write for MemoryWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for MemoryWriter {
+    fn drop(&mut self) {
+        let data = std::mem::take(&mut self.data);
+        let generation = bump(&self.generation);
+        self.files
+            .write()
+            .unwrap()
+            .insert(std::mem::take(&mut self.path), (data.into(), generation));
+    }
+}
+
+impl Vfs for MemoryFs {
+    fn list(&self, path: &Path) -> io::Result<Vec<(PathBuf, EntryKind)>> {
+        let files = self.files.read().unwrap();
+        let mut children = BTreeMap::new();
+        for file in files.keys() {
+            let Ok(rest) = file.strip_prefix(path) else {
+                continue;
+            };
+            let mut rest = rest.iter();
+            let Some(name) = rest.next() else {
+                continue;
+            };
+            let kind = if rest.next().is_some() {
+                EntryKind::Folder
+            } else {
+                EntryKind::File
+            };
+            children.insert(path.join(name), kind);
+        }
+        Ok(children.into_iter().collect())
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        let data = self
+            .files
+            .read()
+            .unwrap()
+            .get(path)
+            .map(|(data, _)| data.clone());
+        match data {
+            Some(data) => Ok(Box::new(Cursor::new(data))),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(MemoryWriter {
+            path: path.to_path_buf(),
+            data: Vec::new(),
+            files: self.files.clone(),
+            generation: self.generation.clone(),
+        }))
+    }
+
+    fn create_new(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        {
+            let mut files = self.files.write().unwrap();
+            if files.contains_key(path) {
+                return Err(io::ErrorKind::AlreadyExists.into());
+            }
+            // Claims the path until the writer is dropped.
+            let generation = bump(&self.generation);
+            files.insert(path.to_path_buf(), (Vec::new().into(), generation));
+        }
+        self.create(path)
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let generation = bump(&self.generation);
+        self.files
+            .write()
+            .unwrap()
+            .insert(path.to_path_buf(), (data.into(), generation));
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let mut files = self.files.write().unwrap();
+        let before = files.len();
+        files.retain(|file, _| !file.starts_with(path));
+        if files.len() == before {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        bump(&self.generation);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut files = self.files.write().unwrap();
+        let moved: Vec<PathBuf> = files
+            .keys()
+            .filter(|file| file.starts_with(from))
+            .cloned()
+            .collect();
+        if moved.is_empty() {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        for file in moved {
+            let data = files.remove(&file).unwrap();
+            let rest = file.strip_prefix(from).unwrap();
+            files.insert(to.join(rest), data);
+        }
+        bump(&self.generation);
+        Ok(())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let files = self.files.read().unwrap();
+        if let Some((data, generation)) = files.get(path) {
+            return Ok(Metadata {
+                kind: EntryKind::File,
+                len: data.len() as u64,
+                modified: Some(modified(*generation)),
+            });
+        }
+        let newest = files
+            .iter()
+            .filter(|(file, _)| file.starts_with(path))
+            .map(|(_, (_, generation))| *generation)
+            .max();
+        if let Some(generation) = newest {
+            return Ok(Metadata {
+                kind: EntryKind::Folder,
+                len: 0,
+                modified: Some(modified(generation)),
+            });
+        }
+        Err(io::ErrorKind::NotFound.into())
+    }
+
+    fn watch(&self, _path: &Path) -> Option<u64> {
+        Some(self.generation.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(fs: &MemoryFs, path: &str) -> Vec<(String, EntryKind)> {
+        fs.list(Path::new(path))
+            .unwrap()
+            .into_iter()
+            .map(|(path, kind)| (path.to_string_lossy().into_owned(), kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_list() {
+        let fs = MemoryFs::default();
+        fs.write(Path::new("/m/a.png"), b"a").unwrap();
+        fs.write(Path::new("/m/img/b.png"), b"b").unwrap();
+        fs.write(Path::new("/m/img/sub/c.png"), b"c").unwrap();
+
+        assert_eq!(
+            names(&fs, "/m"),
+            [
+                ("/m/a.png".to_string(), EntryKind::File),
+                ("/m/img".to_string(), EntryKind::Folder),
+            ]
+        );
+        assert_eq!(
+            fs.metadata(Path::new("/m/img")).unwrap().kind,
+            EntryKind::Folder
+        );
+        assert!(fs.metadata(Path::new("/m/none")).is_err());
+    }
+
+    #[test]
+    fn test_remove() {
+        let fs = MemoryFs::default();
+        fs.write(Path::new("/m/a.png"), b"a").unwrap();
+        fs.write(Path::new("/m/img/b.png"), b"b").unwrap();
+        fs.write(Path::new("/m/img/c.png"), b"c").unwrap();
+
+        fs.remove(Path::new("/m/img")).unwrap();
+        assert_eq!(
+            names(&fs, "/m"),
+            [("/m/a.png".to_string(), EntryKind::File)]
+        );
+        assert_eq!(
+            fs.remove(Path::new("/m/img")).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_rename() {
+        let fs = MemoryFs::default();
+        fs.write(Path::new("/m/img/b.png"), b"b").unwrap();
+        fs.write(Path::new("/m/img/sub/c.png"), b"c").unwrap();
+        fs.write(Path::new("/m/a.png"), b"a").unwrap();
+        fs.write(Path::new("/m/d.png"), b"d").unwrap();
+
+        fs.rename(Path::new("/m/img"), Path::new("/m/pictures"))
+            .unwrap();
+        assert_eq!(fs.read(Path::new("/m/pictures/sub/c.png")).unwrap(), b"c");
+        assert!(fs.metadata(Path::new("/m/img")).is_err());
+
+        // A file at the target is replaced.
+        fs.rename(Path::new("/m/a.png"), Path::new("/m/d.png"))
+            .unwrap();
+        assert_eq!(fs.read(Path::new("/m/d.png")).unwrap(), b"a");
+        assert!(
+            fs.rename(Path::new("/m/a.png"), Path::new("/m/e.png"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_writer_commits_on_drop() {
+        let fs = MemoryFs::default();
+        let path = Path::new("/m/a.png");
+        let mut writer = fs.create(path).unwrap();
+        writer.write_all(b"data").unwrap();
+        writer.flush().unwrap();
+        assert!(fs.read(path).is_err());
+
+        drop(writer);
+        assert_eq!(fs.read(path).unwrap(), b"data");
+    }
+
+    #[test]
+    fn test_create_new() {
+        let fs = MemoryFs::default();
+        let path = Path::new("/m/a.png");
+        let mut writer = fs.create_new(path).unwrap();
+        assert_eq!(
+            fs.create_new(path).err().unwrap().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        writer.write_all(b"data").unwrap();
+        drop(writer);
+        assert_eq!(fs.read(path).unwrap(), b"data");
+    }
+
+    #[test]
+    fn test_modified_changes_on_write() {
+        let fs = MemoryFs::default();
+        let path = Path::new("/m/a.png");
+        fs.write(path, b"a").unwrap();
+        let first = fs.metadata(path).unwrap().modified.unwrap();
+
+        fs.write(Path::new("/m/b.png"), b"b").unwrap();
+        assert_eq!(fs.metadata(path).unwrap().modified, Some(first));
+
+        fs.write(path, b"new").unwrap();
+        assert!(fs.metadata(path).unwrap().modified.unwrap() > first);
+    }
+}
@@ -0,0 +1,265 @@
+//! File access for the whole app. Paths are resolved against the mounted
+//! file systems, longest root first, and fall back to the disk.
+
+pub mod archive;
+pub mod disk;
+pub mod memory;
+pub mod trash;
+
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock, RwLock},
+    time::SystemTime,
+};
+
+use disk::DiskFs;
+use memory::MemoryFs;
+
+/// Root of the in-memory file system, for files that have no path on disk.
+pub const MEMORY_ROOT: &str = "/memory";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Folder,
+    /// A file browsed as a folder.
+    Archive,
+}
+
+impl EntryKind {
+    pub fn is_folder(self) -> bool {
+        self != Self::File
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    pub kind: EntryKind,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+}
+
+pub trait Vfs: Send + Sync {
+    /// Direct children of a folder.
+    fn list(&self, path: &Path) -> io::Result<Vec<(PathBuf, EntryKind)>>;
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.open(path)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// Creates or truncates a file, along with missing parent folders.
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>>;
+
+    /// Like `create`, but fails with `AlreadyExists` instead of replacing a
+    /// file, checked and claimed in one step.
+    fn create_new(&self, path: &Path) -> io::Result<Box<dyn Write + Send>>;
+
+    /// Creates a folder along with missing parent folders. File systems whose
+    /// folders only exist through the files in them have nothing to do.
+    fn create_dir(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let mut file = self.create(path)?;
+        file.write_all(data)?;
+        file.flush()
+    }
+
+    /// Removes a file, or a folder with everything in it.
+    fn remove(&self, path: &Path) -> io::Result<()>;
+
+    /// Moves a file or folder within this file system, replacing a file at
+    /// `to`. Files are flushed to storage first.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Gives `to` the modification time and permissions of `from`, where
+    /// the file system keeps them.
+    fn copy_attributes(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// A stamp that changes when `path` or its direct children change.
+    fn watch(&self, path: &Path) -> Option<u64>;
+
+    fn is_read_only(&self, _path: &Path) -> bool {
+        false
+    }
+}
+
+fn read_only_error() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "Archives are read-only")
+}
+
+type Mounts = RwLock<Vec<(PathBuf, Arc<dyn Vfs>)>>;
+
+fn mounts() -> &'static Mounts {
+    static MOUNTS: OnceLock<Mounts> = OnceLock::new();
+    MOUNTS.get_or_init(|| {
+        let memory: Arc<dyn Vfs> = Arc::new(MemoryFs::default());
+        RwLock::new(vec![(PathBuf::from(MEMORY_ROOT), memory)])
+    })
+}
+
+fn disk() -> Arc<dyn Vfs> {
+    static DISK: OnceLock<Arc<DiskFs>> = OnceLock::new();
+    DISK.get_or_init(|| Arc::new(DiskFs::default())).clone()
+}
+
+/// Serves everything under `root` from `fs`, replacing an earlier mount there.
+pub fn mount(root: impl Into<PathBuf>, fs: Arc<dyn Vfs>) {
+    let root = root.into();
+    let mut mounts = mounts().write().unwrap();
+    mounts.retain(|(existing, _)| *existing != root);
+    mounts.push((root, fs));
+    mounts.sort_by_key(|(root, _)| std::cmp::Reverse(root.components().count()));
+}
+
+fn backend(path: &Path) -> Arc<dyn Vfs> {
+    mounts()
+        .read()
+        .unwrap()
+        .iter()
+        .find(|(root, _)| path.starts_with(root))
+        .map(|(_, fs)| fs.clone())
+        .unwrap_or_else(disk)
+}
+
+pub fn list(path: &Path) -> io::Result<Vec<(PathBuf, EntryKind)>> {
+    backend(path).list(path)
+}
+
+pub fn open(path: &Path) -> io::Result<Box<dyn Read + Send>> {
+    backend(path).open(path)
+}
+
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    backend(path).read(path)
+}
+
+pub fn create(path: &Path) -> io::Result<Box<dyn Write + Send>> {
+    backend(path).create(path)
+}
+
+pub fn create_new(path: &Path) -> io::Result<Box<dyn Write + Send>> {
+    backend(path).create_new(path)
+}
+
+pub fn create_dir(path: &Path) -> io::Result<()> {
+    backend(path).create_dir(path)
+}
+
+pub fn write(path: &Path, data: &[u8]) -> io::Result<()> {
+    backend(path).write(path, data)
+}
+
+pub fn remove(path: &Path) -> io::Result<()> {
+    backend(path).remove(path)
+}
+
+pub fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    let fs = backend(from);
+    if !Arc::ptr_eq(&fs, &backend(to)) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Cannot move between file systems",
+        ));
+    }
+    fs.rename(from, to)
+}
+
+pub fn copy_attributes(from: &Path, to: &Path) -> io::Result<()> {
+    backend(to).copy_attributes(from, to)
+}
+
+/// A hidden sibling of `path` to write to before replacing it.
+pub fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.partial", name))
+}
+
+pub fn metadata(path: &Path) -> io::Result<Metadata> {
+    backend(path).metadata(path)
+}
+
+pub fn watch(path: &Path) -> Option<u64> {
+    backend(path).watch(path)
+}
+
+pub fn is_read_only(path: &Path) -> bool {
+    backend(path).is_read_only(path)
+}
+
+/// True for folders and archives.
+pub fn is_dir(path: &Path) -> bool {
+    metadata(path).is_ok_and(|m| m.kind.is_folder())
+}
+
+/// Every file under `path`. Archives inside it are listed as files, not
+/// entered, and trash folders are left out.
+pub fn walk_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut folders = vec![path.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        let Ok(children) = list(&folder) else {
+            continue;
+        };
+        for (child, kind) in children {
+            match kind {
+                EntryKind::Folder if child.ends_with(trash::LOCAL_TRASH) => {}
+                EntryKind::Folder => folders.push(child),
+                EntryKind::File | EntryKind::Archive => files.push(child),
+            }
+        }
+    }
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_longest_mount_wins() {
+        let outer = Arc::new(MemoryFs::default());
+        let inner = Arc::new(MemoryFs::default());
+        mount("/vfs-mount/outer/inner", inner.clone());
+        mount("/vfs-mount/outer", outer.clone());
+
+        write(Path::new("/vfs-mount/outer/a.png"), b"a").unwrap();
+        write(Path::new("/vfs-mount/outer/inner/b.png"), b"b").unwrap();
+        assert!(outer.read(Path::new("/vfs-mount/outer/a.png")).is_ok());
+        assert!(
+            outer
+                .read(Path::new("/vfs-mount/outer/inner/b.png"))
+                .is_err()
+        );
+        assert!(
+            inner
+                .read(Path::new("/vfs-mount/outer/inner/b.png"))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_rename_between_mounts() {
+        mount("/vfs-rename/a", Arc::new(MemoryFs::default()));
+        mount("/vfs-rename/b", Arc::new(MemoryFs::default()));
+        let from = Path::new("/vfs-rename/a/file.png");
+        write(from, b"data").unwrap();
+
+        let error = rename(from, Path::new("/vfs-rename/b/file.png")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        assert_eq!(read(from).unwrap(), b"data");
+
+        rename(from, Path::new("/vfs-rename/a/moved.png")).unwrap();
+        assert_eq!(read(Path::new("/vfs-rename/a/moved.png")).unwrap(), b"data");
+    }
+}
@@ -0,0 +1,353 @@
+//! Deleted files are moved to a trash instead of being removed. On Linux it
+//! is the freedesktop.org home trash, elsewhere a `.trash` folder in the
+//! project. Both use the same layout: the items in `files/` and a
+//! `.trashinfo` file per item in `info/` recording where it came from.
+
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use log::info;
+
+/// Name of the trash folder kept inside projects.
+pub const LOCAL_TRASH: &str = ".trash";
+
+const INFO_EXTENSION: &str = "trashinfo";
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct TrashedItem {
+    /// Where the item was before it was deleted.
+    pub original: PathBuf,
+    /// Local time of the deletion, as `YYYY-MM-DDThh:mm:ss`.
+    pub deleted: String,
+    files_path: PathBuf,
+    info_path: PathBuf,
+}
+
+/// The freedesktop.org home trash, `$XDG_DATA_HOME/Trash`.
+#[cfg(all(target_os = "linux", not(target_arch = "wasm32")))]
+fn home_trash() -> Option<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".local/share")))?;
+    Some(data_home.join("Trash"))
+}
+
+#[cfg(not(all(target_os = "linux", not(target_arch = "wasm32"))))]
+fn home_trash() -> Option<PathBuf> {
+    None
+}
+
+/// The trash that deletions in the project at `root` go to.
+fn trash_dir(root: &Path) -> PathBuf {
+    // Files in memory have no place in the home trash.
+    if root.starts_with(super::MEMORY_ROOT) {
+        return root.join(LOCAL_TRASH);
+    }
+    home_trash().unwrap_or_else(|| root.join(LOCAL_TRASH))
+}
+
+/// Moves `path`, a file or folder in the project at `root`, to the trash.
+pub fn move_to_trash(path: &Path, root: &Path) -> io::Result<TrashedItem> {
+    move_into(path, &trash_dir(root))
+}
+
+/// Moves a restored item back to the trash it was restored from.
+pub fn move_back(item: &TrashedItem) -> io::Result<TrashedItem> {
+    let trash = item
+        .files_path
+        .parent()
+        .and_then(Path::parent)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not in a trash"))?;
+    move_into(&item.original, trash)
+}
+
+fn move_into(path: &Path, trash: &Path) -> io::Result<TrashedItem> {
+    if super::is_read_only(path) {
+        return Err(super::read_only_error());
+    }
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Nothing to delete"))?;
+
+    let deleted = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S").to_string();
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_path(path),
+        deleted
+    );
+
+    // Names are claimed by creating their info file, as the spec asks, so
+    // two deletions never pick the same one.
+    let mut n = 1;
+    let (name, info_path, mut info_file) = loop {
+        let mut candidate = name.to_os_string();
+        if n > 1 {
+            candidate.push(format!(".{}", n));
+        }
+        n += 1;
+        if super::metadata(&trash.join("files").join(&candidate)).is_ok() {
+            continue;
+        }
+        let mut info_name = candidate.clone();
+        info_name.push(format!(".{}", INFO_EXTENSION));
+        let info_path = trash.join("info").join(info_name);
+        match super::create_new(&info_path) {
+            Ok(file) => break (candidate, info_path, file),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    };
+    let written = info_file
+        .write_all(info.as_bytes())
+        .and_then(|_| info_file.flush());
+    drop(info_file);
+    if let Err(e) = written {
+        let _ = super::remove(&info_path);
+        return Err(e);
+    }
+
+    let files_path = trash.join("files").join(&name);
+    if let Err(e) = move_item(path, &files_path) {
+        let _ = super::remove(&info_path);
+        return Err(e);
+    }
+    info!("Moved {:?} to {:?}", path, files_path);
+    Ok(TrashedItem {
+        original: path.to_path_buf(),
+        deleted,
+        files_path,
+        info_path,
+    })
+}
+
+/// Renames `from` to `to`, copying when they are on different devices.
+fn move_item(from: &Path, to: &Path) -> io::Result<()> {
+    match super::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_item(from, to)?;
+            super::remove(from)
+        }
+        result => result,
+    }
+}
+
+/// Copies a file, or a folder with everything in it including empty
+/// folders. Archives are copied as files.
+fn copy_item(from: &Path, to: &Path) -> io::Result<()> {
+    if super::metadata(from)?.kind != super::EntryKind::Folder {
+        let mut output = super::create(to)?;
+        io::copy(&mut super::open(from)?, &mut output)?;
+        return output.flush();
+    }
+    super::create_dir(to)?;
+    for (child, _) in super::list(from)? {
+        if let Some(name) = child.file_name() {
+            copy_item(&child, &to.join(name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Items deleted from the project at `root`, most recent first.
+pub fn list(root: &Path) -> Vec<TrashedItem> {
+    let trash = trash_dir(root);
+    let Ok(infos) = super::list(&trash.join("info")) else {
+        return Vec::new();
+    };
+
+    let mut items: Vec<TrashedItem> = infos
+        .into_iter()
+        .filter(|(path, _)| path.extension().is_some_and(|ext| ext == INFO_EXTENSION))
+        .filter_map(|(info_path, _)| {
+            let info = String::from_utf8(super::read(&info_path).ok()?).ok()?;
+            let mut original = None;
+            let mut deleted = String::new();
+            for line in info.lines() {
+                if let Some(path) = line.strip_prefix("Path=") {
+                    original = Some(decode_path(path));
+                } else if let Some(date) = line.strip_prefix("DeletionDate=") {
+                    deleted = date.to_string();
+                }
+            }
+            let original = original.filter(|path| path.starts_with(root))?;
+            let files_path = trash.join("files").join(info_path.file_stem()?);
+            Some(TrashedItem {
+                original,
+                deleted,
+                files_path,
+                info_path,
+            })
+        })
+        .collect();
+    items.sort_by(|a, b| b.deleted.cmp(&a.deleted));
+    items
+}
+
+impl TrashedItem {
+    /// False once the item was restored or purged.
+    pub fn is_in_trash(&self) -> bool {
+        super::metadata(&self.files_path).is_ok()
+    }
+}
+
+/// Puts the item back where it was deleted from.
+pub fn restore(item: &TrashedItem) -> io::Result<()> {
+    if !item.is_in_trash() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "No longer in the trash",
+        ));
+    }
+    if super::metadata(&item.original).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "Something else is there now",
+        ));
+    }
+    move_item(&item.files_path, &item.original)?;
+    super::remove(&item.info_path)?;
+    info!("Restored {:?}", item.original);
+    Ok(())
+}
+
+/// Deletes the item for good.
+pub fn purge(item: &TrashedItem) -> io::Result<()> {
+    super::remove(&item.files_path)?;
+    super::remove(&item.info_path)
+}
+
+/// Raw bytes of a path. Only Unix paths may be any bytes, elsewhere they
+/// are taken as UTF-8.
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Percent-encodes a path for a `.trashinfo` file.
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for byte in path_bytes(path) {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn decode_path(encoded: &str) -> PathBuf {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    path_from_bytes(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs;
+
+    #[test]
+    fn test_encode_decode_path() {
+        let path = Path::new("/games/My Game/img/100%_ß.png");
+        let encoded = encode_path(path);
+        assert_eq!(encoded, "/games/My%20Game/img/100%25_%C3%9F.png");
+        assert_eq!(decode_path(&encoded), path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_encode_decode_non_utf8_path() {
+        let path = path_from_bytes(b"/games/\xff\xfe.png".to_vec());
+        let encoded = encode_path(&path);
+        assert_eq!(encoded, "/games/%FF%FE.png");
+        assert_eq!(decode_path(&encoded), path);
+    }
+
+    #[test]
+    fn test_list_and_restore() {
+        let root = Path::new(vfs::MEMORY_ROOT).join("trash-restore");
+        let first = root.join("img/a.png");
+        let second = root.join("audio/a.png");
+        vfs::write(&first, b"first").unwrap();
+        vfs::write(&second, b"second").unwrap();
+
+        let first_item = move_to_trash(&first, &root).unwrap();
+        let second_item = move_to_trash(&second, &root).unwrap();
+        assert_ne!(first_item.files_path, second_item.files_path);
+        assert!(vfs::metadata(&first).is_err());
+
+        let items = list(&root);
+        assert_eq!(items.len(), 2);
+        let mut originals: Vec<&Path> = items.iter().map(|item| item.original.as_path()).collect();
+        originals.sort();
+        assert_eq!(originals, [second.as_path(), first.as_path()]);
+
+        vfs::write(&first, b"new").unwrap();
+        let error = restore(&first_item).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        vfs::remove(&first).unwrap();
+
+        restore(&first_item).unwrap();
+        assert_eq!(vfs::read(&first).unwrap(), b"first");
+        assert_eq!(
+            restore(&first_item).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        purge(&second_item).unwrap();
+        assert!(list(&root).is_empty());
+    }
+
+    #[test]
+    fn test_copy_keeps_empty_folders() {
+        let dir = std::env::temp_dir().join(format!("trash-copy-{}", std::process::id()));
+        let from = dir.join("from");
+        let to = dir.join("to");
+        vfs::write(&from.join("img/a.png"), b"a").unwrap();
+        vfs::create_dir(&from.join("empty/nested")).unwrap();
+
+        copy_item(&from, &to).unwrap();
+        assert_eq!(vfs::read(&to.join("img/a.png")).unwrap(), b"a");
+        assert!(vfs::is_dir(&to.join("empty/nested")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
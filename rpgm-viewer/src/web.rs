@@ -0,0 +1,56 @@
+//! Browser-only file access: uploads through the file picker and downloads.
+
+use eframe::wasm_bindgen::{JsCast as _, JsValue, closure::Closure};
+
+use crate::components::dropped_file::Uploads;
+
+/// Opens the file picker and adds the chosen files to `uploads` once read.
+pub fn pick_files(ctx: &egui::Context, uploads: Uploads) {
+    let ctx = ctx.clone();
+    wasm_bindgen_futures::spawn_local(async move {
+        let Some(files) = rfd::AsyncFileDialog::new().pick_files().await else {
+            return;
+        };
+        for file in files {
+            let data = file.read().await;
+            uploads.lock().unwrap().push((file.file_name(), data));
+        }
+        ctx.request_repaint();
+    });
+}
+
+/// How long a download link stays valid after it was clicked.
+const REVOKE_DELAY_MS: i32 = 60_000;
+
+/// Lets the browser save `data` as `name`.
+pub fn download(name: &str, data: &[u8]) -> Result<(), String> {
+    let js_error = |e: JsValue| format!("{:?}", e);
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(data));
+    let blob = web_sys::Blob::new_with_u8_array_sequence(&parts).map_err(js_error)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js_error)?;
+
+    let window = web_sys::window().ok_or("No window")?;
+    let document = window.document().ok_or("No document")?;
+    let anchor = document
+        .create_element("a")
+        .map_err(js_error)?
+        .dyn_into::<web_sys::HtmlAnchorElement>()
+        .map_err(|_| "Failed to create a link")?;
+    anchor.set_href(&url);
+    anchor.set_download(name);
+    anchor.click();
+
+    // The download may not have started yet, revoking the URL now would
+    // cancel it.
+    let revoke = Closure::once_into_js(move || {
+        let _ = web_sys::Url::revoke_object_url(&url);
+    });
+    window
+        .set_timeout_with_callback_and_timeout_and_arguments_0(
+            revoke.unchecked_ref(),
+            REVOKE_DELAY_MS,
+        )
+        .map_err(js_error)?;
+    Ok(())
+}